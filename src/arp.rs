@@ -0,0 +1,191 @@
+use ipnet::Ipv4Net;
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+/// A local IPv4 interface ARP can sweep.
+#[derive(Debug, Clone)]
+pub struct LocalNet {
+    pub name: String,
+    pub index: i32,
+    pub mac: [u8; 6],
+    pub ip: Ipv4Addr,
+    pub net: Ipv4Net,
+}
+
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+/// Index into `nets` of the directly attached subnet `ip` is on, if any, so
+/// it can be resolved over ARP. The interface's own address isn't swept.
+pub fn local_net(nets: &[LocalNet], ip: Ipv4Addr) -> Option<usize> {
+    nets.iter().position(|n| n.net.contains(&ip) && n.ip != ip)
+}
+
+fn build_request(src_mac: [u8; 6], src_ip: Ipv4Addr, target: Ipv4Addr) -> [u8; 60] {
+    let mut f = [0u8; 60]; // minimum Ethernet frame, zero padded
+    f[0..6].copy_from_slice(&[0xff; 6]);
+    f[6..12].copy_from_slice(&src_mac);
+    f[12..14].copy_from_slice(&0x0806u16.to_be_bytes());
+    f[14..22].copy_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 1]); // Ethernet/IPv4, request
+    f[22..28].copy_from_slice(&src_mac);
+    f[28..32].copy_from_slice(&src_ip.octets());
+    f[38..42].copy_from_slice(&target.octets());
+    f
+}
+
+/// Sender (IP, MAC) of an ARP reply frame.
+fn parse_reply(frame: &[u8]) -> Option<(Ipv4Addr, [u8; 6])> {
+    if frame.len() < 42 || frame[12..14] != [0x08, 0x06] || frame[20..22] != [0, 2] { return None; }
+    let mac: [u8; 6] = frame[22..28].try_into().ok()?;
+    Some((Ipv4Addr::new(frame[28], frame[29], frame[30], frame[31]), mac))
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use socket2::Socket;
+    use std::ffi::CStr;
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+    use std::time::Instant;
+
+    const ETH_P_ARP: u16 = 0x0806;
+
+    /// IPv4 interfaces (non-loopback, up) with their MAC, via getifaddrs.
+    pub fn local_networks() -> io::Result<Vec<LocalNet>> {
+        let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut ifap) } != 0 { return Err(io::Error::last_os_error()); }
+        let mut v4: Vec<(String, Ipv4Addr, Ipv4Addr)> = Vec::new();
+        let mut links: HashMap<String, (i32, [u8; 6])> = HashMap::new();
+        let mut cur = ifap;
+        while !cur.is_null() {
+            let ifa = unsafe { &*cur };
+            cur = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || ifa.ifa_flags & libc::IFF_UP as u32 == 0 || ifa.ifa_flags & libc::IFF_LOOPBACK as u32 != 0 {
+                continue;
+            }
+            let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().into_owned();
+            match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+                libc::AF_INET if !ifa.ifa_netmask.is_null() => {
+                    let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                    let mask = unsafe { &*(ifa.ifa_netmask as *const libc::sockaddr_in) };
+                    v4.push((name, u32::from_be(addr.sin_addr.s_addr).into(), u32::from_be(mask.sin_addr.s_addr).into()));
+                }
+                libc::AF_PACKET => {
+                    let ll = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_ll) };
+                    if ll.sll_halen == 6 {
+                        let mut mac = [0u8; 6];
+                        mac.copy_from_slice(&ll.sll_addr[..6]);
+                        links.insert(name, (ll.sll_ifindex, mac));
+                    }
+                }
+                _ => {}
+            }
+        }
+        unsafe { libc::freeifaddrs(ifap) };
+        Ok(v4.into_iter().filter_map(|(name, ip, mask)| {
+            let (index, mac) = *links.get(&name)?;
+            let net = Ipv4Net::with_netmask(ip, mask).ok()?.trunc();
+            Some(LocalNet { name, index, mac, ip, net })
+        }).collect())
+    }
+
+    fn link_addr(ifindex: i32, dst: [u8; 6]) -> libc::sockaddr_ll {
+        let mut sll: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        sll.sll_family = libc::AF_PACKET as u16;
+        sll.sll_protocol = ETH_P_ARP.to_be();
+        sll.sll_ifindex = ifindex;
+        sll.sll_halen = 6;
+        sll.sll_addr[..6].copy_from_slice(&dst);
+        sll
+    }
+
+    pub fn sweep(net: &LocalNet, targets: &[Ipv4Addr], pace: Duration, wait: Duration) -> io::Result<HashMap<Ipv4Addr, [u8; 6]>> {
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, ETH_P_ARP.to_be() as i32) };
+        if fd < 0 { return Err(io::Error::last_os_error()); }
+        // Owns the fd from here on, closing it on drop
+        let sock = unsafe { Socket::from_raw_fd(fd) };
+        let bind_addr = link_addr(net.index, [0; 6]);
+        let len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        if unsafe { libc::bind(fd, &bind_addr as *const _ as *const libc::sockaddr, len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let wanted: std::collections::HashSet<Ipv4Addr> = targets.iter().copied().collect();
+        let mut found = HashMap::new();
+        let mut buf = [0u8; 1514];
+        let mut drain = |sock: &Socket, until: Instant, found: &mut HashMap<Ipv4Addr, [u8; 6]>| -> io::Result<()> {
+            while let Some(left) = until.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+                sock.set_read_timeout(Some(left))?;
+                match (&*sock).read(&mut buf) {
+                    Ok(n) => if let Some((ip, mac)) = parse_reply(&buf[..n]) && wanted.contains(&ip) { found.insert(ip, mac); },
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        };
+
+        let dst = link_addr(net.index, [0xff; 6]);
+        for &ip in targets {
+            let frame = build_request(net.mac, net.ip, ip);
+            let sent = unsafe {
+                libc::sendto(fd, frame.as_ptr() as *const libc::c_void, frame.len(), 0, &dst as *const _ as *const libc::sockaddr, len)
+            };
+            if sent < 0 { return Err(io::Error::last_os_error()); }
+            // Pick up replies between requests instead of sleeping blindly
+            drain(&sock, Instant::now() + pace, &mut found)?;
+        }
+        drain(&sock, Instant::now() + wait, &mut found)?;
+        Ok(found)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+    pub fn local_networks() -> io::Result<Vec<LocalNet>> { Ok(Vec::new()) }
+    pub fn sweep(_: &LocalNet, _: &[Ipv4Addr], _: Duration, _: Duration) -> io::Result<HashMap<Ipv4Addr, [u8; 6]>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "ARP scanning is only supported on Linux"))
+    }
+}
+
+pub use imp::local_networks;
+
+/// ARP-sweeps `targets` on interface `net`, pacing requests at `rate` per
+/// second and waiting `wait` for late replies. Returns responders and their MACs.
+pub async fn sweep(net: LocalNet, targets: Vec<Ipv4Addr>, rate: u32, wait: Duration) -> io::Result<HashMap<Ipv4Addr, [u8; 6]>> {
+    let pace = Duration::from_secs_f64(1.0 / rate.max(1) as f64);
+    tokio::task::spawn_blocking(move || imp::sweep(&net, &targets, pace, wait))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_request_reply_roundtrip() {
+        let mac = [0x02, 0, 0, 0, 0, 1];
+        let req = build_request(mac, Ipv4Addr::new(192, 168, 1, 10), Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(&req[12..14], &[0x08, 0x06]);
+        assert_eq!(parse_reply(&req), None); // a request is not a reply
+        let mut reply = req;
+        reply[20..22].copy_from_slice(&[0, 2]);
+        reply[22..28].copy_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+        reply[28..32].copy_from_slice(&[192, 168, 1, 20]);
+        assert_eq!(parse_reply(&reply), Some((Ipv4Addr::new(192, 168, 1, 20), [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])));
+        assert_eq!(format_mac(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]), "aa:bb:cc:dd:ee:ff");
+    }
+    #[test]
+    fn test_local_net() {
+        let net = LocalNet { name: "eth0".into(), index: 2, mac: [0; 6], ip: Ipv4Addr::new(10, 0, 0, 5), net: "10.0.0.0/24".parse().unwrap() };
+        let nets = [net];
+        assert_eq!(local_net(&nets, Ipv4Addr::new(10, 0, 0, 7)), Some(0));
+        assert_eq!(local_net(&nets, Ipv4Addr::new(10, 0, 0, 5)), None);
+        assert_eq!(local_net(&nets, Ipv4Addr::new(8, 8, 8, 8)), None);
+    }
+}
@@ -0,0 +1,67 @@
+/// BER tag-length-value with a definite length (SNMP, SPNEGO).
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Non-negative INTEGER.
+pub fn integer(v: u32) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+    let mut content = bytes[bytes.iter().position(|&b| b != 0).unwrap_or(3)..].to_vec();
+    // Keep a leading zero so the value stays positive
+    if content[0] & 0x80 != 0 { content.insert(0, 0); }
+    tlv(0x02, &content)
+}
+
+/// OBJECT IDENTIFIER from its arcs.
+pub fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut body = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        body.extend(chunk.into_iter().rev());
+    }
+    tlv(0x06, &body)
+}
+
+/// Splits one TLV off the front: (tag, content, rest).
+pub fn read_tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = buf.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 { return None; }
+        let len = rest.get(..n)?.iter().fold(0usize, |acc, &b| acc << 8 | b as usize);
+        (len, &rest[n..])
+    };
+    Some((tag, rest.get(..len)?, &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_long_form_roundtrip() {
+        let content = vec![7u8; 300];
+        let encoded = tlv(0x04, &content);
+        assert_eq!(&encoded[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        let (tag, value, rest) = read_tlv(&encoded).unwrap();
+        assert_eq!((tag, value.len(), rest.len()), (0x04, 300, 0));
+        assert_eq!(oid(&[1, 3, 6, 1, 4, 1, 311, 2, 2, 10]), vec![0x06, 0x0a, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a]);
+    }
+}
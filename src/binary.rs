@@ -0,0 +1,330 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Number, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Nesting deeper than any result gets is treated as a corrupt file.
+const MAX_DEPTH: usize = 64;
+
+/// Compact result logs: one MessagePack or CBOR (RFC 8949) map per result,
+/// back to back like JSON lines, so they can be appended to and streamed.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Msgpack,
+    Cbor,
+}
+
+impl Format {
+    /// From the first byte of a log: both formats start every record with a
+    /// map header, and their map headers don't overlap.
+    pub fn detect(first: u8) -> Option<Self> {
+        match first {
+            0x80..=0x8f | 0xde | 0xdf => Some(Self::Msgpack),
+            0xa0..=0xbb | 0xbf => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, value: &Value) -> Vec<u8> {
+        let mut out = Vec::with_capacity(256);
+        match self {
+            Self::Msgpack => msgpack(&mut out, value),
+            Self::Cbor => cbor(&mut out, value),
+        }
+        out
+    }
+}
+
+fn msgpack(out: &mut Vec<u8>, value: &Value) {
+    let len = |out: &mut Vec<u8>, n: usize, fix: u8, fix_max: usize, wide: [u8; 3]| {
+        match n {
+            n if n <= fix_max => out.push(fix | n as u8),
+            n if n <= u8::MAX as usize && wide[0] != 0 => out.extend([wide[0], n as u8]),
+            n if n <= u16::MAX as usize => { out.push(wide[1]); out.extend((n as u16).to_be_bytes()); }
+            n => { out.push(wide[2]); out.extend((n as u32).to_be_bytes()); }
+        }
+    };
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) if u < 0x80 => out.push(u as u8),
+            (Some(u), _) if u <= u8::MAX as u64 => out.extend([0xcc, u as u8]),
+            (Some(u), _) if u <= u16::MAX as u64 => { out.push(0xcd); out.extend((u as u16).to_be_bytes()); }
+            (Some(u), _) if u <= u32::MAX as u64 => { out.push(0xce); out.extend((u as u32).to_be_bytes()); }
+            (Some(u), _) => { out.push(0xcf); out.extend(u.to_be_bytes()); }
+            (None, Some(i)) if i >= -32 => out.push(i as u8),
+            (None, Some(i)) if i >= i8::MIN as i64 => out.extend([0xd0, i as u8]),
+            (None, Some(i)) if i >= i16::MIN as i64 => { out.push(0xd1); out.extend((i as i16).to_be_bytes()); }
+            (None, Some(i)) if i >= i32::MIN as i64 => { out.push(0xd2); out.extend((i as i32).to_be_bytes()); }
+            (None, Some(i)) => { out.push(0xd3); out.extend(i.to_be_bytes()); }
+            (None, None) => { out.push(0xcb); out.extend(n.as_f64().unwrap_or_default().to_be_bytes()); }
+        },
+        Value::String(s) => {
+            len(out, s.len(), 0xa0, 31, [0xd9, 0xda, 0xdb]);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            len(out, items.len(), 0x90, 15, [0, 0xdc, 0xdd]);
+            for item in items { msgpack(out, item); }
+        }
+        Value::Object(map) => {
+            len(out, map.len(), 0x80, 15, [0, 0xde, 0xdf]);
+            for (k, v) in map {
+                msgpack(out, &Value::String(k.clone()));
+                msgpack(out, v);
+            }
+        }
+    }
+}
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    match n {
+        n if n < 24 => out.push(major | n as u8),
+        n if n <= u8::MAX as u64 => out.extend([major | 24, n as u8]),
+        n if n <= u16::MAX as u64 => { out.push(major | 25); out.extend((n as u16).to_be_bytes()); }
+        n if n <= u32::MAX as u64 => { out.push(major | 26); out.extend((n as u32).to_be_bytes()); }
+        n => { out.push(major | 27); out.extend(n.to_be_bytes()); }
+    }
+}
+
+fn cbor(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => cbor_head(out, 0, u),
+            // -1 - n, i.e. !n in two's complement
+            (None, Some(i)) => cbor_head(out, 1, !i as u64),
+            (None, None) => { out.push(0xfb); out.extend(n.as_f64().unwrap_or_default().to_be_bytes()); }
+        },
+        Value::String(s) => {
+            cbor_head(out, 3, s.len() as u64);
+            out.extend(s.as_bytes());
+        }
+        Value::Array(items) => {
+            cbor_head(out, 4, items.len() as u64);
+            for item in items { cbor(out, item); }
+        }
+        Value::Object(map) => {
+            cbor_head(out, 5, map.len() as u64);
+            for (k, v) in map {
+                cbor_head(out, 3, k.len() as u64);
+                out.extend(k.as_bytes());
+                cbor(out, v);
+            }
+        }
+    }
+}
+
+/// Reads the records of a binary log one at a time, so a conversion never
+/// holds more than one result in memory.
+pub struct Records<R> {
+    input: BufReader<R>,
+    format: Format,
+}
+
+impl<R: Read> Records<R> {
+    pub fn new(input: R, format: Format) -> Self {
+        Self { input: BufReader::new(input), format }
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let mut b = [0u8; 1];
+        self.input.read_exact(&mut b)?;
+        Ok(b[0])
+    }
+
+    fn uint(&mut self, bytes: usize) -> io::Result<u64> {
+        let mut buf = [0u8; 8];
+        self.input.read_exact(&mut buf[8 - bytes..])?;
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    fn text(&mut self, len: u64) -> io::Result<String> {
+        let mut buf = Vec::new();
+        // A corrupt length can't make us allocate more than the file holds
+        (&mut self.input).take(len).read_to_end(&mut buf)?;
+        if buf.len() as u64 != len { return Err(io::ErrorKind::UnexpectedEof.into()); }
+        String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn items(&mut self, len: u64, depth: usize) -> io::Result<Value> {
+        let mut items = Vec::new();
+        for _ in 0..len { items.push(self.value(depth + 1)?); }
+        Ok(Value::Array(items))
+    }
+
+    fn entries(&mut self, len: u64, depth: usize) -> io::Result<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let Value::String(key) = self.value(depth + 1)? else { return Err(invalid("map key is not a string")) };
+            map.insert(key, self.value(depth + 1)?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Value> {
+        if depth > MAX_DEPTH { return Err(invalid("nested too deeply")); }
+        let b = self.byte()?;
+        match self.format {
+            Format::Msgpack => self.msgpack(b, depth),
+            Format::Cbor => self.cbor(b, depth),
+        }
+    }
+
+    fn msgpack(&mut self, b: u8, depth: usize) -> io::Result<Value> {
+        Ok(match b {
+            0x00..=0x7f => Value::from(b),
+            0x80..=0x8f => self.entries((b & 0x0f) as u64, depth)?,
+            0x90..=0x9f => self.items((b & 0x0f) as u64, depth)?,
+            0xa0..=0xbf => Value::String(self.text((b & 0x1f) as u64)?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xca => float(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => float(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => Value::from(self.uint(1 << (b - 0xcc))?),
+            0xd0..=0xd3 => {
+                let bytes = 1 << (b - 0xd0);
+                let shift = 64 - 8 * bytes as u32;
+                Value::from(((self.uint(bytes)? << shift) as i64) >> shift)
+            }
+            0xd9..=0xdb => { let len = self.uint(1 << (b - 0xd9))?; Value::String(self.text(len)?) }
+            0xdc | 0xdd => { let len = self.uint(if b == 0xdc { 2 } else { 4 })?; self.items(len, depth)? }
+            0xde | 0xdf => { let len = self.uint(if b == 0xde { 2 } else { 4 })?; self.entries(len, depth)? }
+            0xe0..=0xff => Value::from(b as i8),
+            _ => return Err(invalid("unsupported MessagePack type")),
+        })
+    }
+
+    fn cbor(&mut self, b: u8, depth: usize) -> io::Result<Value> {
+        let (major, info) = (b >> 5, b & 0x1f);
+        if major == 7 {
+            return Ok(match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                25 => float(half(self.uint(2)? as u16)),
+                26 => float(f32::from_bits(self.uint(4)? as u32) as f64),
+                27 => float(f64::from_bits(self.uint(8)?)),
+                _ => return Err(invalid("unsupported CBOR simple value")),
+            });
+        }
+        let n = match info {
+            0..=23 => info as u64,
+            24..=27 => self.uint(1 << (info - 24))?,
+            _ => return Err(invalid("indefinite-length CBOR items are not supported")),
+        };
+        Ok(match major {
+            0 => Value::from(n),
+            1 => i64::try_from(n).map(|n| Value::from(-1 - n)).map_err(|_| invalid("integer out of range"))?,
+            3 => Value::String(self.text(n)?),
+            4 => self.items(n, depth)?,
+            5 => self.entries(n, depth)?,
+            // A tag only annotates the item after it
+            6 => self.value(depth + 1)?,
+            _ => return Err(invalid("byte strings are not supported")),
+        })
+    }
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = io::Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.input.fill_buf() {
+            Ok([]) => None,
+            Ok(_) => Some(self.value(0)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+/// IEEE 754 half precision, which CBOR encoders may pick for small floats.
+fn half(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((bits >> 10) & 0x1f) as i32;
+    let frac = (bits & 0x3ff) as f64;
+    sign * match exp {
+        0 => frac * 2f64.powi(-24),
+        31 if frac == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + frac / 1024.0) * 2f64.powi(exp - 15),
+    }
+}
+
+/// What `convert` turns a binary log into.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// JSON lines, as `--format json` writes them
+    Json,
+    /// `timestamp,ip,port,state,latency_ms`, a row per port
+    Csv,
+}
+
+/// `convert`: a MessagePack or CBOR log (told apart by its first byte) to
+/// JSON lines or CSV. Returns the number of records.
+pub fn convert(input: &str, to: Target, out: &mut dyn Write) -> Result<usize> {
+    let file = std::fs::File::open(input).with_context(|| format!("failed to open '{}'", input))?;
+    let mut reader = BufReader::new(file);
+    let Some(&first) = reader.fill_buf()?.first() else { return Ok(0) };
+    let Some(format) = Format::detect(first) else { bail!("'{}' is not a MessagePack or CBOR results log", input) };
+    if to == Target::Csv { writeln!(out, "timestamp,ip,port,state,latency_ms")?; }
+    let mut count = 0;
+    for record in Records::new(reader, format) {
+        let record = record.with_context(|| format!("'{}' is corrupt after {} records", input, count))?;
+        count += 1;
+        match to {
+            Target::Json => writeln!(out, "{}", record)?,
+            Target::Csv => {
+                // A hit lists its ports, an --all-ports row is one
+                let ports = match record.get("ports").and_then(Value::as_array) {
+                    Some(ports) => ports.clone(),
+                    None => vec![record.clone()],
+                };
+                let field = |v: &Value, key: &str| v.get(key).map(|f| f.as_str().map_or_else(|| f.to_string(), str::to_string)).unwrap_or_default();
+                for port in &ports {
+                    writeln!(out, "{},{},{},{},{}", field(&record, "timestamp"), field(&record, "ip"), field(port, "port"), field(port, "state"), field(port, "latency_ms"))?;
+                }
+            }
+        }
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_round_trip() {
+        let value = serde_json::json!({
+            "schema_version": 1, "ip": "203.0.113.7", "ports": [{"port": 443, "state": "open", "latency_ms": 70000}, {"port": 22, "latency_ms": 5_000_000_000u64}],
+            "neg": [-1, -33, -200, -40000, -3_000_000_000i64], "geo": {"latitude": 60.17, "city": "x".repeat(40)}, "ok": true, "none": null,
+            "long": "y".repeat(300), "wide": (0..20).collect::<Vec<_>>(),
+        });
+        for format in [Format::Msgpack, Format::Cbor] {
+            let mut bytes = format.encode(&value);
+            bytes.extend(format.encode(&serde_json::json!({"ip": "198.51.100.1"})));
+            assert_eq!(Format::detect(bytes[0]), Some(format));
+            let records: Vec<Value> = Records::new(&bytes[..], format).collect::<io::Result<_>>().unwrap();
+            assert_eq!(records, vec![value.clone(), serde_json::json!({"ip": "198.51.100.1"})], "{:?}", format);
+            // A cut-off record is an error, not a silent end
+            assert!(Records::new(&bytes[..bytes.len() - 3], format).any(|r| r.is_err()));
+        }
+        // Reference encodings from RFC 8949 appendix A and the MessagePack spec
+        assert_eq!(Format::Cbor.encode(&serde_json::json!({"a": [1, -100]})), [0xa1, 0x61, 0x61, 0x82, 0x01, 0x38, 0x63]);
+        assert_eq!(Format::Msgpack.encode(&serde_json::json!({"a": [1, -100]})), [0x81, 0xa1, 0x61, 0x92, 0x01, 0xd0, 0x9c]);
+        assert_eq!(half(0x3c00), 1.0);
+        assert_eq!(half(0xc400), -4.0);
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::net::{TcpSocket, TcpStream, UdpSocket};
+
+/// Local address / interface selection for outgoing probes.
+/// Multiple source IPs are handed out round-robin to spread load.
+pub struct SourceBinding {
+    ips: Vec<Ipv4Addr>,
+    interface: Option<String>,
+    next: AtomicUsize,
+}
+
+impl SourceBinding {
+    pub fn new(source_ips: Option<&str>, interface: Option<&str>) -> Result<Self> {
+        let mut ips = Vec::new();
+        if let Some(list) = source_ips {
+            for s in list.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                ips.push(s.parse::<Ipv4Addr>().with_context(|| format!("invalid source IP '{}'", s))?);
+            }
+        }
+        if interface.is_some() && !cfg!(any(target_os = "linux", target_os = "android")) {
+            bail!("--interface is only supported on Linux");
+        }
+        Ok(Self { ips, interface: interface.map(str::to_string), next: AtomicUsize::new(0) })
+    }
+
+    pub fn is_default(&self) -> bool { self.ips.is_empty() && self.interface.is_none() }
+
+    /// Next source IP in the rotation, if any were configured.
+    pub fn next_ip(&self) -> Option<Ipv4Addr> {
+        if self.ips.is_empty() { return None; }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.ips.len();
+        Some(self.ips[i])
+    }
+
+    /// Source IPs are IPv4 and only apply to IPv4 destinations.
+    pub async fn connect(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        if self.is_default() { return TcpStream::connect(addr).await; }
+        let socket = if addr.is_ipv6() { TcpSocket::new_v6()? } else { TcpSocket::new_v4()? };
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(ref iface) = self.interface {
+            socket.bind_device(Some(iface.as_bytes()))?;
+        }
+        if addr.is_ipv4() && let Some(ip) = self.next_ip() {
+            socket.bind(SocketAddr::new(ip.into(), 0))?;
+        }
+        socket.connect(addr).await
+    }
+
+    /// A UDP socket connected to `addr`, bound the same way as `connect`.
+    pub async fn connect_udp(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let local = match self.next_ip().filter(|_| addr.is_ipv4()) {
+            Some(ip) => SocketAddr::new(ip.into(), 0),
+            None if addr.is_ipv4() => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            None => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        let socket = UdpSocket::bind(local).await?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(ref iface) = self.interface {
+            socket.bind_device(Some(iface.as_bytes()))?;
+        }
+        socket.connect(addr).await?;
+        Ok(socket)
+    }
+
+    pub fn describe(&self) -> String {
+        let ips = self.ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(",");
+        match (&self.interface, ips.is_empty()) {
+            (None, true) => "default".to_string(),
+            (None, false) => ips,
+            (Some(i), true) => i.clone(),
+            (Some(i), false) => format!("{}@{}", ips, i),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_round_robin() {
+        let b = SourceBinding::new(Some("10.0.0.1, 10.0.0.2"), None).unwrap();
+        assert_eq!(b.next_ip(), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(b.next_ip(), Some(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(b.next_ip(), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(SourceBinding::new(Some("bogus"), None).is_err());
+    }
+    #[test]
+    fn test_udp_source() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        // Loopback is the only source address every platform has
+        let b = SourceBinding::new(Some("127.0.0.1"), None).unwrap();
+        let sock = rt.block_on(b.connect_udp("127.0.0.1:9".parse().unwrap())).unwrap();
+        assert_eq!(sock.local_addr().unwrap().ip(), Ipv4Addr::LOCALHOST);
+        assert_eq!(sock.peer_addr().unwrap(), "127.0.0.1:9".parse().unwrap());
+    }
+}
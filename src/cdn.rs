@@ -0,0 +1,120 @@
+use crate::cloud::CloudRanges;
+use crate::{http, tls, tlsaudit, web};
+use ipnet::Ipv4Net;
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+/// Edge networks of CDNs that publish them; Cloudflare and CloudFront are
+/// also taken from the --update-cloud table when it's there.
+const EDGES: &[(&str, &str)] = &[
+    ("173.245.48.0/20", "cloudflare"), ("103.21.244.0/22", "cloudflare"), ("103.22.200.0/22", "cloudflare"),
+    ("103.31.4.0/22", "cloudflare"), ("141.101.64.0/18", "cloudflare"), ("108.162.192.0/18", "cloudflare"),
+    ("190.93.240.0/20", "cloudflare"), ("188.114.96.0/20", "cloudflare"), ("197.234.240.0/22", "cloudflare"),
+    ("198.41.128.0/17", "cloudflare"), ("162.158.0.0/15", "cloudflare"), ("104.16.0.0/13", "cloudflare"),
+    ("104.24.0.0/14", "cloudflare"), ("172.64.0.0/13", "cloudflare"), ("131.0.72.0/22", "cloudflare"),
+    ("23.235.32.0/20", "fastly"), ("43.249.72.0/22", "fastly"), ("103.244.50.0/24", "fastly"),
+    ("103.245.222.0/23", "fastly"), ("103.245.224.0/24", "fastly"), ("104.156.80.0/20", "fastly"),
+    ("140.248.64.0/18", "fastly"), ("140.248.128.0/17", "fastly"), ("146.75.0.0/17", "fastly"),
+    ("151.101.0.0/16", "fastly"), ("157.52.64.0/18", "fastly"), ("167.82.0.0/17", "fastly"),
+    ("172.111.64.0/18", "fastly"), ("185.31.16.0/22", "fastly"), ("199.27.72.0/21", "fastly"),
+    ("199.232.0.0/16", "fastly"),
+];
+
+/// Response headers only an edge adds: header name, lowercase substring of
+/// its value ("" for any value), provider.
+const HEADERS: &[(&str, &str, &str)] = &[
+    ("cf-ray", "", "cloudflare"),
+    ("server", "cloudflare", "cloudflare"),
+    ("x-amz-cf-id", "", "cloudfront"),
+    ("via", "cloudfront", "cloudfront"),
+    ("x-fastly-request-id", "", "fastly"),
+    ("x-served-by", "cache-", "fastly"),
+    ("server", "akamaighost", "akamai"),
+    ("x-akamai-transformed", "", "akamai"),
+    ("x-iinfo", "", "imperva"),
+    ("x-cdn", "incapsula", "imperva"),
+    ("x-sucuri-id", "", "sucuri"),
+    ("server", "sucuri", "sucuri"),
+    ("x-azure-ref", "", "azure-front-door"),
+    ("server", "ddos-guard", "ddos-guard"),
+    ("x-cdn", "stackpath", "stackpath"),
+];
+
+/// Certificate issuer or name fragments (lowercase) that belong to an edge
+/// rather than to the site behind it.
+const CERTIFICATES: &[(&str, &str)] = &[
+    ("cloudflare", "cloudflare"),
+    ("cloudfront.net", "cloudfront"),
+    ("fastly.net", "fastly"),
+    ("akamai", "akamai"),
+    ("incapsula", "imperva"),
+    ("imperva", "imperva"),
+    ("sucuri", "sucuri"),
+    ("azureedge.net", "azure-front-door"),
+    ("azurefd.net", "azure-front-door"),
+];
+
+/// A CDN or WAF edge answering in place of the origin server.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CdnFront {
+    pub provider: &'static str,
+    /// What pointed at it: `header`, `certificate` and/or `ip`
+    pub evidence: Vec<&'static str>,
+}
+
+pub fn by_ip(ip: Ipv4Addr, clouds: Option<&CloudRanges>) -> Option<&'static str> {
+    let listed = clouds.and_then(|c| c.lookup(ip)).and_then(|tag| match (tag.provider.as_str(), tag.service.as_deref()) {
+        ("cloudflare", _) => Some("cloudflare"),
+        ("aws", Some("CLOUDFRONT")) => Some("cloudfront"),
+        _ => None,
+    });
+    listed.or_else(|| EDGES.iter().find(|(net, _)| net.parse::<Ipv4Net>().is_ok_and(|n| n.contains(&ip))).map(|&(_, p)| p))
+}
+
+pub fn by_headers(resp: &http::Response) -> Option<&'static str> {
+    HEADERS.iter().find(|(name, needle, _)| {
+        resp.headers.iter().any(|(k, v)| k.eq_ignore_ascii_case(name) && v.to_ascii_lowercase().contains(needle))
+    }).map(|&(_, _, p)| p)
+}
+
+pub fn by_certificate(cert: &tls::CertInfo) -> Option<&'static str> {
+    let names: Vec<String> = std::iter::once(&cert.issuer).chain(&cert.subject_cn).chain(&cert.san).map(|s| s.to_ascii_lowercase()).collect();
+    CERTIFICATES.iter().find(|(needle, _)| names.iter().any(|n| n.contains(needle))).map(|&(_, p)| p)
+}
+
+/// Headers are the most direct sign, so they name the provider when the
+/// sources disagree; evidence lists every source that agrees with it.
+pub fn detect(ip: Ipv4Addr, clouds: Option<&CloudRanges>, audits: &[tlsaudit::TlsAudit], pages: &[web::WebInfo]) -> Option<CdnFront> {
+    let found = [
+        ("header", pages.iter().find_map(|p| p.cdn)),
+        ("certificate", audits.iter().filter_map(|a| a.certificate.as_ref()).find_map(by_certificate)),
+        ("ip", by_ip(ip, clouds)),
+    ];
+    let provider = found.iter().find_map(|(_, p)| *p)?;
+    let evidence = found.iter().filter(|(_, p)| *p == Some(provider)).map(|(e, _)| *e).collect();
+    Some(CdnFront { provider, evidence })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_detect() {
+        let resp = http::Response { status: 403, headers: vec![("Server".into(), "cloudflare".into()), ("CF-RAY".into(), "8a1-HEL".into())], body: Vec::new() };
+        assert_eq!(by_headers(&resp), Some("cloudflare"));
+        let resp = http::Response { status: 200, headers: vec![("Via".into(), "1.1 abc.cloudfront.net (CloudFront)".into())], body: Vec::new() };
+        assert_eq!(by_headers(&resp), Some("cloudfront"));
+        let cert = tls::CertInfo {
+            subject_cn: Some("example.com".into()), issuer: "CN=Cloudflare Inc ECC CA-3, O=Cloudflare, Inc.".into(), not_after: String::new(),
+            san: vec!["sni.cloudflaressl.com".into()], self_signed: false, expired: false,
+        };
+        assert_eq!(by_certificate(&cert), Some("cloudflare"));
+        assert_eq!(by_ip(Ipv4Addr::new(151, 101, 1, 69), None), Some("fastly"));
+        assert_eq!(by_ip(Ipv4Addr::new(8, 8, 8, 8), None), None);
+
+        let audit = tlsaudit::TlsAudit { port: 443, versions: Vec::new(), ciphers: Default::default(), alpn: Vec::new(), certificate: Some(cert), findings: Vec::new() };
+        let front = detect(Ipv4Addr::new(104, 16, 1, 1), None, &[audit], &[]).unwrap();
+        assert_eq!((front.provider, front.evidence), ("cloudflare", vec!["certificate", "ip"]));
+        assert_eq!(detect(Ipv4Addr::new(8, 8, 8, 8), None, &[], &[]), None);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::resolver::Resolver;
+use crate::{filter, http, targets};
+use anyhow::{bail, Context, Result};
+use ipnet::Ipv4Net;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use tracing::warn;
+
+const AWS_URL: &str = "https://ip-ranges.amazonaws.com/ip-ranges.json";
+const GCP_URL: &str = "https://www.gstatic.com/ipranges/cloud.json";
+/// The Service Tags file gets a new dated name every week; this page links the current one.
+const AZURE_PAGE: &str = "https://www.microsoft.com/en-us/download/details.aspx?id=56519";
+const CLOUDFLARE_URL: &str = "https://www.cloudflare.com/ips-v4";
+
+/// Which cloud a hit's address belongs to.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CloudTag {
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
+/// Published provider ranges, `<cidr> <provider> [region [service]]` per
+/// line. Lookups pick the most specific prefix.
+#[derive(Clone)]
+pub struct CloudRanges {
+    tags: Vec<CloudTag>,
+    /// Prefix length and network -> tag, longest prefixes first
+    by_len: Vec<(u8, HashMap<u32, usize>)>,
+    all: targets::RangeSet,
+}
+
+impl CloudRanges {
+    /// `path`, else the --update-cloud cache; None when neither exists.
+    pub fn load(path: Option<&str>) -> Result<Option<Self>> {
+        let path = match path {
+            Some(p) => PathBuf::from(p),
+            None => match cache_file().filter(|p| p.exists()) {
+                Some(p) => p,
+                None => return Ok(None),
+            },
+        };
+        let content = std::fs::read_to_string(&path).with_context(|| format!("failed to read cloud ranges '{}'", path.display()))?;
+        parse(&content).with_context(|| format!("invalid cloud ranges '{}'", path.display())).map(Some)
+    }
+
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<&CloudTag> {
+        let ip = u32::from(ip);
+        self.by_len.iter().find_map(|(len, nets)| {
+            let mask = u32::MAX.checked_shl(32 - *len as u32).unwrap_or(0);
+            nets.get(&(ip & mask))
+        }).map(|&i| &self.tags[i])
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.all.contains(ip)
+    }
+
+    /// All cloud address space, for drawing random targets from it.
+    pub fn range_set(&self) -> &targets::RangeSet {
+        &self.all
+    }
+}
+
+fn parse(content: &str) -> Result<CloudRanges> {
+    let mut tags = Vec::new();
+    let mut index: HashMap<(String, Option<String>, Option<String>), usize> = HashMap::new();
+    let mut by_len: HashMap<u8, HashMap<u32, usize>> = HashMap::new();
+    let mut ranges = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() { continue; }
+        let mut fields = line.split_whitespace();
+        let net = fields.next().unwrap_or_default();
+        let net = net.parse::<Ipv4Net>().with_context(|| format!("line {}: invalid CIDR '{}'", n + 1, net))?.trunc();
+        let Some(provider) = fields.next() else { bail!("line {}: missing provider", n + 1) };
+        let key = (provider.to_string(), fields.next().map(str::to_string), fields.next().map(str::to_string));
+        let tag = *index.entry(key.clone()).or_insert_with(|| {
+            tags.push(CloudTag { provider: key.0, region: key.1, service: key.2 });
+            tags.len() - 1
+        });
+        // The first line for a prefix wins
+        by_len.entry(net.prefix_len()).or_default().entry(u32::from(net.network())).or_insert(tag);
+        ranges.push((u32::from(net.network()), u32::from(net.broadcast())));
+    }
+    let mut by_len: Vec<_> = by_len.into_iter().collect();
+    by_len.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+    Ok(CloudRanges { tags, by_len, all: targets::RangeSet::new(ranges) })
+}
+
+pub fn cache_file() -> Option<PathBuf> {
+    Some(filter::cache_dir()?.join("cloud.txt"))
+}
+
+/// Downloads every provider's feed into the cache. A provider whose feed
+/// can't be fetched keeps its ranges from the previous download. Returns the
+/// file and the number of prefixes.
+pub async fn update_cache(resolver: &Resolver) -> Result<(PathBuf, usize)> {
+    let path = cache_file().context("no cache directory (set XDG_CACHE_HOME or HOME)")?;
+    let previous = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut table = format!("# Cloud provider ranges, fetched {}\n", chrono::Local::now().format("%Y-%m-%d"));
+    let mut fetched = 0;
+    for provider in ["aws", "gcp", "azure", "cloudflare"] {
+        let lines = match fetch(resolver, provider).await {
+            Ok(lines) => { fetched += 1; lines }
+            Err(e) => {
+                warn!("{:#}; keeping the previous {} ranges", e, provider);
+                previous.lines().filter(|l| l.split_whitespace().nth(1) == Some(provider)).map(str::to_string).collect()
+            }
+        };
+        for line in lines { table.push_str(&line); table.push('\n'); }
+    }
+    if fetched == 0 { bail!("no cloud range feed could be downloaded"); }
+    let count = table.lines().filter(|l| !l.starts_with('#')).count();
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).with_context(|| format!("failed to create '{}'", dir.display()))?; }
+    std::fs::write(&path, table).with_context(|| format!("failed to write '{}'", path.display()))?;
+    Ok((path, count))
+}
+
+async fn get(resolver: &Resolver, url: &str) -> Result<String> {
+    let resp = http::download(resolver, url).await.with_context(|| format!("failed to download {}", url))?;
+    if resp.status != 200 { bail!("{} answered HTTP {}", url, resp.status); }
+    Ok(String::from_utf8_lossy(&resp.body).into_owned())
+}
+
+async fn fetch(resolver: &Resolver, provider: &str) -> Result<Vec<String>> {
+    let json = |body: String| serde_json::from_str::<Value>(&body).with_context(|| format!("unexpected {} feed", provider));
+    let lines = match provider {
+        "aws" => aws_lines(&json(get(resolver, AWS_URL).await?)?),
+        "gcp" => gcp_lines(&json(get(resolver, GCP_URL).await?)?),
+        "azure" => {
+            let page = get(resolver, AZURE_PAGE).await?;
+            let url = azure_link(&page).context("no Service Tags link on the Azure download page")?;
+            azure_lines(&json(get(resolver, url).await?)?)
+        }
+        _ => cloudflare_lines(&get(resolver, CLOUDFLARE_URL).await?),
+    };
+    if lines.is_empty() { bail!("the {} feed lists no IPv4 ranges", provider); }
+    Ok(lines)
+}
+
+fn line(net: &str, provider: &str, region: Option<&str>, service: Option<&str>) -> Option<String> {
+    let net: Ipv4Net = net.parse().ok()?;
+    let word = |s: Option<&str>| s.filter(|s| !s.is_empty()).map(|s| s.replace(char::is_whitespace, "_"));
+    Some(match (word(region), word(service)) {
+        (Some(r), Some(s)) => format!("{} {} {} {}", net, provider, r, s),
+        (Some(r), None) => format!("{} {} {}", net, provider, r),
+        (None, Some(s)) => format!("{} {} - {}", net, provider, s),
+        (None, None) => format!("{} {}", net, provider),
+    })
+}
+
+/// `AMAZON` is the superset of every service, so it only tags what no
+/// specific service (EC2, CLOUDFRONT, ...) claims.
+fn aws_lines(doc: &Value) -> Vec<String> {
+    let mut prefixes: Vec<&Value> = doc["prefixes"].as_array().map(|a| a.iter().collect()).unwrap_or_default();
+    prefixes.sort_by_key(|p| p["service"] == "AMAZON");
+    let mut seen = BTreeSet::new();
+    prefixes.into_iter()
+        .filter(|p| seen.insert(p["ip_prefix"].as_str().unwrap_or_default()))
+        .filter_map(|p| line(p["ip_prefix"].as_str()?, "aws", p["region"].as_str(), p["service"].as_str()))
+        .collect()
+}
+
+fn gcp_lines(doc: &Value) -> Vec<String> {
+    doc["prefixes"].as_array().into_iter().flatten()
+        .filter_map(|p| line(p["ipv4Prefix"].as_str()?, "gcp", p["scope"].as_str(), None))
+        .collect()
+}
+
+/// Regional `AzureCloud.<region>` tags; the catch-all `AzureCloud` fills in
+/// what they don't list.
+fn azure_lines(doc: &Value) -> Vec<String> {
+    let mut tags: Vec<&Value> = doc["values"].as_array().into_iter().flatten()
+        .filter(|v| v["name"].as_str().is_some_and(|n| n == "AzureCloud" || n.starts_with("AzureCloud.")))
+        .collect();
+    tags.sort_by_key(|v| v["name"] == "AzureCloud");
+    let mut seen = BTreeSet::new();
+    let mut lines = Vec::new();
+    for tag in tags {
+        let region = tag["properties"]["region"].as_str();
+        for prefix in tag["properties"]["addressPrefixes"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            if seen.insert(prefix) && let Some(l) = line(prefix, "azure", region, None) { lines.push(l); }
+        }
+    }
+    lines
+}
+
+fn azure_link(page: &str) -> Option<&str> {
+    let start = page.find("https://download.microsoft.com/download/")?;
+    let end = start + page[start..].find(".json")? + ".json".len();
+    Some(&page[start..end])
+}
+
+fn cloudflare_lines(body: &str) -> Vec<String> {
+    body.lines().filter_map(|l| line(l.trim(), "cloudflare", None, None)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_feeds_and_lookup() {
+        let aws = serde_json::json!({"prefixes": [
+            {"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "AMAZON"},
+            {"ip_prefix": "3.5.140.0/22", "region": "ap-northeast-2", "service": "S3"},
+            {"ip_prefix": "3.0.0.0/15", "region": "ap-southeast-1", "service": "AMAZON"},
+        ]});
+        let azure = serde_json::json!({"values": [
+            {"name": "AzureCloud", "properties": {"region": "", "addressPrefixes": ["13.64.0.0/16", "20.0.0.0/16", "2603:1000::/40"]}},
+            {"name": "AzureCloud.westus", "properties": {"region": "westus", "addressPrefixes": ["13.64.0.0/16"]}},
+            {"name": "Storage", "properties": {"region": "", "addressPrefixes": ["20.1.0.0/16"]}},
+        ]});
+        let mut table: Vec<String> = aws_lines(&aws);
+        table.extend(azure_lines(&azure));
+        table.extend(cloudflare_lines("104.16.0.0/13\n"));
+        assert_eq!(table, vec![
+            "3.5.140.0/22 aws ap-northeast-2 S3", "3.0.0.0/15 aws ap-southeast-1 AMAZON",
+            "13.64.0.0/16 azure westus", "20.0.0.0/16 azure", "104.16.0.0/13 cloudflare",
+        ]);
+        let ranges = parse(&table.join("\n")).unwrap();
+        let tag = ranges.lookup(Ipv4Addr::new(3, 5, 141, 9)).unwrap();
+        assert_eq!((tag.provider.as_str(), tag.service.as_deref()), ("aws", Some("S3")));
+        assert_eq!(ranges.lookup(Ipv4Addr::new(3, 1, 0, 1)).unwrap().region.as_deref(), Some("ap-southeast-1"));
+        assert!(ranges.contains(Ipv4Addr::new(104, 17, 0, 1)) && !ranges.contains(Ipv4Addr::new(8, 8, 8, 8)));
+        assert_eq!(azure_link("<a href=\"https://download.microsoft.com/download/7/1/D/x/ServiceTags_Public_20261012.json\">"),
+            Some("https://download.microsoft.com/download/7/1/D/x/ServiceTags_Public_20261012.json"));
+    }
+}
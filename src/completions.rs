@@ -0,0 +1,52 @@
+use clap::Command;
+
+/// Shells `completions` writes scripts for.
+pub use clap_complete::Shell;
+
+/// Completion script for `cmd`, as clap_complete writes it. In bash, zsh and
+/// fish, `--profile` names are looked up on every completion through
+/// `<bin> completions --list-profiles`, so they follow the config file as it changes.
+pub fn generate(shell: Shell, cmd: &mut Command) -> String {
+    let bin = cmd.get_bin_name().unwrap_or(cmd.get_name()).to_string();
+    let mut out = Vec::new();
+    clap_complete::generate(shell, cmd, &bin, &mut out);
+    let script = String::from_utf8_lossy(&out).into_owned();
+    let profiles = format!("{} completions --list-profiles 2>/dev/null", bin);
+    match shell {
+        // The generated scripts offer file names for --profile; these ask the binary instead
+        Shell::Bash => {
+            let mut lines: Vec<String> = script.lines().map(str::to_string).collect();
+            for i in 1..lines.len() {
+                if lines[i - 1].trim() == "--profile)" {
+                    lines[i] = lines[i].replace("compgen -f", &format!("compgen -W \"$({})\" --", profiles));
+                }
+            }
+            lines.join("\n") + "\n"
+        }
+        Shell::Zsh => script.replace(":PROFILE:_default'", &format!(":PROFILE:{{compadd -- ${{(f)\"$({})\"}}}}'", profiles)),
+        // Fish merges this with the generated lines for --profile
+        Shell::Fish => format!("{}complete -c {} -l profile -x -a '({})'\n", script, bin, profiles),
+        _ => script,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+    #[test]
+    fn test_profile_hooks() {
+        let mut cmd = Command::new("pn")
+            .arg(Arg::new("profile").long("profile").value_name("PROFILE"))
+            .arg(Arg::new("file").long("file"))
+            .subcommand(Command::new("scan").arg(Arg::new("profile").long("profile").value_name("PROFILE")));
+        let bash = generate(Shell::Bash, &mut cmd);
+        assert_eq!(bash.matches("COMPREPLY=($(compgen -W \"$(pn completions --list-profiles 2>/dev/null)\" -- \"${cur}\"))").count(), 2);
+        assert!(bash.contains("--file)\n                    COMPREPLY=($(compgen -f \"${cur}\"))"));
+        let zsh = generate(Shell::Zsh, &mut cmd);
+        assert_eq!(zsh.matches(":PROFILE:{compadd -- ${(f)\"$(pn completions --list-profiles 2>/dev/null)\"}}'").count(), 2);
+        let fish = generate(Shell::Fish, &mut cmd);
+        assert!(fish.ends_with("complete -c pn -l profile -x -a '(pn completions --list-profiles 2>/dev/null)'\n"));
+        assert!(generate(Shell::PowerShell, &mut cmd).contains("'pn'"));
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command, CommandFactory};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use toml::Table;
+
+/// Reads the config file into a flat table of settings. When `profile` is
+/// given, the matching `[profile.<name>]` table is layered over the top-level
+/// settings. Returns None when the file doesn't exist and no profile was asked for.
+pub fn load_table(path: &str, profile: Option<&str>) -> Result<Option<Table>> {
+    if !Path::new(path).exists() {
+        if let Some(name) = profile { bail!("profile '{}' requested but config file '{}' does not exist", name, path); }
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read config '{}'", path))?;
+    let mut table: Table = toml::from_str(&content).with_context(|| format!("invalid TOML in '{}'", path))?;
+    let profiles = match table.remove("profile") {
+        Some(toml::Value::Table(t)) => t,
+        Some(_) => bail!("'profile' in '{}' must be a table of [profile.<name>] sections", path),
+        None => Table::new(),
+    };
+    if let Some(name) = profile {
+        let Some(toml::Value::Table(overrides)) = profiles.get(name) else {
+            let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            bail!("unknown profile '{}' (available: {})", name, if known.is_empty() { "none".to_string() } else { known.join(", ") });
+        };
+        table.extend(overrides.clone());
+    }
+    Ok(Some(table))
+}
+
+/// Names of the `[profile.<name>]` sections, for shell completion; empty
+/// when the file is missing or unreadable.
+pub fn profile_names(path: &str) -> Vec<String> {
+    let table = std::fs::read_to_string(path).ok().and_then(|content| toml::from_str::<Table>(&content).ok());
+    match table.as_ref().and_then(|t| t.get("profile")) {
+        Some(toml::Value::Table(profiles)) => profiles.keys().cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Binds every flag, subcommands' included, to a `<PREFIX>_<FIELD>`
+/// environment variable (e.g. `PULSENET_RATE`), which clap reads when the
+/// flag isn't on argv. Positionals and --help/--version are left alone: what
+/// a command works on comes from argv only.
+pub fn with_env(cmd: Command, prefix: &str) -> Command {
+    let names: Vec<String> = cmd.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    let mut cmd = cmd.mut_args(|arg| {
+        if arg.is_positional() || matches!(arg.get_action(), ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version) {
+            return arg;
+        }
+        let var = format!("{}_{}", prefix, arg.get_id().as_str().to_ascii_uppercase());
+        arg.env(var)
+    });
+    for name in names { cmd = cmd.mut_subcommand(name, |sub| with_env(sub, prefix)); }
+    cmd
+}
+
+/// Merges settings with per-field precedence: built-in defaults, then each
+/// layer in order (later layers win), then anything set through the
+/// environment or explicitly on the command line (clap already ranks CLI over
+/// env). `cli` is the value parsed from `matches`.
+pub fn resolve<T>(cli: &T, matches: &ArgMatches, layers: &[(&str, Table)]) -> Result<T>
+where
+    T: Serialize + DeserializeOwned + CommandFactory,
+{
+    let cmd = T::command();
+    let known: HashSet<&str> = cmd.get_arguments().map(|a| a.get_id().as_str()).collect();
+    let explicit: HashSet<&str> = known.iter().copied()
+        .filter(|id| matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)))
+        .collect();
+
+    let mut merged = Table::try_from(cli).context("failed to serialize settings")?;
+    for (name, layer) in layers {
+        for (key, value) in layer {
+            if !known.contains(key.as_str()) { bail!("unknown setting '{}' in {}", key, name); }
+            if !explicit.contains(key.as_str()) { merged.insert(key.clone(), value.clone()); }
+        }
+    }
+    merged.try_into().context("invalid value in configuration")
+}
+
+/// Renders every setting of `T` as a config file: each key under its help
+/// text, commented out at its default, except those given on the command
+/// line or through the environment, which are written active. `skip` leaves
+/// out settings that make no sense in the file itself.
+pub fn scaffold<T>(cli: &T, matches: &ArgMatches, skip: &[&str]) -> Result<String>
+where
+    T: Serialize + CommandFactory,
+{
+    let values = Table::try_from(cli).context("failed to serialize settings")?;
+    let mut out = String::from("# PulseNet settings. Uncomment a line to change it; environment variables\n# and command-line flags still override this file.\n");
+    for arg in T::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        if arg.is_hide_set() || skip.contains(&id) || matches!(id, "help" | "version") { continue; }
+        out.push('\n');
+        for line in arg.get_help().map(ToString::to_string).unwrap_or_default().lines() { writeln!(out, "# {}", line)?; }
+        let explicit = matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable));
+        match values.get(id) {
+            Some(value) if explicit => writeln!(out, "{} = {}", id, value)?,
+            Some(value) => writeln!(out, "# {} = {}", id, value)?,
+            // Unset by default: show what goes there instead of a value
+            None => {
+                let name = arg.get_value_names().and_then(|n| n.first()).map_or(id.to_ascii_uppercase(), ToString::to_string);
+                writeln!(out, "# {} = <{}>", id, name)?;
+            }
+        }
+    }
+    out.push_str("\n# Named profiles, selected with --profile <name>, override the settings above\n# [profile.fast]\n# rate = 5000\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_profile_layering() {
+        let dir = std::env::temp_dir().join(format!("pulsenet-cfg-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pulsenet.toml");
+        std::fs::write(&path, "rate = 100\nworkers = 8\n[profile.fast]\nrate = 5000\n[profile.stealth]\nrate = 5\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let base = load_table(path, None).unwrap().unwrap();
+        assert_eq!(base["rate"].as_integer(), Some(100));
+        assert!(!base.contains_key("profile"));
+        let fast = load_table(path, Some("fast")).unwrap().unwrap();
+        assert_eq!(fast["rate"].as_integer(), Some(5000));
+        assert_eq!(fast["workers"].as_integer(), Some(8));
+        assert!(load_table(path, Some("nope")).is_err());
+        assert_eq!(profile_names(path), vec!["fast", "stealth"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[derive(clap::Parser, Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct TestArgs {
+        #[arg(long, default_value_t = 500)]
+        rate: u32,
+        #[arg(long, default_value = "80")]
+        ports: String,
+        #[arg(long)]
+        json: bool,
+        #[arg(long)]
+        cidr: Option<String>,
+    }
+
+    fn resolve_with(argv: &[&str], file: &str) -> Result<TestArgs> {
+        resolve_with_env(argv, file, "PULSENET_UNSET")
+    }
+
+    fn resolve_with_env(argv: &[&str], file: &str, prefix: &str) -> Result<TestArgs> {
+        use clap::FromArgMatches;
+        let matches = with_env(TestArgs::command(), prefix).try_get_matches_from(argv)?;
+        let cli = TestArgs::from_arg_matches(&matches)?;
+        resolve(&cli, &matches, &[("config file", toml::from_str(file)?)])
+    }
+
+    #[test]
+    fn test_cli_beats_file_per_field() {
+        let args = resolve_with(&["pulsenet", "--rate", "9"], "rate = 100\nports = \"22\"\njson = true").unwrap();
+        assert_eq!(args, TestArgs { rate: 9, ports: "22".into(), json: true, cidr: None });
+        // A CLI value equal to the default still counts as explicit
+        let args = resolve_with(&["pulsenet", "--rate", "500"], "rate = 100").unwrap();
+        assert_eq!(args.rate, 500);
+        // Defaults only apply where neither file nor CLI set a value
+        let args = resolve_with(&["pulsenet", "--cidr", "10.0.0.0/8"], "").unwrap();
+        assert_eq!(args, TestArgs { rate: 500, ports: "80".into(), json: false, cidr: Some("10.0.0.0/8".into()) });
+    }
+
+    #[test]
+    fn test_env_between_file_and_cli() {
+        // Unique prefix so parallel tests never observe these variables
+        unsafe {
+            std::env::set_var("PULSENET_ENVTEST_RATE", "42");
+            std::env::set_var("PULSENET_ENVTEST_JSON", "true");
+            std::env::set_var("PULSENET_ENVTEST_PORTS", "443");
+        }
+        let args = resolve_with_env(&["pulsenet", "--ports", "22"], "rate = 100\njson = false", "PULSENET_ENVTEST").unwrap();
+        assert_eq!(args, TestArgs { rate: 42, ports: "22".into(), json: true, cidr: None });
+    }
+
+    #[test]
+    fn test_env_skips_positionals() {
+        unsafe {
+            std::env::set_var("PULSENET_POSTEST_IP", "192.0.2.1");
+            std::env::set_var("PULSENET_POSTEST_HELP", "true");
+            std::env::set_var("PULSENET_POSTEST_LIMIT", "3");
+        }
+        let cmd = Command::new("pulsenet").subcommand(Command::new("history").disable_help_flag(true)
+            .arg(clap::Arg::new("ip"))
+            .arg(clap::Arg::new("limit").long("limit"))
+            .arg(clap::Arg::new("help").long("help").action(ArgAction::Help)));
+        let matches = with_env(cmd, "PULSENET_POSTEST").try_get_matches_from(["pulsenet", "history"]).unwrap();
+        let (_, sub) = matches.subcommand().unwrap();
+        assert_eq!(sub.get_one::<String>("ip"), None);
+        assert_eq!(sub.get_one::<String>("limit").map(String::as_str), Some("3"));
+    }
+
+    #[test]
+    fn test_scaffold() {
+        use clap::FromArgMatches;
+        let matches = with_env(TestArgs::command(), "PULSENET_UNSET").try_get_matches_from(["pulsenet", "--rate", "9"]).unwrap();
+        let cli = TestArgs::from_arg_matches(&matches).unwrap();
+        let file = scaffold(&cli, &matches, &["json"]).unwrap();
+        assert!(file.contains("\nrate = 9\n"));
+        assert!(file.contains("\n# ports = \"80\"\n"));
+        assert!(file.contains("\n# cidr = <CIDR>\n"));
+        assert!(!file.contains("json"));
+        // Only what was passed is active, and it reads back
+        let args = resolve_with(&["pulsenet"], &file).unwrap();
+        assert_eq!(args, TestArgs { rate: 9, ports: "80".into(), json: false, cidr: None });
+    }
+
+    #[test]
+    fn test_rejects_unknown_and_mistyped_settings() {
+        assert!(resolve_with(&["pulsenet"], "rtae = 5").is_err());
+        assert!(resolve_with(&["pulsenet"], "rate = \"fast\"").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+
+/// A 5-field cron expression (minute hour day-of-month month day-of-week),
+/// with `*`, lists, ranges, `/step` and the `@hourly`-style shortcuts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Bitmask of the values a field allows, each within `min..=max`.
+fn field(spec: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|&s| s > 0).with_context(|| format!("invalid step in '{}'", part))?),
+            None => (part, 1),
+        };
+        let (lo, hi) = match range {
+            "*" => (min, max),
+            _ => {
+                let (lo, hi) = range.split_once('-').unwrap_or((range, ""));
+                let lo = lo.parse::<u32>().with_context(|| format!("invalid value '{}'", part))?;
+                // `5/15` runs from 5 to the end of the range
+                let hi = if !hi.is_empty() { hi.parse::<u32>().with_context(|| format!("invalid value '{}'", part))? } else if step > 1 { max } else { lo };
+                (lo, hi)
+            }
+        };
+        if lo < min || hi > max || lo > hi { bail!("'{}' is outside {}-{}", part, min, max); }
+        for v in (lo..=hi).step_by(step as usize) { mask |= 1 << v; }
+    }
+    Ok(mask)
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let expanded = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let parts: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = parts[..] else {
+            bail!("invalid schedule '{}': expected 5 fields (minute hour day month weekday)", expr);
+        };
+        let parse = || -> Result<Self> {
+            let mut weekdays = field(weekday, 0, 7)?;
+            // 7 is another name for Sunday
+            if weekdays & (1 << 7) != 0 { weekdays = (weekdays | 1) & !(1 << 7); }
+            Ok(Self {
+                minutes: field(minute, 0, 59)?,
+                hours: field(hour, 0, 23)?,
+                days: field(day, 1, 31)?,
+                months: field(month, 1, 12)?,
+                weekdays,
+                any_day: day == "*",
+                any_weekday: weekday == "*",
+            })
+        };
+        parse().with_context(|| format!("invalid schedule '{}'", expr))
+    }
+
+    /// Like cron: when both day fields are restricted, either may match.
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day && self.months & (1 << date.month()) != 0
+    }
+
+    /// The first matching minute strictly after `after`, or None if the
+    /// expression never matches (e.g. February 30th).
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut t: NaiveDateTime = after.naive_local().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = t + Duration::days(366 * 5);
+        while t < limit {
+            if !self.day_matches(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.date().and_hms_opt(t.hour(), 0, 0)? + Duration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+            } else if let Some(local) = Local.from_local_datetime(&t).earliest() {
+                return Some(local);
+            } else {
+                // Skipped by a DST change
+                t += Duration::minutes(1);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_next_run() {
+        let at = |s: &str| Local.from_local_datetime(&NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()).unwrap();
+        let nightly = Schedule::parse("0 2 * * *").unwrap();
+        assert_eq!(nightly.next_after(at("2026-10-15 01:59")), Some(at("2026-10-15 02:00")));
+        assert_eq!(nightly.next_after(at("2026-10-15 02:00")), Some(at("2026-10-16 02:00")));
+        let weekdays = Schedule::parse("*/15 9-17 * * 1-5").unwrap();
+        // 2026-10-17 is a Saturday
+        assert_eq!(weekdays.next_after(at("2026-10-16 17:50")), Some(at("2026-10-19 09:00")));
+        assert_eq!(Schedule::parse("@weekly").unwrap(), Schedule::parse("0 0 * * 7").unwrap());
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(at("2026-01-01 00:00")), None);
+        assert!(Schedule::parse("0 2 * *").is_err() && Schedule::parse("61 * * * *").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use crate::pause::PauseControl;
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// Set by SIGTERM or Ctrl-C under --daemon: no new targets start, probes in
+/// flight finish and outputs are flushed before the process exits.
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self { tx: Arc::new(watch::channel(false).0) }
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Stops dispatching new targets, as the first signal does.
+    pub fn stop(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub async fn wait(&self) {
+        let _ = self.tx.subscribe().wait_for(|set| *set).await;
+    }
+
+    /// The first signal starts a clean stop (lifting a pause so queued
+    /// targets can drain); a second one exits right away.
+    pub fn listen(&self, pause: PauseControl) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).ok();
+            loop {
+                #[cfg(unix)]
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    Some(_) = async { term.as_mut()?.recv().await } => {}
+                }
+                #[cfg(not(unix))]
+                let _ = tokio::signal::ctrl_c().await;
+                if this.is_set() {
+                    warn!("Second stop signal, exiting without flushing");
+                    std::process::exit(130);
+                }
+                warn!("Stop requested, finishing probes in flight");
+                notify("STOPPING=1");
+                this.stop();
+                pause.set(false);
+            }
+        });
+    }
+}
+
+/// Holds `--pid-file` for the life of the process and removes it on drop.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Refuses to start over a PID file whose process is still running;
+    /// one left behind by a crash is replaced.
+    pub fn create(path: &str) -> Result<Self> {
+        if let Some(pid) = std::fs::read_to_string(path).ok().and_then(|s| s.trim().parse::<u32>().ok())
+            && pid != std::process::id() && alive(pid) {
+            bail!("PulseNet is already running (pid {} in '{}')", pid, path);
+        }
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, format!("{}\n", std::process::id()))
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .with_context(|| format!("failed to write pid file '{}'", path))?;
+        Ok(Self { path: path.into() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    // Signal 0 only checks; EPERM still means the process exists
+    let found = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    found || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn alive(_pid: u32) -> bool {
+    false
+}
+
+/// sd_notify(3) for `Type=notify` units, e.g. `READY=1` or `STATUS=...`;
+/// does nothing outside systemd.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET")
+        && let Err(e) = send(&socket, state) {
+        debug!("sd_notify to {:?} failed: {}", socket, e);
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(unix)]
+fn send(socket: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+    let sock = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in the abstract namespace
+    if let Some(name) = socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            return sock.send_to_addr(state.as_bytes(), &addr).map(drop);
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(std::io::ErrorKind::Unsupported.into());
+        }
+    }
+    sock.send_to(state.as_bytes(), socket).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_pid_file() {
+        let path = std::env::temp_dir().join(format!("pulsenet-{}.pid", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        // Stale: no process 0x7ffffffe
+        std::fs::write(&path, "2147483646\n").unwrap();
+        {
+            let _pid = PidFile::create(&path).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), std::process::id().to_string());
+        }
+        assert!(!std::path::Path::new(&path).exists());
+        #[cfg(unix)]
+        {
+            // pid 1 is always running
+            std::fs::write(&path, "1\n").unwrap();
+            assert!(PidFile::create(&path).is_err());
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::dns;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::Instant;
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const SSDP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+/// DNS-SD meta query: every responder lists the service types it offers
+const SERVICES_META: &str = "_services._dns-sd._udp.local";
+
+/// A LAN device seen through mDNS or SSDP.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Device {
+    pub protocols: BTreeSet<&'static str>,
+    /// Host and instance names (`nas.local`, SSDP `SERVER` strings)
+    pub names: BTreeSet<String>,
+    /// mDNS service types and SSDP search targets
+    pub services: BTreeSet<String>,
+}
+
+pub type Inventory = BTreeMap<Ipv4Addr, Device>;
+
+/// Queries mDNS and SSDP and listens for answers and unsolicited
+/// announcements for `listen`.
+pub async fn discover(listen: Duration) -> io::Result<Inventory> {
+    let mut inventory = Inventory::new();
+    let deadline = Instant::now() + listen;
+    let (mdns, ssdp) = tokio::join!(mdns_listen(deadline), ssdp_listen(deadline));
+    for (ip, dev) in mdns?.into_iter().chain(ssdp?) {
+        let entry = inventory.entry(ip).or_default();
+        entry.protocols.extend(dev.protocols);
+        entry.names.extend(dev.names);
+        entry.services.extend(dev.services);
+    }
+    Ok(inventory)
+}
+
+/// Joins `group` on `port`, sharing the port with other responders (avahi etc.).
+/// Falls back to an ephemeral port, which still receives unicast answers.
+fn multicast_socket(group: Ipv4Addr, port: u16) -> io::Result<UdpSocket> {
+    let shared = || -> io::Result<Socket> {
+        let s = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        s.set_reuse_address(true)?;
+        #[cfg(unix)]
+        s.set_reuse_port(true)?;
+        s.bind(&SockAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)))?;
+        s.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+        Ok(s)
+    };
+    let sock = match shared() {
+        Ok(s) => s,
+        Err(_) => {
+            let s = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+            s.bind(&SockAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))?;
+            s
+        }
+    };
+    sock.set_nonblocking(true)?;
+    UdpSocket::from_std(sock.into())
+}
+
+async fn recv_until(sock: &UdpSocket, deadline: Instant, mut handle: impl FnMut(Ipv4Addr, &[u8])) {
+    let mut buf = vec![0u8; 9000];
+    while let Ok(Ok((n, SocketAddr::V4(from)))) = tokio::time::timeout_at(deadline, sock.recv_from(&mut buf)).await {
+        handle(*from.ip(), &buf[..n]);
+    }
+}
+
+async fn mdns_listen(deadline: Instant) -> io::Result<Inventory> {
+    let sock = multicast_socket(MDNS_GROUP, 5353)?;
+    sock.send_to(&dns::build_query(0, SERVICES_META, dns::TYPE_PTR, false), (MDNS_GROUP, 5353)).await?;
+    let mut found = Inventory::new();
+    recv_until(&sock, deadline, |from, pkt| {
+        if let Some(msg) = dns::parse(pkt) { merge_mdns(&mut found, from, &msg); }
+    }).await;
+    Ok(found)
+}
+
+fn merge_mdns(found: &mut Inventory, from: Ipv4Addr, msg: &dns::Message) {
+    // Even a bare query proves the sender is alive
+    found.entry(from).or_default().protocols.insert("mdns");
+    for rec in msg.records() {
+        match &rec.data {
+            dns::RData::A(addr) => {
+                let dev = found.entry(*addr).or_default();
+                dev.protocols.insert("mdns");
+                dev.names.insert(rec.name.clone());
+            }
+            dns::RData::Name(target) if rec.rtype == dns::TYPE_PTR && !rec.name.ends_with(".arpa") => {
+                let dev = found.entry(from).or_default();
+                if rec.name == SERVICES_META {
+                    dev.services.insert(target.clone());
+                } else {
+                    dev.services.insert(rec.name.clone());
+                    // Instance name is the label in front of the service type
+                    if let Some(instance) = target.strip_suffix(&rec.name).map(|s| s.trim_end_matches('.')) && !instance.is_empty() {
+                        dev.names.insert(instance.to_string());
+                    }
+                }
+            }
+            dns::RData::Srv { target, .. } => { found.entry(from).or_default().names.insert(target.clone()); }
+            _ => {}
+        }
+    }
+}
+
+async fn ssdp_listen(deadline: Instant) -> io::Result<Inventory> {
+    let sock = multicast_socket(SSDP_GROUP, 1900)?;
+    let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: ssdp:all\r\n\r\n";
+    sock.send_to(search.as_bytes(), (SSDP_GROUP, 1900)).await?;
+    let mut found = Inventory::new();
+    recv_until(&sock, deadline, |from, pkt| {
+        let text = String::from_utf8_lossy(pkt);
+        // Our own M-SEARCH loops back through the group
+        if text.starts_with("M-SEARCH") { return; }
+        let (server, target) = parse_ssdp(&text);
+        let dev = found.entry(from).or_default();
+        dev.protocols.insert("ssdp");
+        dev.names.extend(server);
+        dev.services.extend(target);
+    }).await;
+    Ok(found)
+}
+
+/// SERVER and ST/NT headers of an SSDP response or NOTIFY.
+fn parse_ssdp(text: &str) -> (Option<String>, Option<String>) {
+    let mut server = None;
+    let mut target = None;
+    for line in text.lines().skip(1) {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let value = value.trim().to_string();
+        match key.trim().to_ascii_uppercase().as_str() {
+            "SERVER" if !value.is_empty() => server = Some(value),
+            "ST" | "NT" if !value.is_empty() => target = Some(value),
+            _ => {}
+        }
+    }
+    (server, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_ssdp_response() {
+        let resp = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nST: urn:schemas-upnp-org:device:MediaRenderer:1\r\nSERVER: Linux/4.9 UPnP/1.0 Sonos/70.3\r\n\r\n";
+        assert_eq!(parse_ssdp(resp), (Some("Linux/4.9 UPnP/1.0 Sonos/70.3".into()), Some("urn:schemas-upnp-org:device:MediaRenderer:1".into())));
+    }
+    #[test]
+    fn test_merge_mdns_records() {
+        let from = Ipv4Addr::new(192, 168, 1, 20);
+        let msg = dns::Message {
+            id: 0, flags: 0x8400,
+            answers: vec![dns::Record { name: "_ipp._tcp.local".into(), rtype: dns::TYPE_PTR, data: dns::RData::Name("Office Printer._ipp._tcp.local".into()) }],
+            extra: vec![dns::Record { name: "printer.local".into(), rtype: dns::TYPE_A, data: dns::RData::A(from) }],
+        };
+        let mut inv = Inventory::new();
+        merge_mdns(&mut inv, from, &msg);
+        let dev = &inv[&from];
+        assert_eq!(dev.services.iter().collect::<Vec<_>>(), vec!["_ipp._tcp.local"]);
+        assert!(dev.names.contains("Office Printer") && dev.names.contains("printer.local"));
+    }
+}
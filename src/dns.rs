@@ -0,0 +1,180 @@
+use rand::Rng;
+use serde::Serialize;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_SRV: u16 = 33;
+
+/// Minimal DNS wire format: enough to build a single-question query and read
+/// the records of a response.
+pub fn build_query(id: u16, name: &str, qtype: u16, recursion_desired: bool) -> Vec<u8> {
+    let mut q = Vec::with_capacity(18 + name.len());
+    q.extend_from_slice(&id.to_be_bytes());
+    q.extend_from_slice(&(if recursion_desired { 0x0100u16 } else { 0 }).to_be_bytes());
+    q.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // 1 question, no records
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        q.push(label.len().min(63) as u8);
+        q.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+    }
+    q.push(0);
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&1u16.to_be_bytes()); // IN
+    q
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    /// PTR, CNAME and NS targets
+    Name(String),
+    Srv { port: u16, target: String },
+    Txt(Vec<String>),
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub data: RData,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    /// Answer section
+    pub answers: Vec<Record>,
+    /// Authority and additional sections
+    pub extra: Vec<Record>,
+}
+
+impl Message {
+    pub fn is_response(&self) -> bool { self.flags & 0x8000 != 0 }
+    pub fn recursion_available(&self) -> bool { self.flags & 0x0080 != 0 }
+    pub fn rcode(&self) -> u8 { (self.flags & 0x000f) as u8 }
+    pub fn truncated(&self) -> bool { self.flags & 0x0200 != 0 }
+    pub fn records(&self) -> impl Iterator<Item = &Record> { self.answers.iter().chain(&self.extra) }
+}
+
+pub fn parse(buf: &[u8]) -> Option<Message> {
+    let u16_at = |i: usize| buf.get(i..i + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let (id, flags) = (u16_at(0)?, u16_at(2)?);
+    let (qd, an) = (u16_at(4)?, u16_at(6)? as usize);
+    let rest = u16_at(8)? as usize + u16_at(10)? as usize;
+    let mut pos = 12;
+    for _ in 0..qd {
+        pos = read_name(buf, pos)?.1 + 4;
+    }
+    let mut records = Vec::with_capacity(an + rest);
+    for _ in 0..an + rest {
+        let (name, p) = read_name(buf, pos)?;
+        let rtype = u16_at(p)?;
+        let len = u16_at(p + 8)? as usize;
+        let start = p + 10;
+        let rdata = buf.get(start..start + len)?;
+        let data = match rtype {
+            TYPE_A if len == 4 => RData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+            TYPE_AAAA if len == 16 => RData::Aaaa(Ipv6Addr::from(<[u8; 16]>::try_from(rdata).ok()?)),
+            2 | 5 | TYPE_PTR => RData::Name(read_name(buf, start)?.0),
+            TYPE_SRV if len >= 7 => RData::Srv { port: u16_at(start + 4)?, target: read_name(buf, start + 6)?.0 },
+            TYPE_TXT => {
+                let mut strings = Vec::new();
+                let mut t = rdata;
+                while let Some((&n, tail)) = t.split_first() {
+                    let n = (n as usize).min(tail.len());
+                    strings.push(String::from_utf8_lossy(&tail[..n]).into_owned());
+                    t = &tail[n..];
+                }
+                RData::Txt(strings)
+            }
+            _ => RData::Other,
+        };
+        records.push(Record { name, rtype, data });
+        pos = start + len;
+    }
+    let extra = records.split_off(an);
+    Some(Message { id, flags, answers: records, extra })
+}
+
+/// A server that resolved a name it isn't authoritative for on our behalf.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OpenResolver {
+    pub query: String,
+    pub answers: Vec<String>,
+}
+
+/// Sends a recursive A query for `name` over `sock`, connected to UDP/53. The
+/// target counts as an open resolver when it offers recursion and returns addresses.
+pub async fn check_open_resolver(sock: UdpSocket, name: &str, wait: Duration) -> io::Result<Option<OpenResolver>> {
+    let id: u16 = rand::thread_rng().r#gen();
+    sock.send(&build_query(id, name, TYPE_A, true)).await?;
+    let mut buf = [0u8; 1500];
+    let Ok(Ok(n)) = tokio::time::timeout(wait, sock.recv(&mut buf)).await else { return Ok(None) };
+    Ok(parse(&buf[..n]).and_then(|msg| open_resolver_answer(&msg, id, name)))
+}
+
+fn open_resolver_answer(msg: &Message, id: u16, name: &str) -> Option<OpenResolver> {
+    if msg.id != id || !msg.is_response() || !msg.recursion_available() || msg.rcode() != 0 { return None; }
+    let answers: Vec<String> = msg.answers.iter()
+        .filter_map(|r| match r.data { RData::A(a) => Some(a.to_string()), _ => None })
+        .collect();
+    (!answers.is_empty()).then(|| OpenResolver { query: name.to_string(), answers })
+}
+
+/// Reads a possibly compressed name at `pos`; returns it and the offset just past it.
+fn read_name(buf: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    for _ in 0..128 { // bounds pointer loops
+        let len = *buf.get(pos)? as usize;
+        match len {
+            0 => return Some((labels.join("."), end.unwrap_or(pos + 1))),
+            l if l & 0xc0 == 0xc0 => {
+                let ptr = ((l & 0x3f) << 8) | *buf.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = ptr;
+            }
+            l => {
+                labels.push(String::from_utf8_lossy(buf.get(pos + 1..pos + 1 + l)?).into_owned());
+                pos += 1 + l;
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_compressed_response() {
+        let mut msg = build_query(0x1234, "printer.local", TYPE_A, false);
+        msg[2] = 0x84; // response, authoritative
+        msg[7] = 1; // one answer
+        msg.extend_from_slice(&[0xc0, 12, 0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 192, 168, 1, 50]);
+        let parsed = parse(&msg).unwrap();
+        assert!(parsed.is_response());
+        assert_eq!(parsed.answers, vec![Record { name: "printer.local".into(), rtype: TYPE_A, data: RData::A(Ipv4Addr::new(192, 168, 1, 50)) }]);
+        assert!(parse(&msg[..msg.len() - 3]).is_none());
+    }
+    #[test]
+    fn test_open_resolver_needs_recursion_and_answers() {
+        let mut msg = build_query(7, "example.com", TYPE_A, true);
+        msg[2..4].copy_from_slice(&0x8180u16.to_be_bytes()); // response, RD, RA, NOERROR
+        msg[7] = 1;
+        msg.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]);
+        let parsed = parse(&msg).unwrap();
+        assert_eq!(open_resolver_answer(&parsed, 7, "example.com").unwrap().answers, vec!["93.184.216.34"]);
+        assert!(open_resolver_answer(&parsed, 8, "example.com").is_none());
+        msg[3] = 0x00; // recursion not available (authoritative-only server)
+        assert!(open_resolver_answer(&parse(&msg).unwrap(), 7, "example.com").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::cloud::{CloudRanges, CloudTag};
+use anyhow::{Context, Result};
+use ipnet::Ipv4Net;
+use ipnetwork::IpNetwork;
+use maxminddb::{geoip2, Reader, WithinOptions};
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct GeoInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+}
+
+/// Per-hit annotations added after a successful probe.
+#[derive(Default, Debug)]
+pub struct Enrichment {
+    pub geo: Option<GeoInfo>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+    pub cloud: Option<CloudTag>,
+}
+
+/// ASN data either from a GeoLite2-ASN style MMDB or an iptoasn.com style
+/// TSV table (`range_start  range_end  asn  country  description`).
+pub enum AsnDb {
+    Mmdb(Reader<Vec<u8>>),
+    Table(Vec<AsnRange>),
+}
+
+pub struct AsnRange { start: u32, end: u32, asn: u32, org: String }
+
+impl AsnDb {
+    pub fn open(path: &str) -> Result<Self> {
+        if path.ends_with(".mmdb") {
+            let reader = Reader::open_readfile(path).with_context(|| format!("failed to open ASN database '{}'", path))?;
+            return Ok(AsnDb::Mmdb(reader));
+        }
+        let content = std::fs::read_to_string(path).with_context(|| format!("failed to read ASN table '{}'", path))?;
+        Ok(AsnDb::Table(parse_asn_table(&content)))
+    }
+
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<(u32, Option<String>)> {
+        match self {
+            AsnDb::Mmdb(reader) => {
+                let rec: geoip2::Asn = reader.lookup(ip.into()).ok()?.decode().ok()??;
+                Some((rec.autonomous_system_number?, rec.autonomous_system_organization.map(str::to_string)))
+            }
+            AsnDb::Table(ranges) => {
+                let ip = u32::from(ip);
+                let idx = ranges.partition_point(|r| r.start <= ip).checked_sub(1)?;
+                let r = &ranges[idx];
+                (ip <= r.end).then(|| (r.asn, Some(r.org.clone())))
+            }
+        }
+    }
+
+    /// All IPv4 prefixes the database attributes to `asn`.
+    pub fn prefixes(&self, asn: u32) -> Result<Vec<Ipv4Net>> {
+        let mut nets = Vec::new();
+        match self {
+            AsnDb::Mmdb(reader) => {
+                let all_v4 = IpNetwork::V4("0.0.0.0/0".parse()?);
+                for item in reader.within(all_v4, WithinOptions::default())? {
+                    let item = item?;
+                    let Some(rec) = item.decode::<geoip2::Asn>()? else { continue };
+                    if rec.autonomous_system_number == Some(asn)
+                        && let IpNetwork::V4(n) = item.network()?
+                    {
+                        nets.push(Ipv4Net::new(n.network(), n.prefix())?);
+                    }
+                }
+            }
+            AsnDb::Table(ranges) => {
+                for r in ranges.iter().filter(|r| r.asn == asn) {
+                    nets.extend(ipnet::Ipv4Subnets::new(r.start.into(), r.end.into(), 0));
+                }
+            }
+        }
+        Ok(nets)
+    }
+}
+
+fn parse_asn_table(content: &str) -> Vec<AsnRange> {
+    let mut ranges: Vec<AsnRange> = content.lines().filter_map(|line| {
+        let mut cols = line.split('\t');
+        let start: Ipv4Addr = cols.next()?.trim().parse().ok()?;
+        let end: Ipv4Addr = cols.next()?.trim().parse().ok()?;
+        let asn: u32 = cols.next()?.trim().trim_start_matches("AS").parse().ok()?;
+        let org = cols.nth(1).unwrap_or("").trim().to_string();
+        // ASN 0 marks unrouted space in iptoasn dumps
+        (asn != 0).then(|| AsnRange { start: start.into(), end: end.into(), asn, org })
+    }).collect();
+    ranges.sort_by_key(|r| r.start);
+    ranges
+}
+
+/// Enrichment stage of the result pipeline. Each data source is optional;
+/// a hit passes through untouched when none are configured.
+#[derive(Default)]
+pub struct Enricher {
+    geo: Option<Reader<Vec<u8>>>,
+    asn: Option<AsnDb>,
+    cloud: Option<CloudRanges>,
+}
+
+impl Enricher {
+    pub fn new(geoip_db: Option<&str>, asn_db: Option<&str>, cloud: Option<CloudRanges>) -> Result<Self> {
+        let geo = geoip_db
+            .map(|p| Reader::open_readfile(p).with_context(|| format!("failed to open GeoIP database '{}'", p)))
+            .transpose()?;
+        let asn = asn_db.map(AsnDb::open).transpose()?;
+        Ok(Self { geo, asn, cloud })
+    }
+
+    pub fn asn_db(&self) -> Option<&AsnDb> { self.asn.as_ref() }
+
+    pub fn has_geo(&self) -> bool { self.geo.is_some() }
+
+    pub fn cloud(&self) -> Option<&CloudRanges> { self.cloud.as_ref() }
+
+    /// IPv4 networks the GeoIP database places in country `cc` (ISO code).
+    pub fn country_networks(&self, cc: &str) -> Result<Vec<Ipv4Net>> {
+        let Some(ref reader) = self.geo else { return Ok(Vec::new()) };
+        let mut nets = Vec::new();
+        let all_v4 = IpNetwork::V4("0.0.0.0/0".parse()?);
+        for item in reader.within(all_v4, WithinOptions::default())? {
+            let item = item?;
+            let Some(rec) = item.decode::<geoip2::Country>()? else { continue };
+            if rec.country.iso_code.is_some_and(|c| c.eq_ignore_ascii_case(cc))
+                && let IpNetwork::V4(n) = item.network()?
+            {
+                nets.push(Ipv4Net::new(n.network(), n.prefix())?);
+            }
+        }
+        Ok(nets)
+    }
+
+    pub fn enrich(&self, ip: Ipv4Addr) -> Enrichment {
+        let (asn, as_org) = match self.asn.as_ref().and_then(|db| db.lookup(ip)) {
+            Some((asn, org)) => (Some(asn), org),
+            None => (None, None),
+        };
+        let cloud = self.cloud.as_ref().and_then(|c| c.lookup(ip)).cloned();
+        Enrichment { geo: self.geo.as_ref().and_then(|r| lookup_geo(r, ip)), asn, as_org, cloud }
+    }
+}
+
+fn lookup_geo(reader: &Reader<Vec<u8>>, ip: Ipv4Addr) -> Option<GeoInfo> {
+    let city: geoip2::City = reader.lookup(ip.into()).ok()?.decode().ok()??;
+    let info = GeoInfo {
+        country: city.country.iso_code.map(str::to_string),
+        city: city.city.names.english.map(str::to_string),
+        latitude: city.location.latitude,
+        longitude: city.location.longitude,
+    };
+    (info != GeoInfo::default()).then_some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_asn_table_lookup() {
+        let db = AsnDb::Table(parse_asn_table(
+            "1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET\n1.0.1.0\t1.0.3.255\t0\tNone\tNot routed\n8.8.8.0\t8.8.8.255\t15169\tUS\tGOOGLE\n",
+        ));
+        assert_eq!(db.lookup(Ipv4Addr::new(8, 8, 8, 8)), Some((15169, Some("GOOGLE".into()))));
+        assert_eq!(db.lookup(Ipv4Addr::new(1, 0, 0, 1)).map(|r| r.0), Some(13335));
+        assert_eq!(db.lookup(Ipv4Addr::new(1, 0, 2, 1)), None);
+        assert_eq!(db.lookup(Ipv4Addr::new(9, 9, 9, 9)), None);
+        assert_eq!(db.prefixes(13335).unwrap(), vec!["1.0.0.0/24".parse::<Ipv4Net>().unwrap()]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::http;
+use crate::resolver::Resolver;
+use anyhow::Result;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Instant};
+use tracing::warn;
+
+const BATCH: usize = 500;
+const FLUSH_EVERY: Duration = Duration::from_secs(2);
+const ATTEMPTS: u32 = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Documents indexed and given up on.
+#[derive(Debug, Default, PartialEq)]
+pub struct EsStats {
+    pub indexed: u64,
+    pub failed: u64,
+}
+
+/// Elasticsearch/OpenSearch `_bulk` indexer. Documents are queued on a bounded
+/// channel, so a slow cluster slows the scan down instead of piling up memory.
+pub struct EsSink {
+    tx: mpsc::Sender<Value>,
+    handle: JoinHandle<EsStats>,
+}
+
+impl EsSink {
+    pub fn start(url: &str, index: &str, resolver: Arc<Resolver>) -> Result<Self> {
+        let url = http::Url::parse(url)?;
+        let index = index.to_string();
+        let (tx, mut rx) = mpsc::channel::<Value>(BATCH * 4);
+        let handle = tokio::spawn(async move {
+            let mut stats = EsStats::default();
+            let mut batch = Vec::with_capacity(BATCH);
+            let mut deadline = Instant::now() + FLUSH_EVERY;
+            loop {
+                match timeout(deadline.saturating_duration_since(Instant::now()), rx.recv()).await {
+                    Ok(Some(doc)) => {
+                        batch.push(doc);
+                        if batch.len() < BATCH { continue; }
+                    }
+                    Ok(None) => break,
+                    Err(_) => {}
+                }
+                if !batch.is_empty() { flush(&resolver, &url, &index, std::mem::take(&mut batch), &mut stats).await; }
+                deadline = Instant::now() + FLUSH_EVERY;
+            }
+            if !batch.is_empty() { flush(&resolver, &url, &index, batch, &mut stats).await; }
+            stats
+        });
+        Ok(Self { tx, handle })
+    }
+
+    /// Queues one result, waiting while the queue is full.
+    pub async fn send(&self, doc: Value) {
+        let _ = self.tx.send(doc).await;
+    }
+
+    /// Flushes what's left and returns the totals.
+    pub async fn finish(self) -> EsStats {
+        drop(self.tx);
+        self.handle.await.unwrap_or_default()
+    }
+}
+
+fn bulk_body(index: &str, docs: &[Value]) -> Vec<u8> {
+    let action = serde_json::json!({ "index": { "_index": index } }).to_string();
+    let mut body = String::new();
+    for doc in docs {
+        body.push_str(&action);
+        body.push('\n');
+        body.push_str(&doc.to_string());
+        body.push('\n');
+    }
+    body.into_bytes()
+}
+
+/// Positions of bulk items worth retrying (throttled or server-side errors) and
+/// the number of items rejected for good.
+fn retryable_items(body: &[u8]) -> Option<(Vec<usize>, usize)> {
+    let resp: Value = serde_json::from_slice(body).ok()?;
+    let items = resp.get("items")?.as_array()?;
+    let mut retry = Vec::new();
+    let mut rejected = 0;
+    for (i, item) in items.iter().enumerate() {
+        let code = item.as_object().and_then(|o| o.values().next()).and_then(|r| r.get("status")).and_then(Value::as_u64).unwrap_or(0);
+        match code {
+            200..=299 => {}
+            429 | 500.. => retry.push(i),
+            _ => rejected += 1,
+        }
+    }
+    Some((retry, rejected))
+}
+
+async fn flush(resolver: &Resolver, url: &http::Url, index: &str, mut docs: Vec<Value>, stats: &mut EsStats) {
+    for attempt in 1..=ATTEMPTS {
+        let headers = vec![("Content-Type", "application/x-ndjson".to_string())];
+        let sent = timeout(REQUEST_TIMEOUT, http::fetch(resolver, url, "POST", "/_bulk", headers, &bulk_body(index, &docs))).await;
+        let outcome = match sent {
+            Ok(Ok(resp)) if (200..300).contains(&resp.status) => Some(retryable_items(&resp.body).unwrap_or_default()),
+            Ok(Ok(resp)) if resp.status == 429 || resp.status >= 500 => None,
+            Ok(Ok(resp)) => {
+                // Bad request, auth failure, missing index permissions: retrying won't help
+                warn!("Elasticsearch bulk request rejected with HTTP {}", resp.status);
+                stats.failed += docs.len() as u64;
+                return;
+            }
+            _ => None,
+        };
+        if let Some((retry, rejected)) = outcome {
+            stats.failed += rejected as u64;
+            stats.indexed += docs.len().saturating_sub(retry.len() + rejected) as u64;
+            if retry.is_empty() { return; }
+            docs = retry.into_iter().filter_map(|i| docs.get_mut(i).map(Value::take)).collect();
+        }
+        if attempt < ATTEMPTS { sleep(Duration::from_millis(500 << attempt)).await; }
+    }
+    stats.failed += docs.len() as u64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_bulk_body_and_retries() {
+        let body = bulk_body("pulsenet", &[serde_json::json!({ "ip": "10.0.0.5" })]);
+        assert_eq!(String::from_utf8(body).unwrap(), "{\"index\":{\"_index\":\"pulsenet\"}}\n{\"ip\":\"10.0.0.5\"}\n");
+        let resp = br#"{"errors":true,"items":[{"index":{"status":201}},{"index":{"status":429}},{"index":{"status":400}}]}"#;
+        assert_eq!(retryable_items(resp), Some((vec![1], 1)));
+        assert_eq!(retryable_items(b"not json"), None);
+    }
+}
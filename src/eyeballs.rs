@@ -0,0 +1,98 @@
+use crate::resolver::Resolver;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// RFC 8305 section 5: recommended wait before starting the next attempt.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// A target given by name, with every address it resolved to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Host {
+    pub name: String,
+    /// In connection order: IPv6 first, then alternating families
+    pub addrs: Vec<IpAddr>,
+}
+
+impl Host {
+    pub async fn resolve(name: &str, resolver: &Resolver) -> io::Result<Self> {
+        let mut addrs = resolver.lookup(name).await?;
+        let mut seen = std::collections::HashSet::new();
+        addrs.retain(|a| seen.insert(*a));
+        Ok(Self { name: name.to_string(), addrs: interleave(addrs) })
+    }
+
+    pub fn dual_stack(&self) -> bool {
+        self.addrs.iter().any(IpAddr::is_ipv4) && self.addrs.iter().any(IpAddr::is_ipv6)
+    }
+}
+
+/// RFC 8305 section 4: start with IPv6, then alternate address families,
+/// keeping the resolver's order within each family.
+fn interleave(addrs: Vec<IpAddr>) -> Vec<IpAddr> {
+    let (mut v6, mut v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(IpAddr::is_ipv6);
+    v6.reverse();
+    v4.reverse();
+    let mut out = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.pop(), v4.pop()) {
+            (None, None) => return out,
+            (a, b) => out.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+pub fn family(addr: IpAddr) -> &'static str {
+    if addr.is_ipv6() { "ipv6" } else { "ipv4" }
+}
+
+/// Happy Eyeballs: starts an attempt per address in order, the next one after
+/// `delay` or as soon as the previous one fails, and returns the first
+/// connection established along with the address that answered.
+pub async fn connect<F, Fut>(addrs: &[IpAddr], port: u16, connect: F, delay: Duration) -> io::Result<(TcpStream, IpAddr)>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let attempt = |addr: IpAddr| {
+        let connect = &connect;
+        async move { (addr, connect(SocketAddr::new(addr, port)).await) }
+    };
+    let mut pending = addrs.iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = io::Error::new(io::ErrorKind::AddrNotAvailable, "no addresses to connect to");
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(&addr) => attempts.push(attempt(addr)),
+                None => return Err(last_error),
+            }
+        }
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok((stream, addr)),
+                // A failure starts the next attempt right away
+                Err(e) => last_error = e,
+            },
+            _ = tokio::time::sleep(delay), if pending.len() > 0 => {
+                if let Some(&addr) = pending.next() { attempts.push(attempt(addr)); }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_interleave() {
+        let a = |s: &str| s.parse::<IpAddr>().unwrap();
+        let order = interleave(vec![a("192.0.2.1"), a("192.0.2.2"), a("2001:db8::1"), a("192.0.2.3"), a("2001:db8::2")]);
+        assert_eq!(order, [a("2001:db8::1"), a("192.0.2.1"), a("2001:db8::2"), a("192.0.2.2"), a("192.0.2.3")]);
+        let host = Host { name: "x".into(), addrs: order };
+        assert!(host.dual_stack() && family(host.addrs[0]) == "ipv6");
+    }
+}
@@ -0,0 +1,171 @@
+use crate::http;
+use crate::resolver::Resolver;
+use anyhow::{bail, Context, Result};
+use ipnet::Ipv4Net;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+/// Special-purpose ranges shipped with the binary, used unless --bogons points elsewhere.
+const DEFAULT_BOGONS: &str = include_str!("bogons.txt");
+/// Team Cymru's full bogons: special-purpose plus unallocated space, refreshed as RIRs allocate.
+const FULLBOGONS_URL: &str = "https://www.team-cymru.org/Services/Bogons/fullbogons-ipv4.txt";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Reserved,
+    Private,
+    Global,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    net: Ipv4Net,
+    kind: Kind,
+    name: String,
+}
+
+/// Decides which addresses are worth probing from a special-purpose table.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    /// Most specific first, so the first match decides
+    entries: Vec<Entry>,
+    allow_private: bool,
+}
+
+impl Filter {
+    pub fn load(path: Option<&str>, allow_private: bool) -> Result<Self> {
+        let entries = match path {
+            Some(p) => {
+                let content = std::fs::read_to_string(p).with_context(|| format!("failed to read bogons file '{}'", p))?;
+                parse(&content).with_context(|| format!("invalid bogons file '{}'", p))?
+            }
+            None => parse(DEFAULT_BOGONS).context("invalid built-in bogons")?,
+        };
+        Ok(Self { entries, allow_private })
+    }
+
+    /// The special range `ip` falls in, with its kind; None for ordinary unicast.
+    pub fn classify(&self, ip: Ipv4Addr) -> Option<(Kind, &str)> {
+        self.entries.iter().find(|e| e.net.contains(&ip)).map(|e| (e.kind, e.name.as_str()))
+    }
+
+    pub fn allows(&self, ip: Ipv4Addr) -> bool {
+        match self.classify(ip) {
+            None | Some((Kind::Global, _)) => true,
+            Some((Kind::Private, _)) => self.allow_private,
+            Some((Kind::Reserved, _)) => false,
+        }
+    }
+
+    /// Like `allows`, for any address a name resolved to. The table is IPv4
+    /// only, so IPv6 is checked against the well-known special ranges.
+    pub fn allows_addr(&self, ip: IpAddr) -> bool {
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => self.allows(v4),
+            IpAddr::V6(v6) => {
+                let segments = v6.segments();
+                // Unique local fc00::/7 and link-local fe80::/10
+                let private = segments[0] & 0xfe00 == 0xfc00 || segments[0] & 0xffc0 == 0xfe80;
+                // Documentation 2001:db8::/32
+                let reserved = v6.is_unspecified() || v6.is_loopback() || v6.is_multicast() || segments[..2] == [0x2001, 0x0db8];
+                !reserved && (!private || self.allow_private)
+            }
+        }
+    }
+}
+
+/// Where downloaded tables are kept: `$XDG_CACHE_HOME/pulsenet`,
+/// `~/.cache/pulsenet` or `%LOCALAPPDATA%\pulsenet`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("pulsenet"))
+}
+
+/// Where --update-bogons keeps its table.
+pub fn cache_file() -> Option<PathBuf> {
+    Some(cache_dir()?.join("bogons.txt"))
+}
+
+/// Downloads the full bogons list and stores it, merged with the built-in
+/// table, in the cache. Returns the file and the number of unallocated ranges.
+pub async fn update_cache(resolver: &Resolver) -> Result<(PathBuf, usize)> {
+    let path = cache_file().context("no cache directory (set XDG_CACHE_HOME or HOME)")?;
+    let resp = http::fetch(resolver, &http::Url::parse(FULLBOGONS_URL)?, "GET", "", Vec::new(), &[]).await
+        .context("failed to download the full bogons list")?;
+    if resp.status != 200 { bail!("full bogons download failed with HTTP {}", resp.status); }
+    let (table, added) = merge_fullbogons(&String::from_utf8_lossy(&resp.body))?;
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir).with_context(|| format!("failed to create '{}'", dir.display()))?; }
+    std::fs::write(&path, table).with_context(|| format!("failed to write '{}'", path.display()))?;
+    Ok((path, added))
+}
+
+/// The built-in table plus every full-bogons prefix that doesn't overlap it,
+/// so private and globally reachable entries keep their meaning.
+fn merge_fullbogons(body: &str) -> Result<(String, usize)> {
+    let builtin = parse(DEFAULT_BOGONS)?;
+    let mut table = DEFAULT_BOGONS.to_string();
+    table.push_str(&format!("# Unallocated space from {}, fetched {}\n", FULLBOGONS_URL, chrono::Local::now().format("%Y-%m-%d")));
+    let mut added = 0;
+    for line in body.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let net: Ipv4Net = line.parse().with_context(|| format!("unexpected line '{}' in the full bogons list", line))?;
+        if builtin.iter().any(|e| e.net.contains(&net.network()) || net.contains(&e.net.network())) { continue; }
+        table.push_str(&format!("{:<19} reserved  Unallocated\n", net));
+        added += 1;
+    }
+    if added == 0 { bail!("the full bogons list has no unallocated ranges, refusing to replace the table"); }
+    Ok((table, added))
+}
+
+/// `<cidr> <reserved|private|global> <name>` per line, `#` comments.
+fn parse(content: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() { continue; }
+        let (net, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (kind, name) = rest.trim_start().split_once(char::is_whitespace).unwrap_or((rest.trim_start(), ""));
+        let net = net.parse::<Ipv4Net>().with_context(|| format!("line {}: invalid CIDR '{}'", n + 1, net))?.trunc();
+        let kind = match kind {
+            "reserved" => Kind::Reserved,
+            "private" => Kind::Private,
+            "global" => Kind::Global,
+            other => bail!("line {}: unknown kind '{}', expected reserved, private or global", n + 1, other),
+        };
+        entries.push(Entry { net, kind, name: name.trim().to_string() });
+    }
+    entries.sort_by_key(|e| std::cmp::Reverse(e.net.prefix_len()));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_special_purpose_table() {
+        let filter = Filter::load(None, false).unwrap();
+        assert_eq!(filter.classify(Ipv4Addr::new(198, 19, 255, 1)), Some((Kind::Reserved, "Benchmarking")));
+        // Globally reachable carve-outs of 192.0.0.0/24
+        assert!(filter.allows(Ipv4Addr::new(192, 0, 0, 9)) && !filter.allows(Ipv4Addr::new(192, 0, 0, 11)));
+        assert!(!filter.allows(Ipv4Addr::new(240, 0, 0, 1)) && !filter.allows(Ipv4Addr::new(0, 1, 2, 3)));
+        assert!(!filter.allows(Ipv4Addr::new(10, 1, 2, 3)) && filter.allows(Ipv4Addr::new(1, 1, 1, 1)));
+        let internal = Filter::load(None, true).unwrap();
+        assert!(internal.allows(Ipv4Addr::new(10, 1, 2, 3)) && !internal.allows(Ipv4Addr::new(127, 0, 0, 1)));
+        assert!(parse("10.0.0.0/8 internal x").is_err());
+        assert!(filter.allows_addr("2606:4700::1111".parse().unwrap()) && !filter.allows_addr("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!filter.allows_addr("fd00::1".parse().unwrap()) && internal.allows_addr("fe80::1".parse().unwrap()));
+        assert!(!internal.allows_addr("::1".parse().unwrap()) && !internal.allows_addr("2001:db8::1".parse().unwrap()));
+    }
+    #[test]
+    fn test_merge_fullbogons() {
+        let body = "# last updated 1760500000 (Wed Oct 15 03:46:40 2026 GMT)\n0.0.0.0/8\n10.0.0.0/8\n41.62.0.0/16\n192.0.0.0/24\n";
+        let (table, added) = merge_fullbogons(body).unwrap();
+        assert_eq!(added, 1);
+        let entries = parse(&table).unwrap();
+        let filter = Filter { entries, allow_private: true };
+        assert_eq!(filter.classify(Ipv4Addr::new(41, 62, 1, 1)), Some((Kind::Reserved, "Unallocated")));
+        assert!(filter.allows(Ipv4Addr::new(10, 1, 1, 1)) && filter.allows(Ipv4Addr::new(192, 0, 0, 9)));
+        assert!(merge_fullbogons("10.0.0.0/8\n").is_err());
+    }
+}
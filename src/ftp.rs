@@ -0,0 +1,68 @@
+use serde::Serialize;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::timeout;
+
+/// Result of the anonymous FTP login attempt.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FtpInfo {
+    pub banner: String,
+    pub anonymous_login: bool,
+}
+
+/// Reads the greeting and tries `anonymous` with an e-mail style password.
+/// Logs out right after, nothing is listed or transferred.
+pub async fn check_anonymous<S: AsyncRead + AsyncWrite + Unpin>(stream: S, wait: Duration) -> io::Result<FtpInfo> {
+    let mut stream = BufReader::new(stream);
+    let (code, banner) = timeout(wait, read_reply(&mut stream)).await.map_err(|_| io::ErrorKind::TimedOut)??;
+    if code != 220 { return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected FTP greeting {}", code))); }
+
+    let mut anonymous_login = false;
+    if let Ok(Ok((code, _))) = timeout(wait, command(&mut stream, "USER anonymous")).await {
+        anonymous_login = match code {
+            230 => true, // no password needed
+            331 => matches!(timeout(wait, command(&mut stream, "PASS pulsenet@example.com")).await, Ok(Ok((230, _)))),
+            _ => false,
+        };
+    }
+    let _ = stream.get_mut().write_all(b"QUIT\r\n").await;
+    Ok(FtpInfo { banner, anonymous_login })
+}
+
+async fn command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>, cmd: &str) -> io::Result<(u16, String)> {
+    stream.get_mut().write_all(format!("{}\r\n", cmd).as_bytes()).await?;
+    read_reply(stream).await
+}
+
+/// Reads one reply, following `123-` continuation lines up to the final `123 ` line.
+async fn read_reply<R: AsyncBufReadExt + Unpin>(r: &mut R) -> io::Result<(u16, String)> {
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        let mut raw = Vec::new();
+        if (&mut *r).take(2048).read_until(b'\n', &mut raw).await? == 0 { break; }
+        let line = String::from_utf8_lossy(&raw).trim_end().to_string();
+        let code = line.get(..3).and_then(|c| c.parse::<u16>().ok());
+        let last = code.is_some() && line.as_bytes().get(3) != Some(&b'-');
+        lines.push(line);
+        if last {
+            let code = code.unwrap_or(0);
+            let text = lines.iter().map(|l| l.get(4..).unwrap_or("").trim()).collect::<Vec<_>>().join(" ");
+            return Ok((code, text.trim().to_string()));
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete FTP reply"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_multiline_reply() {
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let mut input: &[u8] = b"220-Welcome to ProFTPD\r\n220-Authorized use only\r\n220 Ready\r\n331 Guest login ok\r\n";
+        let (code, text) = rt.block_on(read_reply(&mut input)).unwrap();
+        assert_eq!((code, text.as_str()), (220, "Welcome to ProFTPD Authorized use only Ready"));
+        assert_eq!(rt.block_on(read_reply(&mut input)).unwrap().0, 331);
+    }
+}
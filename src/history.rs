@@ -0,0 +1,236 @@
+use crate::{pg, report};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use rusqlite::{params, Connection, OpenFlags};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::net::Ipv4Addr;
+
+/// Every observation is kept (no upserts), so the database is the history.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    config TEXT NOT NULL,
+    hits INTEGER
+);
+CREATE TABLE IF NOT EXISTS observations (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    seen_at TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    port INTEGER,
+    state TEXT,
+    latency_ms INTEGER,
+    details TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS observations_ip ON observations (ip, seen_at);
+CREATE INDEX IF NOT EXISTS observations_run ON observations (run_id);";
+
+/// `sqlite://results.db` (relative) or `sqlite:///var/lib/pulsenet.db`.
+pub fn sqlite_path(s: &str) -> Option<&str> {
+    s.strip_prefix("sqlite://").filter(|p| !p.is_empty())
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Results backend for `--output sqlite://...`.
+pub struct SqliteSink {
+    conn: Connection,
+    run_id: i64,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database and registers a run.
+    pub fn open(path: &str, config: &Value) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("failed to open SQLite database '{}'", path))?;
+        // One fsync per hit would dominate fast scans
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        conn.execute_batch(SCHEMA).context("failed to create the PulseNet schema")?;
+        conn.execute("INSERT INTO runs (started_at, config) VALUES (?1, ?2)", params![now(), config.to_string()])?;
+        Ok(Self { run_id: conn.last_insert_rowid(), conn })
+    }
+
+    /// One row per probed port of a hit, or a single port-less row (ARP).
+    pub fn record(&self, ip: Ipv4Addr, result: &Value) -> Result<()> {
+        let (seen_at, ip, details) = (now(), ip.to_string(), pg::host_details(result).to_string());
+        let rows = pg::port_rows(result);
+        if rows.is_empty() {
+            self.conn.execute("INSERT INTO observations (run_id, seen_at, ip, details) VALUES (?1, ?2, ?3, ?4)",
+                params![self.run_id, seen_at, ip, details])?;
+        }
+        for (port, state, latency) in rows {
+            self.conn.execute(
+                "INSERT INTO observations (run_id, seen_at, ip, port, state, latency_ms, details) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![self.run_id, seen_at, ip, port, state, latency, details],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self, hits: u32) -> Result<()> {
+        self.conn.execute("UPDATE runs SET finished_at = ?2, hits = ?3 WHERE id = ?1", params![self.run_id, now(), hits])?;
+        Ok(())
+    }
+}
+
+fn open_existing(path: &str) -> Result<Connection> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open SQLite database '{}'", path))
+}
+
+/// Addresses already seen, for --known: every host in a `sqlite://` history
+/// database, or the leading address of each line of a found IPs file (any
+/// --found-format) or results log (text, JSON, MessagePack or CBOR).
+pub fn load_known(spec: &str) -> Result<HashSet<Ipv4Addr>> {
+    if let Some(path) = sqlite_path(spec) {
+        let conn = open_existing(path)?;
+        let mut stmt = conn.prepare("SELECT DISTINCT ip FROM observations")?;
+        let ips = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        return Ok(ips.iter().filter_map(|ip| ip.parse().ok()).collect());
+    }
+    let content = std::fs::read(spec).with_context(|| format!("failed to read known hosts '{}'", spec))?;
+    if let Some(format) = content.first().copied().and_then(crate::binary::Format::detect) {
+        let records = crate::binary::Records::new(&content[..], format).collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("'{}' is corrupt", spec))?;
+        return Ok(records.iter().filter_map(|r| r.get("ip")?.as_str()?.parse().ok()).collect());
+    }
+    Ok(String::from_utf8_lossy(&content).lines().filter_map(known_ip).collect())
+}
+
+fn known_ip(line: &str) -> Option<Ipv4Addr> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return serde_json::from_str::<Value>(line).ok()?.get("ip")?.as_str()?.parse().ok();
+    }
+    // Text log lines start with the timestamp: `[2026-01-01 10:00:00] 10.0.0.9, Ports: 80`
+    let line = if line.starts_with('[') { line.split_once("] ")?.1 } else { line };
+    line.split([',', ':', ' ', '\t']).next()?.parse().ok()
+}
+
+/// `pulsenet history <ip>`: every observation of a host, oldest first.
+pub fn print_history(path: &str, ip: Ipv4Addr) -> Result<()> {
+    let conn = open_existing(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT seen_at, run_id, port, state, latency_ms, details FROM observations WHERE ip = ?1 ORDER BY seen_at, port")?;
+    let rows = stmt.query_map(params![ip.to_string()], |r| Ok((
+        r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, Option<u16>>(2)?,
+        r.get::<_, Option<String>>(3)?, r.get::<_, Option<i64>>(4)?, r.get::<_, String>(5)?,
+    )))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    if rows.is_empty() { bail!("{} was never seen in '{}'", ip, path); }
+
+    println!("{} {}", "HISTORY".bright_white().bold(), ip.to_string().bright_white());
+    let mut last_details = String::new();
+    for (seen_at, run, port, state, latency, details) in &rows {
+        let port = port.map_or("-".to_string(), |p| p.to_string());
+        let state = state.as_deref().unwrap_or("up");
+        let colored_state = match state { "open" | "up" => state.green(), "closed" => state.red(), _ => state.yellow() };
+        let latency = latency.map(|l| format!("{}ms", l)).unwrap_or_default();
+        println!("  {} {:>6} {:>6} {:<14} {}", seen_at.bright_black(), format!("run {}", run), port, colored_state, latency.cyan());
+        // Probe details only when they change, they're the same for every port of a run
+        if *details != last_details && details != "{}" {
+            println!("  {:>35} {}", "", details.bright_black());
+            last_details = details.clone();
+        }
+    }
+    let first = &rows[0].0;
+    let last = &rows[rows.len() - 1].0;
+    println!("  {} observations between {} and {}", rows.len(), first, last);
+    Ok(())
+}
+
+/// Open ports per host for each of the last `limit` runs, oldest first.
+fn load_runs(conn: &Connection, limit: usize) -> Result<Vec<(i64, String, Vec<report::Host>)>> {
+    let mut stmt = conn.prepare("SELECT id, started_at FROM runs ORDER BY id DESC LIMIT ?1")?;
+    let mut runs: Vec<(i64, String)> = stmt.query_map(params![limit as i64], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    runs.reverse();
+    let mut obs = conn.prepare("SELECT ip, port, state FROM observations WHERE run_id = ?1")?;
+    runs.into_iter().map(|(id, started)| {
+        let mut hosts: BTreeMap<String, Vec<u16>> = BTreeMap::new();
+        for row in obs.query_map(params![id], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Option<u16>>(1)?, r.get::<_, Option<String>>(2)?)))? {
+            let (ip, port, state) = row?;
+            let ports = hosts.entry(ip).or_default();
+            if let Some(port) = port && state.as_deref() == Some("open") { ports.push(port); }
+        }
+        let hosts = hosts.into_iter().map(|(ip, ports)| report::Host { ip, ports, ..report::Host::default() }).collect();
+        Ok((id, started, hosts))
+    }).collect()
+}
+
+/// Per-run hits and changes against the run before, then how the open port
+/// counts moved across the window.
+pub fn trends_table(runs: &[(i64, String, Vec<report::Host>)]) -> Vec<String> {
+    let mut lines = vec![format!("{:>5}  {:<19} {:>6} {:>5} {:>5} {:>7} {:>7}", "RUN", "STARTED", "HOSTS", "NEW", "GONE", "OPENED", "CLOSED")];
+    for (i, (id, started, hosts)) in runs.iter().enumerate() {
+        let changes = i.checked_sub(1).map(|prev| report::diff(&runs[prev].2, hosts));
+        let count = |key: &str| changes.as_ref().map_or("-".to_string(), |c| {
+            let entries = c[key].as_array().map(Vec::as_slice).unwrap_or_default();
+            // Opened/closed count ports, not hosts
+            entries.iter().map(|e| e.get("ports").and_then(Value::as_array).map_or(1, Vec::len)).sum::<usize>().to_string()
+        });
+        lines.push(format!("{:>5}  {:<19} {:>6} {:>5} {:>5} {:>7} {:>7}", id, started, hosts.len(),
+            count("new_hosts"), count("gone_hosts"), count("opened"), count("closed")));
+    }
+    let per_port = |hosts: &[report::Host]| {
+        let mut counts: BTreeMap<u16, i64> = BTreeMap::new();
+        for port in hosts.iter().flat_map(|h| &h.ports) { *counts.entry(*port).or_default() += 1; }
+        counts
+    };
+    if let (Some(first), Some(last)) = (runs.first(), runs.last()) && runs.len() > 1 {
+        let (before, after) = (per_port(&first.2), per_port(&last.2));
+        let mut ports: Vec<u16> = before.keys().chain(after.keys()).copied().collect();
+        ports.sort_unstable();
+        ports.dedup();
+        lines.push(String::new());
+        lines.push(format!("{:>7} {:>7} {:>7}  (run {} -> run {})", "PORT", "OPEN", "CHANGE", first.0, last.0));
+        for port in ports {
+            let (was, now) = (before.get(&port).copied().unwrap_or(0), after.get(&port).copied().unwrap_or(0));
+            lines.push(format!("{:>7} {:>7} {:>+7}", port, now, now - was));
+        }
+    }
+    lines
+}
+
+/// `pulsenet trends`: estate-wide changes over the last runs.
+pub fn print_trends(path: &str, limit: usize) -> Result<()> {
+    let runs = load_runs(&open_existing(path)?, limit)?;
+    if runs.is_empty() { bail!("no runs recorded in '{}'", path); }
+    println!("{}", "TRENDS".bright_white().bold());
+    for line in trends_table(&runs) { println!("  {}", line); }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_record_and_trends() {
+        let path = std::env::temp_dir().join(format!("pulsenet-history-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        for ports in [vec![22, 80], vec![80, 443]] {
+            let sink = SqliteSink::open(path, &serde_json::json!({})).unwrap();
+            let rows: Vec<Value> = ports.iter().map(|p| serde_json::json!({ "port": p, "state": "open", "latency_ms": 3 })).collect();
+            sink.record(Ipv4Addr::new(10, 0, 0, 5), &serde_json::json!({ "ip": "10.0.0.5", "ports": rows })).unwrap();
+            sink.finish(1).unwrap();
+        }
+        let runs = load_runs(&open_existing(path).unwrap(), 10).unwrap();
+        assert_eq!(load_known(&format!("sqlite://{}", path)).unwrap(), HashSet::from([Ipv4Addr::new(10, 0, 0, 5)]));
+        for suffix in ["", "-wal", "-shm"] { let _ = std::fs::remove_file(format!("{}{}", path, suffix)); }
+        assert_eq!(runs.iter().map(|r| r.2[0].ports.clone()).collect::<Vec<_>>(), [vec![22, 80], vec![80, 443]]);
+        let table = trends_table(&runs);
+        assert_eq!(table[2].split_whitespace().skip(3).collect::<Vec<_>>(), ["1", "0", "0", "1", "1"]);
+        assert!(table.iter().any(|l| l.trim_start().starts_with("443") && l.trim_end().ends_with("+1")));
+        assert_eq!(sqlite_path("sqlite://scans.db"), Some("scans.db"));
+    }
+    #[test]
+    fn test_known_ip_formats() {
+        let lines = ["10.0.0.1", "10.0.0.2:443", "ip,port", "10.0.0.3,22", "[2026-01-01 10:00:00] 10.0.0.9, Ports: 80",
+            r#"{"timestamp":"...","ip":"10.0.0.4","ports":[]}"#];
+        let found: Vec<Option<Ipv4Addr>> = lines.iter().map(|l| known_ip(l)).collect();
+        assert_eq!(found, [Some(Ipv4Addr::new(10, 0, 0, 1)), Some(Ipv4Addr::new(10, 0, 0, 2)), None,
+            Some(Ipv4Addr::new(10, 0, 0, 3)), Some(Ipv4Addr::new(10, 0, 0, 9)), Some(Ipv4Addr::new(10, 0, 0, 4))]);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::rawsock;
+use serde::Serialize;
+use socket2::Protocol;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const IPPROTO_TCP: u8 = 6;
+/// Unclaimed answers older than this are dropped when the table grows.
+const KEEP: Duration = Duration::from_secs(60);
+const MAX_PENDING: usize = 100_000;
+
+/// An ICMP destination-unreachable answer to one of our TCP probes.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Unreachable {
+    pub code: u8,
+    pub reason: &'static str,
+    /// Router or firewall that sent it
+    pub from: String,
+}
+
+/// RFC 792 / RFC 1812 names of the destination-unreachable codes.
+fn reason(code: u8) -> &'static str {
+    match code {
+        0 => "net unreachable",
+        1 => "host unreachable",
+        2 => "protocol unreachable",
+        3 => "port unreachable",
+        4 => "fragmentation needed",
+        5 => "source route failed",
+        6 => "destination network unknown",
+        7 => "destination host unknown",
+        9 => "network administratively prohibited",
+        10 => "host administratively prohibited",
+        11 => "network unreachable for TOS",
+        12 => "host unreachable for TOS",
+        13 => "communication administratively prohibited",
+        14 => "host precedence violation",
+        15 => "precedence cutoff",
+        _ => "unreachable",
+    }
+}
+
+/// Target (ip, port) and the answer, if `buf` is an unreachable quoting a TCP segment.
+fn parse(buf: &[u8]) -> Option<((Ipv4Addr, u16), Unreachable)> {
+    let outer = rawsock::parse_ipv4(buf)?;
+    let icmp = outer.payload;
+    if icmp.len() < 8 || icmp[0] != ICMP_DEST_UNREACHABLE { return None; }
+    // The original IP header plus at least the first 8 bytes of its payload
+    let inner = rawsock::parse_ipv4(&icmp[8..])?;
+    if inner.protocol != IPPROTO_TCP || inner.payload.len() < 4 { return None; }
+    let port = u16::from_be_bytes([inner.payload[2], inner.payload[3]]);
+    Some(((inner.dst, port), Unreachable { code: icmp[1], reason: reason(icmp[1]), from: outer.src.to_string() }))
+}
+
+type Pending = HashMap<(Ipv4Addr, u16), (Unreachable, Instant)>;
+
+/// Reads every ICMP unreachable the host receives on a raw socket so probes
+/// that failed can tell a firewall's answer apart from silence.
+pub struct Watcher {
+    pending: Arc<Mutex<Pending>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    /// Needs raw socket privileges.
+    pub fn start() -> io::Result<Self> {
+        let sock = rawsock::open_raw(Protocol::ICMPV4)?;
+        // Lets the thread notice `stop` when the network is quiet
+        sock.set_read_timeout(Some(Duration::from_millis(500)))?;
+        let pending: Arc<Mutex<Pending>> = Arc::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (table, done) = (pending.clone(), stop.clone());
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 1500];
+            while !done.load(Ordering::Relaxed) {
+                let n = match (&sock).read(&mut buf) {
+                    Ok(n) => n,
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+                    Err(_) => break,
+                };
+                let Some((key, answer)) = parse(&buf[..n]) else { continue };
+                let mut table = table.lock().unwrap_or_else(|e| e.into_inner());
+                if table.len() >= MAX_PENDING { table.retain(|_, (_, at)| at.elapsed() < KEEP); }
+                table.insert(key, (answer, Instant::now()));
+            }
+        });
+        Ok(Self { pending, stop })
+    }
+
+    /// The unreachable received for a probe of `ip:port`, if any.
+    pub fn take(&self, ip: Ipv4Addr, port: u16) -> Option<Unreachable> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner()).remove(&(ip, port)).map(|(answer, _)| answer)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_unreachable() {
+        // From 192.0.2.1: type 3 code 13, quoting 10.0.0.1:40000 -> 203.0.113.5:443
+        let mut inner = vec![0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, IPPROTO_TCP, 0, 0, 10, 0, 0, 1, 203, 0, 113, 5];
+        inner.extend_from_slice(&[0x9c, 0x40, 0x01, 0xbb, 0, 0, 0, 1]);
+        let mut icmp = vec![ICMP_DEST_UNREACHABLE, 13, 0, 0, 0, 0, 0, 0];
+        icmp.extend_from_slice(&inner);
+        let mut pkt = vec![0x45, 0, 0, (20 + icmp.len()) as u8, 0, 0, 0, 0, 64, 1, 0, 0, 192, 0, 2, 1, 10, 0, 0, 1];
+        pkt.extend_from_slice(&icmp);
+        let (key, answer) = parse(&pkt).unwrap();
+        assert_eq!(key, (Ipv4Addr::new(203, 0, 113, 5), 443));
+        assert_eq!((answer.code, answer.reason, answer.from.as_str()), (13, "communication administratively prohibited", "192.0.2.1"));
+        pkt[20] = 11; // time exceeded
+        assert!(parse(&pkt).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+
+/// Endpoints extracted from a third-party export, grouped per host.
+pub type Endpoints = BTreeMap<Ipv4Addr, Vec<u16>>;
+
+/// Loads ip:port pairs from a Shodan or Censys export. CSV files are read by
+/// header (an `ip` column and any `*port*` column); everything else is treated
+/// as JSON, either one document per line (Shodan `--download` banners, Censys
+/// host dumps) or a single array.
+pub fn load_export(path: &str) -> Result<Endpoints> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read import file '{}'", path))?;
+    let endpoints = if path.to_ascii_lowercase().ends_with(".csv") { parse_csv(&content)? } else { parse_json(&content)? };
+    if endpoints.is_empty() { bail!("no IPv4 endpoints found in '{}'", path); }
+    Ok(endpoints)
+}
+
+fn add(endpoints: &mut Endpoints, ip: Ipv4Addr, port: u16) {
+    let ports = endpoints.entry(ip).or_default();
+    if !ports.contains(&port) { ports.push(port); }
+}
+
+fn parse_json(content: &str) -> Result<Endpoints> {
+    let mut endpoints = Endpoints::new();
+    let trimmed = content.trim_start();
+    let docs: Vec<Value> = if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed).context("invalid JSON export")?
+    } else {
+        trimmed.lines().filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>().context("invalid NDJSON export")?
+    };
+    for doc in &docs { collect_json(doc, &mut endpoints); }
+    Ok(endpoints)
+}
+
+fn collect_json(doc: &Value, endpoints: &mut Endpoints) {
+    // Shodan uses ip_str, Censys ip (v2 API nests hosts under "host")
+    let ip = ["ip_str", "ip"].iter()
+        .find_map(|k| doc.get(k).and_then(Value::as_str))
+        .and_then(|s| s.parse::<Ipv4Addr>().ok());
+    let Some(ip) = ip else {
+        if let Some(host) = doc.get("host") { collect_json(host, endpoints); }
+        return;
+    };
+    if let Some(port) = doc.get("port").and_then(as_port) { add(endpoints, ip, port); }
+    // Shodan host lookups and Censys hosts list services/data entries
+    for key in ["services", "data"] {
+        for svc in doc.get(key).and_then(Value::as_array).into_iter().flatten() {
+            if let Some(port) = svc.get("port").and_then(as_port) { add(endpoints, ip, port); }
+        }
+    }
+    for port in doc.get("ports").and_then(Value::as_array).into_iter().flatten().filter_map(as_port) {
+        add(endpoints, ip, port);
+    }
+}
+
+fn as_port(v: &Value) -> Option<u16> {
+    v.as_u64().and_then(|p| u16::try_from(p).ok())
+        .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+fn parse_csv(content: &str) -> Result<Endpoints> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let header = split_csv_line(lines.next().unwrap_or_default());
+    let col = |pred: &dyn Fn(&str) -> bool| header.iter().position(|h| pred(&h.to_ascii_lowercase()));
+    let ip_col = col(&|h| h == "ip" || h == "ip_str" || h.ends_with(".ip"))
+        .context("CSV export has no 'ip' column")?;
+    let port_col = col(&|h| h.contains("port")).context("CSV export has no port column")?;
+
+    let mut endpoints = Endpoints::new();
+    for line in lines {
+        let fields = split_csv_line(line);
+        let Some(ip) = fields.get(ip_col).and_then(|s| s.trim().parse::<Ipv4Addr>().ok()) else { continue };
+        // Censys flattens repeated fields into one cell: "80, 443" or "[80, 443]"
+        let cell = fields.get(port_col).map(String::as_str).unwrap_or("");
+        for port in cell.split([',', ';', '|', ' ', '[', ']']).filter_map(|p| p.trim().parse::<u16>().ok()) {
+            add(&mut endpoints, ip, port);
+        }
+    }
+    Ok(endpoints)
+}
+
+/// Splits one CSV record, honoring double-quoted fields and `""` escapes.
+pub fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut cur = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => { cur.push('"'); chars.next(); }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut cur)),
+            _ => cur.push(c),
+        }
+    }
+    fields.push(cur);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_shodan_ndjson() {
+        let data = r#"{"ip_str":"1.2.3.4","port":443,"data":"..."}
+{"ip_str":"1.2.3.4","port":8443}
+{"ip_str":"2001:db8::1","port":80}"#;
+        let eps = parse_json(data).unwrap();
+        assert_eq!(eps.len(), 1);
+        assert_eq!(eps[&Ipv4Addr::new(1, 2, 3, 4)], vec![443, 8443]);
+    }
+    #[test]
+    fn test_censys_json_and_csv() {
+        let json = r#"[{"ip":"5.6.7.8","services":[{"port":22},{"port":"80"}]}]"#;
+        assert_eq!(parse_json(json).unwrap()[&Ipv4Addr::new(5, 6, 7, 8)], vec![22, 80]);
+        let csv = "ip,services.port,location.country\n5.6.7.8,\"22, 443\",FI\nbad,80,FI\n";
+        assert_eq!(parse_csv(csv).unwrap()[&Ipv4Addr::new(5, 6, 7, 8)], vec![22, 443]);
+    }
+}
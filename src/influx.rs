@@ -0,0 +1,56 @@
+use crate::http;
+use crate::resolver::Resolver;
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+
+/// A line protocol field value.
+pub enum Field {
+    Int(u64),
+    Float(f64),
+}
+
+fn escape_tag(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// `measurement,tag=v field=1i,other=2.5 <ns timestamp>`
+pub fn line(measurement: &str, tags: &[(&str, String)], fields: &[(&str, Field)], timestamp_ns: i64) -> String {
+    let tags: String = tags.iter().filter(|(_, v)| !v.is_empty())
+        .map(|(k, v)| format!(",{}={}", k, escape_tag(v))).collect();
+    let fields = fields.iter().map(|(k, v)| match v {
+        Field::Int(i) => format!("{}={}i", k, i),
+        Field::Float(f) => format!("{}={}", k, f),
+    }).collect::<Vec<_>>().join(",");
+    format!("{}{} {} {}", measurement.replace([',', ' '], "_"), tags, fields, timestamp_ns)
+}
+
+/// Writes line protocol to an InfluxDB write endpoint (v2 `/api/v2/write?org=..&bucket=..`
+/// with a token, or v1 `/write?db=..` with credentials in the URL), or appends it
+/// to a file for Telegraf and friends when `dest` isn't an http(s) URL.
+pub async fn write(resolver: &Resolver, dest: &str, token: Option<&str>, lines: &str) -> Result<()> {
+    if !dest.starts_with("http://") && !dest.starts_with("https://") {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(dest)
+            .with_context(|| format!("failed to open '{}'", dest))?;
+        return Ok(file.write_all(lines.as_bytes())?);
+    }
+    let url = http::Url::parse(dest)?;
+    let mut headers = vec![("Content-Type", "text/plain; charset=utf-8".to_string())];
+    if let Some(token) = token { headers.push(("Authorization", format!("Token {}", token))); }
+    let resp = http::fetch(resolver, &url, "POST", "", headers, lines.as_bytes()).await
+        .with_context(|| format!("failed to reach InfluxDB at {}:{}", url.host, url.port))?;
+    if !(200..300).contains(&resp.status) {
+        bail!("InfluxDB write failed with HTTP {}: {}", resp.status, String::from_utf8_lossy(&resp.body).trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_line_protocol() {
+        let l = line("pulsenet_scan", &[("target", "10.0.0.0/24,192.168.1.0/24".into()), ("ports", String::new())],
+            &[("hits", Field::Int(3)), ("latency_avg_ms", Field::Float(12.5))], 1_700_000_000_000_000_000);
+        assert_eq!(l, r"pulsenet_scan,target=10.0.0.0/24\,192.168.1.0/24 hits=3i,latency_avg_ms=12.5 1700000000000000000");
+    }
+}
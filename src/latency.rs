@@ -0,0 +1,69 @@
+/// Latencies above this land in the last slot.
+const MAX_MS: usize = 10_000;
+/// Upper edges (exclusive, ms) of the ranges shown in the summary histogram.
+const DISPLAY_EDGES: [u64; 10] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, u64::MAX];
+
+/// Per-millisecond hit latency counts: exact percentiles in constant memory.
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self { counts: vec![0; MAX_MS + 1], total: 0 }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, ms: u128) {
+        self.counts[(ms as usize).min(MAX_MS)] += 1;
+        self.total += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Smallest latency that at least `p` percent of samples don't exceed.
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        if self.total == 0 { return None; }
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.counts.iter().position(|&c| { seen += c; seen >= rank }).map(|ms| ms as u64)
+    }
+
+    /// (label, count) per display range, trimmed to the populated span.
+    pub fn buckets(&self) -> Vec<(String, u64)> {
+        let mut start = 0;
+        let mut buckets: Vec<(String, u64)> = DISPLAY_EDGES.iter().map(|&end| {
+            let upto = (end.min(MAX_MS as u64 + 1)) as usize;
+            let count = self.counts[start..upto].iter().sum();
+            let label = if end == u64::MAX { format!(">={}ms", start) } else { format!("{}-{}ms", start, end - 1) };
+            start = upto;
+            (label, count)
+        }).collect();
+        let last = buckets.iter().rposition(|b| b.1 > 0).map_or(0, |i| i + 1);
+        buckets.truncate(last);
+        let first = buckets.iter().position(|b| b.1 > 0).unwrap_or(0);
+        buckets.drain(..first);
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_percentiles_and_buckets() {
+        let mut hist = Histogram::default();
+        assert_eq!(hist.percentile(50.0), None);
+        for ms in 1..=100 { hist.record(ms); }
+        hist.record(60_000);
+        assert_eq!((hist.percentile(50.0), hist.percentile(99.0), hist.percentile(100.0)), (Some(51), Some(100), Some(MAX_MS as u64)));
+        let buckets = hist.buckets();
+        assert_eq!(buckets.first(), Some(&("0-4ms".to_string(), 4)));
+        assert_eq!(buckets.last(), Some(&(">=2500ms".to_string(), 1)));
+        assert_eq!(buckets.iter().map(|b| b.1).sum::<u64>(), 101);
+    }
+}
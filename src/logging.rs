@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use colored::{ColoredString, Colorize};
+use std::fmt;
+use std::io::IsTerminal;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Console diagnostics in the same style as the rest of the UI: `⚠ message`,
+/// with the module and span fields only shown for debug and trace events.
+struct Console;
+
+impl<S, N> FormatEvent<S, N> for Console
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let meta = event.metadata();
+        // colored follows stdout; stderr may be redirected on its own
+        let ansi = writer.has_ansi_escapes();
+        let paint = |s: ColoredString| if ansi { s } else { s.clear() };
+        match *meta.level() {
+            Level::ERROR => write!(writer, "{} ", paint("✖".red()))?,
+            Level::WARN => write!(writer, "{} ", paint("⚠".yellow()))?,
+            Level::INFO => write!(writer, "{} ", paint("ℹ".blue()))?,
+            _ => {
+                write!(writer, "{} ", paint("·".bright_black()))?;
+                // Events from main carry the bare crate name
+                if let Some(module) = meta.target().strip_prefix("PulseNet::") { write!(writer, "{} ", paint(module.bright_black()))?; }
+                if let Some(scope) = ctx.event_scope() {
+                    for span in scope.from_root() { write!(writer, "{} ", paint(span.name().bright_black()))?; }
+                }
+            }
+        }
+        ctx.format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Routes diagnostics to stderr. `level` is a level name (`warn`, `debug`)
+/// or full filter directives (`warn,PulseNet::tls=trace`); `json` emits one
+/// JSON object per event, with the current spans, for log pipelines.
+/// Escapes are only written to a terminal, and never without `color`.
+pub fn init(level: &str, json: bool, color: bool) -> Result<()> {
+    let filter = EnvFilter::try_new(level).with_context(|| format!("invalid --log-level '{}'", level))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr)
+        .with_ansi(color && std::io::stderr().is_terminal());
+    if json {
+        builder.json().with_current_span(true).with_span_list(false).init();
+    } else {
+        builder.event_format(Console).init();
+    }
+    Ok(())
+}
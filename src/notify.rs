@@ -0,0 +1,81 @@
+use std::io::Write;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tracing::debug;
+
+/// Hits within this long of the last notification are folded into the next one.
+const QUIET: Duration = Duration::from_secs(10);
+
+/// Terminal bell plus a desktop notification (notify-send, or osascript on
+/// macOS) for --notify. A burst of hits raises one notification rather than
+/// one per host; the bell alone is all there is where neither tool exists.
+pub struct Notifier {
+    last: Option<Instant>,
+    pending: usize,
+    latest: String,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self { last: None, pending: 0, latest: String::new() }
+    }
+
+    /// `endpoint` is the plain `ip:ports` of the hit.
+    pub fn hit(&mut self, endpoint: &str) {
+        self.pending += 1;
+        self.latest = endpoint.to_string();
+        if self.last.is_none_or(|t| t.elapsed() >= QUIET) {
+            bell();
+            desktop("PulseNet: hit found", &hits_message(self.pending, &self.latest));
+            self.pending = 0;
+            self.last = Some(Instant::now());
+        }
+    }
+
+    /// Hits still held back are covered by the totals here.
+    pub fn done(self, found: u32, scanned: u32) {
+        bell();
+        desktop("PulseNet: scan complete", &format!("{} hits from {} targets", found, scanned));
+    }
+}
+
+fn hits_message(count: usize, latest: &str) -> String {
+    match count {
+        1 => latest.to_string(),
+        n => format!("{} new hits, latest {}", n, latest),
+    }
+}
+
+fn bell() {
+    let mut err = std::io::stderr();
+    let _ = err.write_all(b"\x07").and_then(|_| err.flush());
+}
+
+/// Fire and forget; the runtime reaps the child.
+fn desktop(title: &str, body: &str) {
+    let mut cmd = if cfg!(target_os = "macos") {
+        let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut cmd = Command::new("osascript");
+        cmd.arg("-e").arg(format!("display notification \"{}\" with title \"{}\"", quote(body), quote(title)));
+        cmd
+    } else if cfg!(unix) {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["-a", "PulseNet", title, body]);
+        cmd
+    } else {
+        return;
+    };
+    cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    if let Err(e) = cmd.spawn() { debug!("desktop notification unavailable: {}", e); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_hits_message() {
+        assert_eq!(hits_message(1, "10.0.0.1:22"), "10.0.0.1:22");
+        assert_eq!(hits_message(4, "10.0.0.9:80,443"), "4 new hits, latest 10.0.0.9:80,443");
+    }
+}
@@ -0,0 +1,186 @@
+use crate::rawsock;
+use rand::Rng;
+use serde::Serialize;
+use socket2::{Protocol, SockAddr};
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const IPPROTO_TCP: u8 = 6;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const ACK: u8 = 0x10;
+
+/// TCP/IP stack traits of a SYN-ACK and the OS family they suggest.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OsGuess {
+    pub family: String,
+    pub ttl: u8,
+    pub initial_ttl: u8,
+    pub window: u16,
+    /// Option layout, e.g. `M1460,S,T,N,W7`
+    pub options: String,
+}
+
+/// Sends a raw SYN to an open port and fingerprints the SYN-ACK (TTL, window,
+/// option layout). The kernel answers the SYN-ACK with a RST since no socket
+/// owns the connection. Requires raw socket privileges.
+pub async fn fingerprint(ip: Ipv4Addr, port: u16, wait: Duration) -> io::Result<Option<OsGuess>> {
+    tokio::task::spawn_blocking(move || fingerprint_blocking(ip, port, wait))
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn fingerprint_blocking(ip: Ipv4Addr, port: u16, wait: Duration) -> io::Result<Option<OsGuess>> {
+    let src = rawsock::local_addr_for(ip)?;
+    let sock = rawsock::open_raw(Protocol::TCP)?;
+    let mut rng = rand::thread_rng();
+    let sport: u16 = rng.gen_range(32768..61000);
+    let syn = build_syn(src, ip, sport, port, rng.r#gen());
+    sock.send_to(&syn, &SockAddr::from(SocketAddr::new(ip.into(), 0)))?;
+
+    let deadline = Instant::now() + wait;
+    let mut buf = [0u8; 1500];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+        sock.set_read_timeout(Some(left))?;
+        let n = match (&sock).read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        let Some(pkt) = rawsock::parse_ipv4(&buf[..n]) else { continue };
+        if pkt.protocol != IPPROTO_TCP || pkt.src != ip || pkt.dst != src { continue; }
+        let Some(seg) = parse_tcp(pkt.payload) else { continue };
+        if seg.sport != port || seg.dport != sport { continue; }
+        if seg.flags & RST != 0 { return Ok(None); }
+        if seg.flags & (SYN | ACK) == SYN | ACK {
+            return Ok(Some(classify(pkt.ttl, seg.window, &seg.options)));
+        }
+    }
+    Ok(None)
+}
+
+/// SYN with a Linux-like option set so the peer echoes its full option layout.
+fn build_syn(src: Ipv4Addr, dst: Ipv4Addr, sport: u16, dport: u16, seq: u32) -> Vec<u8> {
+    let options: [u8; 20] = [
+        2, 4, 0x05, 0xb4,       // MSS 1460
+        4, 2,                   // SACK permitted
+        8, 10, 0, 0, 0, 1, 0, 0, 0, 0, // Timestamps
+        1,                      // NOP
+        3, 3, 7,                // Window scale 7
+    ];
+    let mut seg = Vec::with_capacity(40);
+    seg.extend_from_slice(&sport.to_be_bytes());
+    seg.extend_from_slice(&dport.to_be_bytes());
+    seg.extend_from_slice(&seq.to_be_bytes());
+    seg.extend_from_slice(&0u32.to_be_bytes());
+    seg.push(((20 + options.len()) / 4) as u8 * 16);
+    seg.push(SYN);
+    seg.extend_from_slice(&64240u16.to_be_bytes());
+    seg.extend_from_slice(&[0, 0, 0, 0]); // checksum, urgent pointer
+    seg.extend_from_slice(&options);
+    let sum = rawsock::transport_checksum(src, dst, IPPROTO_TCP, &seg);
+    seg[16..18].copy_from_slice(&sum.to_be_bytes());
+    seg
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TcpOpt { Eol, Nop, Mss(u16), WindowScale(u8), SackPermitted, Timestamp, Other(u8) }
+
+struct TcpSegment { sport: u16, dport: u16, flags: u8, window: u16, options: Vec<TcpOpt> }
+
+fn parse_tcp(seg: &[u8]) -> Option<TcpSegment> {
+    if seg.len() < 20 { return None; }
+    let data_off = (seg[12] >> 4) as usize * 4;
+    let opts = seg.get(20..data_off.min(seg.len()))?;
+    Some(TcpSegment {
+        sport: u16::from_be_bytes([seg[0], seg[1]]),
+        dport: u16::from_be_bytes([seg[2], seg[3]]),
+        flags: seg[13],
+        window: u16::from_be_bytes([seg[14], seg[15]]),
+        options: parse_options(opts),
+    })
+}
+
+fn parse_options(mut b: &[u8]) -> Vec<TcpOpt> {
+    let mut out = Vec::new();
+    while let Some(&kind) = b.first() {
+        match kind {
+            0 => { out.push(TcpOpt::Eol); break; }
+            1 => { out.push(TcpOpt::Nop); b = &b[1..]; continue; }
+            _ => {}
+        }
+        let len = *b.get(1).unwrap_or(&0) as usize;
+        if len < 2 || len > b.len() { break; }
+        out.push(match (kind, len) {
+            (2, 4) => TcpOpt::Mss(u16::from_be_bytes([b[2], b[3]])),
+            (3, 3) => TcpOpt::WindowScale(b[2]),
+            (4, 2) => TcpOpt::SackPermitted,
+            (8, 10) => TcpOpt::Timestamp,
+            _ => TcpOpt::Other(kind),
+        });
+        b = &b[len..];
+    }
+    out
+}
+
+fn layout(options: &[TcpOpt]) -> String {
+    options.iter().map(|o| match o {
+        TcpOpt::Eol => "E".to_string(),
+        TcpOpt::Nop => "N".to_string(),
+        TcpOpt::Mss(m) => format!("M{}", m),
+        TcpOpt::WindowScale(w) => format!("W{}", w),
+        TcpOpt::SackPermitted => "S".to_string(),
+        TcpOpt::Timestamp => "T".to_string(),
+        TcpOpt::Other(k) => format!("?{}", k),
+    }).collect::<Vec<_>>().join(",")
+}
+
+/// p0f-style heuristics over the initial TTL and the option layout.
+pub fn classify(ttl: u8, window: u16, options: &[TcpOpt]) -> OsGuess {
+    let initial_ttl = [32u8, 64, 128, 255].into_iter().find(|&t| ttl <= t).unwrap_or(255);
+    // Layout with values stripped, so MSS differences along the path don't matter
+    let shape: String = options.iter().map(|o| match o {
+        TcpOpt::Eol => 'E', TcpOpt::Nop => 'N', TcpOpt::Mss(_) => 'M', TcpOpt::WindowScale(_) => 'W',
+        TcpOpt::SackPermitted => 'S', TcpOpt::Timestamp => 'T', TcpOpt::Other(_) => '?',
+    }).collect();
+    let family = match initial_ttl {
+        64 if shape == "MSTNW" => "Linux",
+        64 if shape.starts_with("MNWNNTSE") || shape.starts_with("MNWNNT") => "macOS/iOS",
+        64 if shape == "MNWST" || shape == "MNWSNNT" => "FreeBSD",
+        64 if options.is_empty() => "Embedded/IoT",
+        64 => "Linux/Unix",
+        128 if window == 0 => "Unknown",
+        128 => "Windows",
+        255 if shape.is_empty() || shape == "M" => "Network device (Cisco/IOS)",
+        255 => "Solaris/Network device",
+        _ => "Embedded/legacy",
+    };
+    OsGuess { family: family.to_string(), ttl, initial_ttl, window, options: layout(options) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_classify_common_stacks() {
+        // Linux SYN-ACK: MSS, SACK, TS, NOP, WS
+        let linux = parse_options(&[2, 4, 5, 0xb4, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 1, 1, 3, 3, 7]);
+        let g = classify(52, 65160, &linux);
+        assert_eq!((g.family.as_str(), g.initial_ttl, g.options.as_str()), ("Linux", 64, "M1460,S,T,N,W7"));
+        // Windows: MSS, NOP, WS, SACK, TS with TTL 128
+        let win = parse_options(&[2, 4, 5, 0xb4, 1, 3, 3, 8, 4, 2, 8, 10, 0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(classify(117, 65535, &win).family, "Windows");
+        assert_eq!(classify(250, 4128, &parse_options(&[2, 4, 5, 0x3c])).family, "Network device (Cisco/IOS)");
+    }
+    #[test]
+    fn test_syn_roundtrip() {
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let dst = Ipv4Addr::new(10, 0, 0, 2);
+        let seg = build_syn(src, dst, 40000, 443, 7);
+        assert_eq!(rawsock::transport_checksum(src, dst, IPPROTO_TCP, &seg), 0);
+        let parsed = parse_tcp(&seg).unwrap();
+        assert_eq!((parsed.sport, parsed.dport, parsed.flags), (40000, 443, SYN));
+        assert_eq!(layout(&parsed.options), "M1460,S,T,N,W7");
+    }
+}
@@ -0,0 +1,332 @@
+use std::io::{self, Write};
+
+/// Rows buffered before they're written out as a row group.
+const ROW_GROUP: usize = 100_000;
+const MAGIC: &[u8] = b"PAR1";
+/// CompressionCodec.ZSTD
+const ZSTD: i32 = 6;
+/// Encoding.PLAIN and Encoding.RLE
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+/// Physical type of a column; strings are UTF-8 annotated byte arrays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Int32,
+    Int64,
+    String,
+}
+
+impl Kind {
+    /// parquet.thrift `Type`
+    fn id(self) -> i32 {
+        match self {
+            Self::Int32 => 1,
+            Self::Int64 => 2,
+            Self::String => 6,
+        }
+    }
+}
+
+pub enum Cell<'a> {
+    Int(i64),
+    Str(&'a str),
+    Null,
+}
+
+struct Column {
+    name: &'static str,
+    kind: Kind,
+    optional: bool,
+    /// Whether each row has a value; only kept for optional columns
+    defined: Vec<bool>,
+    /// PLAIN-encoded values of the current row group
+    values: Vec<u8>,
+}
+
+struct Chunk {
+    offset: u64,
+    values: usize,
+    uncompressed: usize,
+    compressed: usize,
+}
+
+/// A flat Parquet file written front to back: a row group every ROW_GROUP
+/// rows, one zstd-compressed PLAIN data page per column, and the footer on
+/// `finish`. A file that was never finished has no footer and won't open.
+pub struct Writer<W: Write> {
+    out: W,
+    offset: u64,
+    columns: Vec<Column>,
+    rows: usize,
+    groups: Vec<(usize, Vec<Chunk>)>,
+}
+
+impl<W: Write> Writer<W> {
+    /// `columns` are (name, type, optional).
+    pub fn new(mut out: W, columns: &[(&'static str, Kind, bool)]) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        let columns = columns.iter().map(|&(name, kind, optional)| Column { name, kind, optional, defined: Vec::new(), values: Vec::new() }).collect();
+        Ok(Self { out, offset: MAGIC.len() as u64, columns, rows: 0, groups: Vec::new() })
+    }
+
+    /// One cell per column, in order. Null in a required column or a value of
+    /// the wrong type is a bug in the caller and panics.
+    pub fn push(&mut self, row: &[Cell]) -> io::Result<()> {
+        assert_eq!(row.len(), self.columns.len());
+        for (column, cell) in self.columns.iter_mut().zip(row) {
+            if column.optional { column.defined.push(!matches!(cell, Cell::Null)); }
+            match (column.kind, cell) {
+                (_, Cell::Null) => assert!(column.optional, "null in required column {}", column.name),
+                (Kind::Int32, Cell::Int(v)) => column.values.extend((*v as i32).to_le_bytes()),
+                (Kind::Int64, Cell::Int(v)) => column.values.extend(v.to_le_bytes()),
+                (Kind::String, Cell::Str(s)) => {
+                    column.values.extend((s.len() as u32).to_le_bytes());
+                    column.values.extend(s.as_bytes());
+                }
+                (kind, _) => panic!("wrong value type for {:?} column {}", kind, column.name),
+            }
+        }
+        self.rows += 1;
+        if self.rows == ROW_GROUP { self.flush_group()?; }
+        Ok(())
+    }
+
+    fn flush_group(&mut self) -> io::Result<()> {
+        if self.rows == 0 { return Ok(()); }
+        let mut chunks = Vec::new();
+        for column in &mut self.columns {
+            let mut page = Vec::new();
+            if column.optional {
+                let levels = rle_levels(&column.defined);
+                page.extend((levels.len() as u32).to_le_bytes());
+                page.extend(levels);
+            }
+            page.append(&mut column.values);
+            column.defined.clear();
+            let compressed = zstd::bulk::compress(&page, 0)?;
+
+            let mut header = Compact::default();
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, page.len() as i32);
+            header.i32(3, compressed.len() as i32);
+            header.begin(5);
+            header.i32(1, self.rows as i32);
+            header.i32(2, PLAIN);
+            header.i32(3, RLE);
+            header.i32(4, RLE);
+            header.end();
+            header.stop();
+
+            self.out.write_all(&header.buf)?;
+            self.out.write_all(&compressed)?;
+            chunks.push(Chunk {
+                offset: self.offset, values: self.rows,
+                uncompressed: header.buf.len() + page.len(), compressed: header.buf.len() + compressed.len(),
+            });
+            self.offset += (header.buf.len() + compressed.len()) as u64;
+        }
+        self.groups.push((self.rows, chunks));
+        self.rows = 0;
+        Ok(())
+    }
+
+    /// Writes the last row group and the footer; `metadata` lands in the
+    /// file's key/value metadata.
+    pub fn finish(mut self, metadata: &[(&str, &str)]) -> io::Result<W> {
+        self.flush_group()?;
+        let mut meta = Compact::default();
+        meta.i32(1, 1);
+        meta.list(2, STRUCT, self.columns.len() + 1);
+        meta.item();
+        meta.binary(4, b"schema");
+        meta.i32(5, self.columns.len() as i32);
+        meta.stop();
+        for column in &self.columns {
+            meta.item();
+            meta.i32(1, column.kind.id());
+            meta.i32(3, column.optional as i32);
+            meta.binary(4, column.name.as_bytes());
+            if column.kind == Kind::String { meta.i32(6, 0); } // ConvertedType.UTF8
+            meta.stop();
+        }
+        meta.i64(3, self.groups.iter().map(|(rows, _)| *rows as i64).sum());
+        meta.list(4, STRUCT, self.groups.len());
+        for (rows, chunks) in &self.groups {
+            meta.item();
+            meta.list(1, STRUCT, chunks.len());
+            for (column, chunk) in self.columns.iter().zip(chunks) {
+                meta.item();
+                meta.i64(2, chunk.offset as i64);
+                meta.begin(3);
+                meta.i32(1, column.kind.id());
+                meta.list(2, I32, 2);
+                meta.varint(zigzag(PLAIN as i64));
+                meta.varint(zigzag(RLE as i64));
+                meta.list(3, BINARY, 1);
+                meta.bytes(column.name.as_bytes());
+                meta.i32(4, ZSTD);
+                meta.i64(5, chunk.values as i64);
+                meta.i64(6, chunk.uncompressed as i64);
+                meta.i64(7, chunk.compressed as i64);
+                meta.i64(9, chunk.offset as i64);
+                meta.end();
+                meta.stop();
+            }
+            meta.i64(2, chunks.iter().map(|c| c.uncompressed as i64).sum());
+            meta.i64(3, *rows as i64);
+            meta.stop();
+        }
+        meta.list(5, STRUCT, metadata.len());
+        for (key, value) in metadata {
+            meta.item();
+            meta.binary(1, key.as_bytes());
+            meta.binary(2, value.as_bytes());
+            meta.stop();
+        }
+        meta.binary(6, format!("PulseNet version {}", env!("CARGO_PKG_VERSION")).as_bytes());
+        meta.stop();
+
+        self.out.write_all(&meta.buf)?;
+        self.out.write_all(&(meta.buf.len() as u32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Definition levels (bit width 1) in the RLE/bit-packing hybrid, as RLE runs.
+fn rle_levels(defined: &[bool]) -> Vec<u8> {
+    let mut out = Compact::default();
+    for run in defined.chunk_by(|a, b| a == b) {
+        out.varint((run.len() as u64) << 1);
+        out.buf.push(run[0] as u8);
+    }
+    out.buf
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+// Thrift compact protocol type ids
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// Just enough of the Thrift compact protocol for Parquet's page headers and
+/// footer. Field ids are delta-encoded against the last field of the struct
+/// being written, so nested structs keep their own.
+#[derive(Default)]
+struct Compact {
+    buf: Vec<u8>,
+    last: i16,
+    outer: Vec<i16>,
+}
+
+impl Compact {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn field(&mut self, id: i16, ty: u8) {
+        match id - self.last {
+            delta @ 1..=15 => self.buf.push((delta as u8) << 4 | ty),
+            _ => {
+                self.buf.push(ty);
+                self.varint(zigzag(id as i64));
+            }
+        }
+        self.last = id;
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, I32);
+        self.varint(zigzag(v as i64));
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, I64);
+        self.varint(zigzag(v));
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.varint(v.len() as u64);
+        self.buf.extend(v);
+    }
+
+    fn binary(&mut self, id: i16, v: &[u8]) {
+        self.field(id, BINARY);
+        self.bytes(v);
+    }
+
+    fn list(&mut self, id: i16, ty: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | ty);
+        } else {
+            self.buf.push(0xf0 | ty);
+            self.varint(len as u64);
+        }
+    }
+
+    /// A struct field; close it with `end`.
+    fn begin(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.item();
+    }
+
+    /// A struct element of a list; close it with `stop`.
+    fn item(&mut self) {
+        self.outer.push(self.last);
+        self.last = 0;
+    }
+
+    fn end(&mut self) {
+        self.stop();
+    }
+
+    /// Ends the current struct: the nested one opened by `begin`/`item`, or
+    /// with none open, the top-level one.
+    fn stop(&mut self) {
+        self.buf.push(0);
+        self.last = self.outer.pop().unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_writer() {
+        let mut thrift = Compact::default();
+        thrift.i32(1, 3);
+        thrift.i64(20, -1);
+        thrift.begin(21);
+        thrift.binary(1, b"ip");
+        thrift.end();
+        thrift.list(22, I32, 1);
+        thrift.varint(zigzag(2));
+        thrift.stop();
+        assert_eq!(thrift.buf, [0x15, 0x06, 0x06, 0x28, 0x01, 0x1c, 0x18, 0x02, b'i', b'p', 0x00, 0x19, 0x15, 0x04, 0x00]);
+        assert_eq!(rle_levels(&[true, true, false]), [0x04, 0x01, 0x02, 0x00]);
+
+        let columns = [("ip", Kind::String, false), ("port", Kind::Int32, false), ("latency_ms", Kind::Int64, true)];
+        let mut writer = Writer::new(Vec::new(), &columns).unwrap();
+        writer.push(&[Cell::Str("203.0.113.7"), Cell::Int(443), Cell::Int(12)]).unwrap();
+        writer.push(&[Cell::Str("203.0.113.7"), Cell::Int(22), Cell::Null]).unwrap();
+        let file = writer.finish(&[("pulsenet.schema_version", "1")]).unwrap();
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let footer = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        let meta = &file[file.len() - 8 - footer..file.len() - 8];
+        // version 1, then a schema list of 4 structs
+        assert_eq!(meta[..4], [0x15, 0x02, 0x19, 0x4c]);
+        assert!(meta.windows(10).any(|w| w == b"latency_ms"));
+    }
+}
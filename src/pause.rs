@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Shared pause switch: dispatch waits while it's on, in-flight probes finish.
+#[derive(Clone)]
+pub struct PauseControl {
+    tx: Arc<watch::Sender<bool>>,
+    keys: bool,
+}
+
+impl PauseControl {
+    pub fn new() -> Self {
+        Self { tx: Arc::new(watch::channel(false).0), keys: false }
+    }
+
+    pub fn set(&self, paused: bool) {
+        self.tx.send_if_modified(|p| std::mem::replace(p, paused) != paused);
+    }
+
+    pub fn toggle(&self) {
+        self.tx.send_modify(|p| *p = !*p);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Returns right away unless paused.
+    pub async fn wait_resumed(&self) {
+        let _ = self.tx.subscribe().wait_for(|paused| !paused).await;
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.tx.subscribe()
+    }
+
+    /// Whether `p`/`r` are being read from the terminal.
+    pub fn keys(&self) -> bool {
+        self.keys
+    }
+
+    /// SIGUSR1 toggles pause (Unix).
+    pub fn listen_signal(&self) {
+        #[cfg(unix)]
+        if let Ok(mut usr1) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
+            let ctrl = self.clone();
+            tokio::spawn(async move {
+                while usr1.recv().await.is_some() { ctrl.toggle(); }
+            });
+        }
+    }
+
+    /// `p` pauses, `r` resumes. Puts the terminal into non-canonical mode
+    /// without echo until the guard is dropped; no-op when stdin isn't a TTY.
+    pub fn listen_keys(&mut self) -> Option<imp::TermGuard> {
+        let guard = imp::TermGuard::raw()?;
+        self.keys = true;
+        let ctrl = self.clone();
+        std::thread::spawn(move || {
+            use std::io::Read;
+            let mut byte = [0u8; 1];
+            while let Ok(1) = std::io::stdin().read(&mut byte) {
+                match byte[0] {
+                    b'p' | b'P' => ctrl.set(true),
+                    b'r' | b'R' => ctrl.set(false),
+                    _ => {}
+                }
+            }
+        });
+        Some(guard)
+    }
+}
+
+#[cfg(unix)]
+pub mod imp {
+    /// Restores the saved terminal settings on drop, or on Ctrl-C. Stopping the
+    /// scan itself is left to `daemon::Shutdown`, which drains the sinks.
+    pub struct TermGuard {
+        saved: libc::termios,
+    }
+
+    impl TermGuard {
+        pub fn raw() -> Option<Self> {
+            if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 { return None; }
+            let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 { return None; }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 { return None; }
+            // Restored right away so a second Ctrl-C, which exits without
+            // unwinding, doesn't leave the shell without echo
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved) };
+                }
+            });
+            Some(Self { saved })
+        }
+    }
+
+    impl Drop for TermGuard {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub mod imp {
+    pub struct TermGuard;
+    impl TermGuard {
+        pub fn raw() -> Option<Self> { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_pause_blocks_until_resumed() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        rt.block_on(async {
+            let ctrl = PauseControl::new();
+            ctrl.wait_resumed().await;
+            ctrl.toggle();
+            let waiting = tokio::time::timeout(std::time::Duration::from_millis(20), ctrl.wait_resumed()).await;
+            assert!(waiting.is_err());
+            let resumer = ctrl.clone();
+            tokio::spawn(async move { resumer.set(false) });
+            ctrl.wait_resumed().await;
+            assert!(!ctrl.is_paused());
+        });
+    }
+}
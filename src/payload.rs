@@ -0,0 +1,141 @@
+use crate::plugin;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket};
+
+/// Response bytes kept in results.
+const MAX_RESPONSE: usize = 1024;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Proto {
+    Tcp,
+    Udp,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    name: Option<String>,
+    port: u16,
+    #[serde(default = "default_proto")]
+    proto: Proto,
+    text: Option<String>,
+    hex: Option<String>,
+}
+
+fn default_proto() -> Proto {
+    Proto::Tcp
+}
+
+#[derive(Deserialize)]
+struct PayloadFile {
+    payload: Vec<Spec>,
+}
+
+/// Bytes to send to a port: after connecting for TCP, as a datagram for UDP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payload {
+    pub name: String,
+    pub port: u16,
+    pub proto: Proto,
+    pub data: Vec<u8>,
+}
+
+/// What came back for a payload; `text` when the response is UTF-8, else `hex`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Response {
+    pub name: String,
+    pub port: u16,
+    pub proto: Proto,
+    pub length: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = s.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) { bail!("odd number of hex digits"); }
+    digits.chunks(2).map(|pair| {
+        let pair = std::str::from_utf8(pair)?;
+        u8::from_str_radix(pair, 16).with_context(|| format!("invalid hex byte '{}'", pair))
+    }).collect()
+}
+
+/// `[[payload]]` tables with `port`, `proto` (tcp/udp), an optional `name`,
+/// and the bytes as `text` (TOML escapes such as `\r\n` apply) or `hex`.
+pub fn parse(content: &str) -> Result<Vec<Payload>> {
+    let file: PayloadFile = toml::from_str(content)?;
+    file.payload.into_iter().enumerate().map(|(i, spec)| {
+        let data = match (spec.text, spec.hex) {
+            (Some(text), None) => text.into_bytes(),
+            (None, Some(hex)) => parse_hex(&hex).with_context(|| format!("payload {}", i + 1))?,
+            _ => bail!("payload {} needs exactly one of 'text' or 'hex'", i + 1),
+        };
+        let name = spec.name.unwrap_or_else(|| format!("{}/{}", spec.port, if spec.proto == Proto::Tcp { "tcp" } else { "udp" }));
+        Ok(Payload { name, port: spec.port, proto: spec.proto, data })
+    }).collect()
+}
+
+pub fn load(path: &str) -> Result<Vec<Payload>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read payload file '{}'", path))?;
+    parse(&content).with_context(|| format!("invalid payload file '{}'", path))
+}
+
+fn response(payload: &Payload, data: &[u8]) -> Response {
+    let kept = &data[..data.len().min(MAX_RESPONSE)];
+    let (text, hex) = match std::str::from_utf8(kept) {
+        Ok(s) => (Some(s.to_string()), None),
+        Err(_) => (None, Some(kept.iter().map(|b| format!("{:02x}", b)).collect())),
+    };
+    Response { name: payload.name.clone(), port: payload.port, proto: payload.proto, length: data.len(), text, hex }
+}
+
+/// Sends a TCP payload to an open port; None when nothing comes back.
+pub async fn send_tcp<F, Fut>(payload: &Payload, connect: F, wait: Duration) -> Option<Response>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let data = plugin::grab(connect, &payload.data, wait).await.ok()?;
+    (!data.is_empty()).then(|| response(payload, &data))
+}
+
+/// Sends a UDP payload over `sock`, connected to its port, and waits for the first datagram back.
+pub async fn send_udp(sock: UdpSocket, payload: &Payload, wait: Duration) -> Option<Response> {
+    sock.send(&payload.data).await.ok()?;
+    let mut buf = vec![0u8; 65535];
+    // An ICMP port unreachable surfaces as a recv error
+    let n = tokio::time::timeout(wait, sock.recv(&mut buf)).await.ok()?.ok()?;
+    Some(response(payload, &buf[..n]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_payload_file() {
+        let payloads = parse(r#"
+            [[payload]]
+            name = "memcached"
+            port = 11211
+            text = "stats\r\n"
+
+            [[payload]]
+            port = 123
+            proto = "udp"
+            hex = "e3 00 04 fa"
+        "#).unwrap();
+        assert_eq!(payloads[0].data, b"stats\r\n");
+        assert_eq!((payloads[1].name.as_str(), payloads[1].proto, payloads[1].data.clone()), ("123/udp", Proto::Udp, vec![0xe3, 0, 4, 0xfa]));
+        assert!(parse("[[payload]]\nport = 1\nhex = \"abc\"").is_err());
+        assert!(parse("[[payload]]\nport = 1\ntext = \"a\"\nhex = \"61\"").is_err());
+        let binary = response(&payloads[1], &[0x1c, 0xff]);
+        assert_eq!((binary.text, binary.hex.as_deref()), (None, Some("1cff")));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::targets::IpSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: u32 = 65535;
+
+/// Classic libpcap file (microsecond timestamps, Ethernet link type).
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(&0xa1b2c3d4u32.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&[0; 8])?; // thiszone, sigfigs
+        out.write_all(&SNAPLEN.to_le_bytes())?;
+        out.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+        Ok(Self { out })
+    }
+
+    pub fn write_frame(&mut self, at: SystemTime, frame: &[u8]) -> io::Result<()> {
+        let ts = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = frame.len().min(SNAPLEN as usize);
+        self.out.write_all(&(ts.as_secs() as u32).to_le_bytes())?;
+        self.out.write_all(&ts.subsec_micros().to_le_bytes())?;
+        self.out.write_all(&(len as u32).to_le_bytes())?;
+        self.out.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.out.write_all(&frame[..len])
+    }
+
+    pub fn into_inner(self) -> W { self.out }
+}
+
+/// Whether an Ethernet frame is IPv4 or ARP traffic to or from one of `hosts`.
+fn involves(frame: &[u8], hosts: &IpSet) -> bool {
+    let addr = |at: usize| frame.get(at..at + 4).map(|b| Ipv4Addr::new(b[0], b[1], b[2], b[3]));
+    let (a, b) = match frame.get(12..14) {
+        Some([0x08, 0x00]) => (addr(26), addr(30)),
+        Some([0x08, 0x06]) => (addr(28), addr(38)),
+        _ => return false,
+    };
+    [a, b].into_iter().flatten().any(|ip| hosts.contains(ip))
+}
+
+/// Background capture of every frame exchanged with the scan targets, on all
+/// interfaces, until `finish`. Needs root (AF_PACKET).
+pub struct Capture {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<u64>>,
+}
+
+impl Capture {
+    /// `hosts` may grow while the capture runs, as targets are handed out.
+    pub fn start(path: &str, hosts: Arc<RwLock<IpSet>>) -> io::Result<Self> {
+        let sock = imp::open()?;
+        let mut writer = PcapWriter::new(BufWriter::new(File::create(path)?))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let handle = std::thread::spawn(move || {
+            let mut buf = vec![0u8; SNAPLEN as usize];
+            let mut packets = 0;
+            while !flag.load(Ordering::Relaxed) {
+                let Some(n) = imp::recv(&sock, &mut buf, Duration::from_millis(200))? else { continue };
+                if involves(&buf[..n], &hosts.read().unwrap()) {
+                    writer.write_frame(SystemTime::now(), &buf[..n])?;
+                    packets += 1;
+                }
+            }
+            writer.into_inner().flush()?;
+            Ok(packets)
+        });
+        Ok(Self { stop, handle })
+    }
+
+    /// Stops the capture, giving late answers a moment to arrive. Returns the packet count.
+    pub fn finish(self) -> io::Result<u64> {
+        std::thread::sleep(Duration::from_millis(300));
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle.join().map_err(|_| io::Error::other("capture thread panicked"))?
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use socket2::Socket;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::time::Duration;
+
+    const ETH_P_ALL: u16 = 0x0003;
+    const ARPHRD_ETHER: u16 = 1;
+    const ARPHRD_LOOPBACK: u16 = 772;
+
+    pub fn open() -> io::Result<Socket> {
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, ETH_P_ALL.to_be() as i32) };
+        if fd < 0 { return Err(io::Error::last_os_error()); }
+        Ok(unsafe { Socket::from_raw_fd(fd) })
+    }
+
+    /// One frame with an Ethernet header, None on timeout or for frames of other link types.
+    pub fn recv(sock: &Socket, buf: &mut [u8], wait: Duration) -> io::Result<Option<usize>> {
+        sock.set_read_timeout(Some(wait))?;
+        let mut from: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        let n = unsafe {
+            libc::recvfrom(sock.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0, &mut from as *mut _ as *mut libc::sockaddr, &mut len)
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted => Ok(None),
+                _ => Err(err),
+            };
+        }
+        // Loopback delivers every packet twice, once outgoing and once incoming
+        let duplicate = from.sll_hatype == ARPHRD_LOOPBACK && from.sll_pkttype == libc::PACKET_OUTGOING;
+        Ok((matches!(from.sll_hatype, ARPHRD_ETHER | ARPHRD_LOOPBACK) && !duplicate).then_some(n as usize))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use socket2::Socket;
+    use std::io;
+    use std::time::Duration;
+    pub fn open() -> io::Result<Socket> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "packet capture is only supported on Linux"))
+    }
+    pub fn recv(_: &Socket, _: &mut [u8], _: Duration) -> io::Result<Option<usize>> { Ok(None) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_pcap_layout_and_filter() {
+        let mut frame = vec![0u8; 34];
+        frame[12..14].copy_from_slice(&[0x08, 0x00]);
+        frame[26..30].copy_from_slice(&[10, 0, 0, 1]);
+        frame[30..34].copy_from_slice(&[10, 0, 0, 2]);
+        let mut hosts = IpSet::default();
+        hosts.insert(Ipv4Addr::new(10, 0, 0, 3));
+        assert!(!involves(&frame, &hosts));
+        hosts.insert(Ipv4Addr::new(10, 0, 0, 2));
+        assert!(involves(&frame, &hosts));
+
+        let mut w = PcapWriter::new(Vec::new()).unwrap();
+        w.write_frame(UNIX_EPOCH + Duration::from_micros(1_500_000), &frame).unwrap();
+        let out = w.into_inner();
+        assert_eq!((out.len(), &out[..4], &out[20..24]), (24 + 16 + 34, &[0xd4, 0xc3, 0xb2, 0xa1][..], &[1, 0, 0, 0][..]));
+        assert_eq!(&out[24..32], &[1, 0, 0, 0, 0x20, 0xa1, 0x07, 0]);
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::net::{IpAddr, Ipv4Addr};
+use tokio_postgres::{Client, NoTls};
+use tracing::warn;
+
+/// Runs, hosts and their ports; ports are upserted per (ip, port) so the
+/// tables hold the latest state plus first/last seen across runs and scanners.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS pulsenet_runs (
+    id BIGSERIAL PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ,
+    scanner TEXT NOT NULL,
+    config JSONB NOT NULL,
+    hits INTEGER
+);
+CREATE TABLE IF NOT EXISTS pulsenet_hosts (
+    ip INET PRIMARY KEY,
+    first_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_run BIGINT NOT NULL REFERENCES pulsenet_runs (id),
+    details JSONB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS pulsenet_ports (
+    ip INET NOT NULL REFERENCES pulsenet_hosts (ip) ON DELETE CASCADE,
+    port INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    latency_ms INTEGER,
+    first_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_run BIGINT NOT NULL REFERENCES pulsenet_runs (id),
+    PRIMARY KEY (ip, port)
+);";
+
+pub fn is_postgres_url(s: &str) -> bool {
+    s.starts_with("postgres://") || s.starts_with("postgresql://")
+}
+
+/// Results backend for `--output postgres://...` (plain TCP, no TLS).
+pub struct PgSink {
+    client: Client,
+    run_id: i64,
+}
+
+impl PgSink {
+    /// Connects, creates the schema if needed and opens a run.
+    pub async fn connect(url: &str, config: &Value) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await.context("failed to connect to PostgreSQL")?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await { warn!("PostgreSQL connection lost: {}", e); }
+        });
+        client.batch_execute(SCHEMA).await.context("failed to create the PulseNet schema")?;
+        let scanner = hostname();
+        let row = client.query_one("INSERT INTO pulsenet_runs (scanner, config) VALUES ($1, $2) RETURNING id", &[&scanner, config]).await
+            .context("failed to register the run")?;
+        Ok(Self { client, run_id: row.get(0) })
+    }
+
+    /// Upserts one hit: the host row with the probe details, then one row per port.
+    pub async fn record(&self, ip: Ipv4Addr, result: &Value) -> Result<()> {
+        let ip = IpAddr::V4(ip);
+        self.client.execute(
+            "INSERT INTO pulsenet_hosts (ip, last_run, details) VALUES ($1, $2, $3)
+             ON CONFLICT (ip) DO UPDATE SET last_seen = now(), last_run = EXCLUDED.last_run, details = EXCLUDED.details",
+            &[&ip, &self.run_id, &host_details(result)],
+        ).await?;
+        for (number, state, latency) in port_rows(result) {
+            self.client.execute(
+                "INSERT INTO pulsenet_ports (ip, port, state, latency_ms, last_run) VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (ip, port) DO UPDATE SET state = EXCLUDED.state, latency_ms = EXCLUDED.latency_ms,
+                     last_seen = now(), last_run = EXCLUDED.last_run",
+                &[&ip, &number, &state, &latency, &self.run_id],
+            ).await?;
+        }
+        Ok(())
+    }
+
+    /// Closes the run with its hit count.
+    pub async fn finish(self, hits: u32) -> Result<()> {
+        self.client.execute("UPDATE pulsenet_runs SET finished_at = now(), hits = $2 WHERE id = $1", &[&self.run_id, &(hits as i32)]).await?;
+        Ok(())
+    }
+}
+
+/// Everything but the fields stored in their own columns and tables.
+pub fn host_details(result: &Value) -> Value {
+    let mut details = result.clone();
+    if let Some(map) = details.as_object_mut() { for key in ["ip", "ports", "timestamp"] { map.remove(key); } }
+    details
+}
+
+/// (port, state, latency) per probed port of a JSON result.
+pub fn port_rows(result: &Value) -> Vec<(i32, &str, Option<i32>)> {
+    result.get("ports").and_then(Value::as_array).into_iter().flatten().filter_map(|p| Some((
+        p.get("port")?.as_i64()? as i32,
+        p.get("state").and_then(Value::as_str).unwrap_or("unknown"),
+        p.get("latency_ms").and_then(Value::as_i64).map(|l| l as i32),
+    ))).collect()
+}
+
+/// The machine a run comes from: `gethostname`, else `$HOSTNAME`, else "unknown".
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0 {
+            let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            if end > 0 { return String::from_utf8_lossy(&buf[..end]).into_owned(); }
+        }
+    }
+    std::env::var("HOSTNAME").ok().filter(|h| !h.trim().is_empty()).unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_rows_from_result() {
+        let result = serde_json::json!({
+            "timestamp": "t", "ip": "10.0.0.5", "asn": 64500,
+            "ports": [{ "port": 22, "state": "open", "latency_ms": 4, "attempts": 1 }, { "port": 80, "state": "closed", "attempts": 1 }],
+        });
+        assert_eq!(port_rows(&result), [(22, "open", Some(4)), (80, "closed", None)]);
+        assert_eq!(host_details(&result), serde_json::json!({ "asn": 64500 }));
+        assert!(is_postgres_url("postgresql://scanner@db/pulsenet") && !is_postgres_url("results.log"));
+        assert!(!hostname().is_empty());
+    }
+}
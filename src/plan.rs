@@ -0,0 +1,110 @@
+use crate::import::split_csv_line;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// One row of a CSV or JSON target file: a host and how to scan it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// IPv4 address or host name
+    pub host: String,
+    /// Replaces --ports for this host
+    pub ports: Option<Vec<u16>>,
+    /// Replaces --timeout for this host's port probes
+    pub timeout: Option<Duration>,
+    /// Copied into the host's results
+    pub labels: Vec<String>,
+}
+
+/// Target files with a `.csv`, `.json`, `.jsonl` or `.ndjson` extension are
+/// read as scan plans; anything else is a plain list.
+pub fn is_plan(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    [".csv", ".json", ".jsonl", ".ndjson"].iter().any(|ext| path.ends_with(ext))
+}
+
+pub fn load(path: &str) -> Result<Vec<Entry>> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read target file '{}'", path))?;
+    let entries = if path.to_ascii_lowercase().ends_with(".csv") { parse_csv(&content) } else { parse_json(&content) };
+    entries.with_context(|| format!("invalid target file '{}'", path))
+}
+
+fn split_list(cell: &str) -> impl Iterator<Item = &str> {
+    cell.split([',', ';', '|']).map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Header row naming the columns: `ip` (or `host`), and optionally `ports`
+/// (`"80,443"` or `80;443`), `timeout` in milliseconds and `labels` (`web;prod`).
+fn parse_csv(content: &str) -> Result<Vec<Entry>> {
+    let mut lines = content.lines().enumerate().filter(|(_, l)| !l.trim().is_empty() && !l.starts_with('#'));
+    let header: Vec<String> = lines.next().map(|(_, l)| split_csv_line(l)).unwrap_or_default()
+        .into_iter().map(|h| h.trim().to_ascii_lowercase()).collect();
+    let col = |name: &str| header.iter().position(|h| h == name);
+    let host_col = col("ip").or_else(|| col("host")).context("no 'ip' or 'host' column")?;
+    let (ports_col, timeout_col, labels_col) = (col("ports"), col("timeout"), col("labels"));
+
+    let mut entries = Vec::new();
+    for (n, line) in lines {
+        let fields = split_csv_line(line);
+        let cell = |col: Option<usize>| col.and_then(|c| fields.get(c)).map(|s| s.trim()).filter(|s| !s.is_empty());
+        let Some(host) = cell(Some(host_col)) else { bail!("line {}: missing host", n + 1) };
+        let ports = cell(ports_col).map(|c| split_list(c).map(|p| p.parse::<u16>()).collect::<Result<Vec<_>, _>>())
+            .transpose().with_context(|| format!("line {}: invalid ports", n + 1))?;
+        let timeout = cell(timeout_col).map(str::parse::<u64>).transpose()
+            .with_context(|| format!("line {}: invalid timeout", n + 1))?.map(Duration::from_millis);
+        let labels = cell(labels_col).map(|c| split_list(c).map(str::to_string).collect()).unwrap_or_default();
+        entries.push(Entry { host: host.to_string(), ports, timeout, labels });
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEntry {
+    #[serde(alias = "host")]
+    ip: String,
+    ports: Option<Vec<u16>>,
+    /// Milliseconds
+    timeout: Option<u64>,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+/// An array of `{"ip", "ports", "timeout", "labels"}` objects, or one per line.
+fn parse_json(content: &str) -> Result<Vec<Entry>> {
+    let trimmed = content.trim_start();
+    let rows: Vec<JsonEntry> = if trimmed.starts_with('[') {
+        serde_json::from_str(trimmed)?
+    } else {
+        trimmed.lines().enumerate().filter(|(_, l)| !l.trim().is_empty())
+            .map(|(n, l)| serde_json::from_str(l).with_context(|| format!("line {}", n + 1)))
+            .collect::<Result<_>>()?
+    };
+    Ok(rows.into_iter().map(|r| Entry {
+        host: r.ip, ports: r.ports, timeout: r.timeout.map(Duration::from_millis), labels: r.labels,
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_csv_and_json_plans() {
+        let csv = "ip,ports,timeout,labels\n203.0.113.10,\"80,443\",,web;prod\n# bastions\nbastion.example.com,22,5000,ssh\n198.51.100.7,,,\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries[0], Entry { host: "203.0.113.10".into(), ports: Some(vec![80, 443]), timeout: None, labels: vec!["web".into(), "prod".into()] });
+        assert_eq!((entries[1].ports.as_deref(), entries[1].timeout), (Some(&[22][..]), Some(Duration::from_secs(5))));
+        assert_eq!(entries[2], Entry { host: "198.51.100.7".into(), ports: None, timeout: None, labels: vec![] });
+        assert!(parse_csv("ip,ports\n203.0.113.10,http\n").is_err());
+        assert!(parse_csv("address\n203.0.113.10\n").is_err());
+
+        let json = r#"{"ip": "203.0.113.10", "ports": [8443], "labels": ["api"]}
+            {"host": "bastion.example.com", "timeout": 5000}"#;
+        assert_eq!(parse_json(json).unwrap(), [
+            Entry { host: "203.0.113.10".into(), ports: Some(vec![8443]), timeout: None, labels: vec!["api".into()] },
+            Entry { host: "bastion.example.com".into(), ports: None, timeout: Some(Duration::from_secs(5)), labels: vec![] },
+        ]);
+        assert_eq!(parse_json(r#"[{"ip": "198.51.100.7"}]"#).unwrap().len(), 1);
+        assert!(parse_json(r#"{"ip": "198.51.100.7", "port": 22}"#).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::debug;
+use wasmi::{Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+/// Instructions a plugin may execute per call before it's cut off.
+const FUEL: u64 = 50_000_000;
+/// Linear memory a plugin instance may grow to.
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+/// Largest response handed to a plugin.
+const MAX_DATA: usize = 16 * 1024;
+
+/// What a plugin reported for one open port.
+#[derive(Serialize, Debug, Clone)]
+pub struct Finding {
+    pub plugin: String,
+    pub port: u16,
+    pub findings: Value,
+}
+
+/// A probe compiled from a `.wasm` file. The module exports `memory` and:
+/// - `pulsenet_alloc(len: i32) -> i32`: buffer for the host to write into
+/// - `pulsenet_probe(ip: i32, port: i32, ptr: i32, len: i32) -> i64`: gets the
+///   bytes read from the port, returns `ptr << 32 | len` of a JSON findings
+///   string, or 0 for nothing to report
+/// - optional `pulsenet_wants(port: i32) -> i32`: non-zero to probe the port
+///   (default: every open port)
+/// - optional `pulsenet_request(port: i32) -> i64`: bytes to send after
+///   connecting, packed like `pulsenet_probe`'s result
+pub struct Plugin {
+    pub name: String,
+    engine: Engine,
+    module: Module,
+}
+
+fn packed(v: i64) -> (usize, usize) {
+    ((v as u64 >> 32) as usize, (v as u64 & 0xffff_ffff) as usize)
+}
+
+fn read(store: &Store<StoreLimits>, memory: &Memory, (ptr, len): (usize, usize)) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    memory.read(store, ptr, &mut buf).context("plugin returned a buffer outside its memory")?;
+    Ok(buf)
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let bytes = std::fs::read(path).with_context(|| format!("failed to read plugin '{}'", path.display()))?;
+        let module = Module::new(&engine, &bytes[..]).with_context(|| format!("invalid WASM module '{}'", path.display()))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let plugin = Self { name, engine, module };
+        // Check the ABI once up front rather than failing on every hit
+        plugin.call(|store, instance| {
+            instance.get_memory(&*store, "memory").context("no exported 'memory'")?;
+            instance.get_typed_func::<i32, i32>(&*store, "pulsenet_alloc")?;
+            instance.get_typed_func::<(i32, i32, i32, i32), i64>(&*store, "pulsenet_probe")?;
+            Ok(())
+        }).with_context(|| format!("plugin '{}' doesn't implement the PulseNet probe ABI", path.display()))?;
+        Ok(plugin)
+    }
+
+    /// Runs `f` on a fresh instance, so plugins keep no state between calls.
+    fn call<T>(&self, f: impl FnOnce(&mut Store<StoreLimits>, &Instance) -> Result<T>) -> Result<T> {
+        let mut store = Store::new(&self.engine, StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build());
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL)?;
+        let instance = Linker::<StoreLimits>::new(&self.engine).instantiate(&mut store, &self.module)?.start(&mut store)?;
+        f(&mut store, &instance)
+    }
+
+    pub fn wants(&self, port: u16) -> Result<bool> {
+        self.call(|store, instance| match instance.get_typed_func::<i32, i32>(&*store, "pulsenet_wants") {
+            Ok(wants) => Ok(wants.call(store, port as i32)? != 0),
+            Err(_) => Ok(true),
+        })
+    }
+
+    pub fn request(&self, port: u16) -> Result<Vec<u8>> {
+        self.call(|store, instance| {
+            let Ok(request) = instance.get_typed_func::<i32, i64>(&*store, "pulsenet_request") else { return Ok(Vec::new()); };
+            let out = request.call(&mut *store, port as i32)?;
+            let memory = instance.get_memory(&*store, "memory").context("no exported 'memory'")?;
+            if out == 0 { Ok(Vec::new()) } else { read(store, &memory, packed(out)) }
+        })
+    }
+
+    /// Hands the port's response to the plugin; None when it has nothing to say.
+    pub fn probe(&self, ip: Ipv4Addr, port: u16, data: &[u8]) -> Result<Option<Value>> {
+        self.call(|store, instance| {
+            let memory = instance.get_memory(&*store, "memory").context("no exported 'memory'")?;
+            let alloc: TypedFunc<i32, i32> = instance.get_typed_func(&*store, "pulsenet_alloc")?;
+            let probe: TypedFunc<(i32, i32, i32, i32), i64> = instance.get_typed_func(&*store, "pulsenet_probe")?;
+            let ptr = alloc.call(&mut *store, data.len() as i32)?;
+            memory.write(&mut *store, ptr as usize, data).context("plugin allocated a buffer outside its memory")?;
+            let out = probe.call(&mut *store, (u32::from(ip) as i32, port as i32, ptr, data.len() as i32))?;
+            if out == 0 { return Ok(None); }
+            let json = read(store, &memory, packed(out))?;
+            let value: Value = serde_json::from_slice(&json).context("plugin findings aren't valid JSON")?;
+            Ok((!value.is_null()).then_some(value))
+        })
+    }
+}
+
+/// Every `.wasm` file in `dir`, sorted by name.
+pub fn load_dir(dir: &str) -> Result<Vec<Arc<Plugin>>> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir).with_context(|| format!("failed to read plugin directory '{}'", dir))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "wasm"))
+        .collect();
+    paths.sort();
+    if paths.is_empty() { bail!("no .wasm plugins in '{}'", dir); }
+    paths.iter().map(|p| Plugin::load(p).map(Arc::new)).collect()
+}
+
+/// Connects, sends `request` and collects the response until the peer goes
+/// quiet for `wait`, closes, or MAX_DATA bytes arrived.
+pub async fn grab<F, Fut>(connect: F, request: &[u8], wait: Duration) -> Result<Vec<u8>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let mut stream = timeout(wait, connect()).await.context("connect timed out")??;
+    if !request.is_empty() { stream.write_all(request).await?; }
+    let mut data = vec![0; MAX_DATA];
+    let mut len = 0;
+    while len < MAX_DATA {
+        match timeout(wait, stream.read(&mut data[len..])).await {
+            Ok(Ok(0)) | Err(_) => break,
+            Ok(Ok(n)) => len += n,
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+    data.truncate(len);
+    Ok(data)
+}
+
+/// Grabs the port's response to the plugin's request and lets the plugin
+/// turn it into findings.
+pub async fn run<F, Fut>(plugin: Arc<Plugin>, ip: Ipv4Addr, port: u16, connect: F, wait: Duration) -> Option<Finding>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let result = async {
+        let data = grab(connect, &plugin.request(port)?, wait).await?;
+        let runner = plugin.clone();
+        tokio::task::spawn_blocking(move || runner.probe(ip, port, &data)).await?
+    }.await;
+    match result {
+        Ok(findings) => findings.map(|findings| Finding { plugin: plugin.name.clone(), port, findings }),
+        Err(e) => {
+            debug!(plugin = %plugin.name, %ip, port, error = %format!("{:#}", e), "plugin failed");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_probe_abi() {
+        // Reports {"ssh":true} for responses starting with "SS", asks for port 22 only
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{\"ssh\":true}")
+            (func (export "pulsenet_alloc") (param i32) (result i32) (i32.const 1024))
+            (func (export "pulsenet_wants") (param i32) (result i32) (i32.eq (local.get 0) (i32.const 22)))
+            (func (export "pulsenet_probe") (param i32 i32 i32 i32) (result i64)
+                (if (result i64) (i32.eq (i32.load16_u (local.get 2)) (i32.const 0x5353))
+                    (then (i64.const 12))
+                    (else (i64.const 0)))))"#;
+        let path = std::env::temp_dir().join(format!("pulsenet-plugin-{}.wasm", std::process::id()));
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        let plugin = Plugin::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(plugin.wants(22).unwrap() && !plugin.wants(80).unwrap());
+        assert!(plugin.request(22).unwrap().is_empty());
+        let ip = Ipv4Addr::new(10, 0, 0, 5);
+        assert_eq!(plugin.probe(ip, 22, b"SSH-2.0-OpenSSH_9.6").unwrap(), Some(serde_json::json!({ "ssh": true })));
+        assert_eq!(plugin.probe(ip, 22, b"HTTP/1.1 400").unwrap(), None);
+        assert_eq!(packed(4 << 32 | 12), (4, 12));
+    }
+    #[test]
+    fn test_memory_limit() {
+        // Wants a port only if its memory could grow by 64 MiB
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (func (export "pulsenet_alloc") (param i32) (result i32) (i32.const 0))
+            (func (export "pulsenet_wants") (param i32) (result i32) (i32.ne (memory.grow (i32.const 1024)) (i32.const -1)))
+            (func (export "pulsenet_probe") (param i32 i32 i32 i32) (result i64) (i64.const 0)))"#;
+        let path = std::env::temp_dir().join(format!("pulsenet-plugin-grow-{}.wasm", std::process::id()));
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        let plugin = Plugin::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!plugin.wants(80).unwrap());
+    }
+}
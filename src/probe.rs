@@ -0,0 +1,347 @@
+use crate::{backoff_delay, bind, dns, eyeballs, ftp, icmp, payload, plugin, proxy, quic, rdp, rtt, script, smb, snmp, ssh, tech, telnet, tlsaudit, web};
+use crate::{CheckResult, OsError, PortProbe, ScanError};
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::{debug, trace, warn};
+
+/// The host a probe runs against and how it may connect.
+pub struct Context<'a> {
+    pub ip: Ipv4Addr,
+    pub binding: &'a bind::SourceBinding,
+    /// A dual-stack host, raced over both families instead of `ip` alone
+    pub dual: Option<&'a eyeballs::Host>,
+    /// From the target file; beats every other connect timeout
+    pub host_timeout: Option<Duration>,
+    /// Connect timeout for ports without their own (adaptive or --timeout)
+    pub port_timeout: Duration,
+    /// Budget for one service check
+    pub wait: Duration,
+}
+
+impl Context<'_> {
+    fn connect(&self, port: u16) -> impl Future<Output = io::Result<TcpStream>> + '_ {
+        self.binding.connect(SocketAddr::new(self.ip.into(), port))
+    }
+
+    pub async fn stream(&self, port: u16) -> Option<TcpStream> {
+        timeout(self.wait, self.connect(port)).await.ok()?.ok()
+    }
+}
+
+/// What a probe learned, filed into the host's result.
+pub enum Finding {
+    Port(PortProbe),
+    Snmp(snmp::SnmpInfo),
+    OpenResolver(dns::OpenResolver),
+    Quic(quic::QuicInfo),
+    Telnet(telnet::TelnetInfo),
+    OpenProxy(proxy::OpenProxy),
+    Payload(payload::Response),
+    Ssh(ssh::SshInfo),
+    Rdp(rdp::RdpInfo),
+    Smb(smb::SmbInfo),
+    Ftp(ftp::FtpInfo),
+    Tls(tlsaudit::TlsAudit),
+    Http(web::WebInfo),
+    Plugin(plugin::Finding),
+}
+
+impl Finding {
+    pub fn apply(self, res: &mut CheckResult) {
+        match self {
+            Self::Port(p) => res.ports.push(p),
+            Self::Snmp(s) => res.snmp = Some(s),
+            Self::OpenResolver(r) => res.open_resolver = Some(r),
+            Self::Quic(q) => res.quic = Some(q),
+            Self::Telnet(t) => res.telnet = Some(t),
+            Self::OpenProxy(p) => res.open_proxy.push(p),
+            Self::Payload(p) => res.payloads.push(p),
+            Self::Ssh(s) => res.ssh = Some(s),
+            Self::Rdp(r) => res.rdp = Some(r),
+            Self::Smb(s) => res.smb = Some(s),
+            Self::Ftp(f) => res.ftp = Some(f),
+            Self::Tls(t) => res.tls.push(t),
+            Self::Http(h) => res.http.push(h),
+            Self::Plugin(f) => res.plugins.push(f),
+        }
+    }
+}
+
+/// One check in the scan pipeline. Service probes run on the open TCP ports
+/// they want, one probe at a time and each across its ports at once; UDP
+/// probes run on their own port alongside the connect scan.
+pub trait Probe: Send + Sync {
+    /// Whether to run on `port`, given every port found open on the host.
+    fn wants(&self, port: u16, open: &[u16]) -> bool;
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>>;
+}
+
+/// TCP connect with retries, backoff, RTT tracking and ICMP attribution.
+pub struct TcpConnectProbe {
+    pub retries: u32,
+    pub retry_backoff_ms: u64,
+    pub rtt: Option<rtt::RttEstimator>,
+    /// Timeouts given in --ports as PORT=MS
+    pub per_port: HashMap<u16, Duration>,
+    /// Present when raw sockets are available
+    pub icmp: Option<icmp::Watcher>,
+}
+
+impl TcpConnectProbe {
+    pub async fn connect(&self, cx: &Context<'_>, port: u16) -> PortProbe {
+        let ip = cx.ip;
+        let port_timeout = cx.host_timeout.or_else(|| self.per_port.get(&port).copied()).unwrap_or(cx.port_timeout);
+        let mut last_error = None;
+        let mut os_error = None;
+        let mut attempts = 0;
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff_delay(self.retry_backoff_ms, attempt)).await;
+            }
+            attempts += 1;
+            let attempt_start = std::time::Instant::now();
+            let mut answered = None;
+            let outcome = match cx.dual {
+                Some(host) => {
+                    let race = eyeballs::connect(&host.addrs, port, |a| cx.binding.connect(a), eyeballs::CONNECTION_ATTEMPT_DELAY);
+                    timeout(port_timeout, race).await.map(|r| r.map(|(stream, peer)| { answered = Some(eyeballs::family(peer)); stream }))
+                }
+                None => timeout(port_timeout, cx.connect(port)).await,
+            };
+            // Both a completed handshake and a RST are a full round trip
+            if let (Some(r), Ok(res)) = (&self.rtt, &outcome)
+                && (res.is_ok() || res.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::ConnectionRefused))
+            {
+                r.record(ip, attempt_start.elapsed());
+            }
+            match outcome {
+                Ok(Ok(_)) => {
+                    return PortProbe { port, latency: Some(attempt_start.elapsed().as_millis()), error: None, attempts, icmp: None, family: answered, os_error: None };
+                }
+                Ok(Err(e)) => {
+                    let err = match e.kind() {
+                        io::ErrorKind::ConnectionRefused => ScanError::ConnectionRefused,
+                        _ => ScanError::Unreachable,
+                    };
+                    trace!(%ip, port, attempt, error = %e, "connect failed");
+                    last_error = Some(err);
+                    os_error = Some(OsError::new(&e));
+                    // A refusal is a definitive answer, retrying won't change it
+                    if err == ScanError::ConnectionRefused { break; }
+                }
+                Err(_) => {
+                    trace!(%ip, port, attempt, "connect timed out");
+                    if last_error.is_none() { last_error = Some(ScanError::Timeout); }
+                }
+            }
+        }
+        let mut icmp = None;
+        if let Some(watcher) = &self.icmp && let Some(err) = last_error {
+            icmp = watcher.take(ip, port);
+            // The kernel may fail the connect before the watcher has read its copy of the ICMP
+            if icmp.is_none() && err != ScanError::Timeout {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                icmp = watcher.take(ip, port);
+            }
+            if icmp.is_some() { last_error = Some(ScanError::Filtered); }
+        }
+        if let Some(err) = last_error { debug!(%ip, port, attempts, error = ?err, icmp = ?icmp, "probe failed"); }
+        PortProbe { port, latency: None, error: last_error, attempts, icmp, family: None, os_error }
+    }
+}
+
+impl Probe for TcpConnectProbe {
+    fn wants(&self, _: u16, _: &[u16]) -> bool { true }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { Some(Finding::Port(self.connect(cx, port).await)) })
+    }
+}
+
+/// Checks that need no open TCP port: SNMP communities, open DNS resolvers
+/// and UDP payloads.
+pub enum UdpProbe {
+    Snmp(Vec<String>),
+    OpenResolver(String),
+    Quic,
+    Payload(payload::Payload),
+}
+
+impl UdpProbe {
+    pub fn port(&self) -> u16 {
+        match self {
+            Self::Snmp(_) => 161,
+            Self::OpenResolver(_) => 53,
+            Self::Quic => quic::PORT,
+            Self::Payload(p) => p.port,
+        }
+    }
+}
+
+impl Probe for UdpProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == self.port() }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, _: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move {
+            // Bound like the connect scan, so --source-ip and --interface hold for UDP too
+            let sock = cx.binding.connect_udp(SocketAddr::new(cx.ip.into(), self.port())).await.ok()?;
+            match self {
+                Self::Snmp(communities) => snmp::probe(sock, communities, cx.wait).await.ok().flatten().map(Finding::Snmp),
+                Self::OpenResolver(name) => dns::check_open_resolver(sock, name, cx.wait).await.ok().flatten().map(Finding::OpenResolver),
+                Self::Quic => quic::probe(sock, cx.ip, cx.wait).await.ok().flatten().map(Finding::Quic),
+                Self::Payload(p) => payload::send_udp(sock, p, cx.wait).await.map(Finding::Payload),
+            }
+        })
+    }
+}
+
+/// A --payload-file entry sent to its open TCP port.
+pub struct TcpPayloadProbe(pub payload::Payload);
+
+impl Probe for TcpPayloadProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == self.0.port }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { payload::send_tcp(&self.0, || cx.connect(port), cx.wait).await.map(Finding::Payload) })
+    }
+}
+
+/// SSH identification, and with `host_key` the server's host key fingerprint.
+pub struct SshProbe {
+    pub host_key: bool,
+}
+
+impl Probe for SshProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == 22 }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { ssh::inspect(cx.stream(port).await?, self.host_key, cx.wait).await.ok().map(Finding::Ssh) })
+    }
+}
+
+pub struct RdpProbe;
+
+impl Probe for RdpProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == 3389 }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { rdp::inspect(cx.ip, || cx.connect(port), cx.wait).await.ok().map(Finding::Rdp) })
+    }
+}
+
+pub struct SmbProbe;
+
+impl Probe for SmbProbe {
+    /// Direct SMB on 445 is preferred over NetBIOS session service on 139
+    fn wants(&self, port: u16, open: &[u16]) -> bool {
+        port == 445 || (port == 139 && !open.contains(&445))
+    }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { smb::inspect(|| cx.connect(port), port == 139, cx.wait).await.ok().map(Finding::Smb) })
+    }
+}
+
+pub struct TelnetProbe;
+
+impl Probe for TelnetProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == 23 }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { telnet::grab(cx.stream(port).await?, cx.wait).await.ok().map(Finding::Telnet) })
+    }
+}
+
+/// Anonymous FTP login, for --auth-checks.
+pub struct FtpProbe;
+
+impl Probe for FtpProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { port == 21 }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { ftp::check_anonymous(cx.stream(port).await?, cx.wait).await.ok().map(Finding::Ftp) })
+    }
+}
+
+/// Relaying through a proxy port, for --open-proxy.
+pub struct OpenProxyProbe(pub proxy::Endpoint);
+
+impl Probe for OpenProxyProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { proxy::PORTS.contains(&port) }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { proxy::check(cx.ip, port, || cx.connect(port), &self.0, cx.wait).await.map(Finding::OpenProxy) })
+    }
+}
+
+pub struct TlsProbe;
+
+impl Probe for TlsProbe {
+    fn wants(&self, _: u16, _: &[u16]) -> bool { true }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { tlsaudit::audit(port, || cx.connect(port), cx.wait).await.ok().map(Finding::Tls) })
+    }
+}
+
+pub struct HttpProbe {
+    pub rules: tech::TechRules,
+    /// Paths to try a WebSocket Upgrade on
+    pub websocket: Vec<String>,
+}
+
+impl Probe for HttpProbe {
+    fn wants(&self, _: u16, _: &[u16]) -> bool { true }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { web::probe(cx.ip, port, || cx.connect(port), &self.rules, &self.websocket, cx.wait).await.ok().map(Finding::Http) })
+    }
+}
+
+pub struct PluginProbe(pub Arc<plugin::Plugin>);
+
+impl Probe for PluginProbe {
+    fn wants(&self, port: u16, _: &[u16]) -> bool { self.0.wants(port).unwrap_or(false) }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move { plugin::run(self.0.clone(), cx.ip, port, || cx.connect(port), cx.wait).await.map(Finding::Plugin) })
+    }
+}
+
+/// Hands each open port's banner to the script's `on_banner` hook.
+pub struct ScriptBannerProbe(pub Arc<script::Script>);
+
+impl Probe for ScriptBannerProbe {
+    fn wants(&self, _: u16, _: &[u16]) -> bool { true }
+
+    fn run<'a>(&'a self, cx: &'a Context<'a>, port: u16) -> BoxFuture<'a, Option<Finding>> {
+        Box::pin(async move {
+            let data = plugin::grab(|| cx.connect(port), &[], cx.wait).await.ok()?;
+            let (runner, ip) = (self.0.clone(), cx.ip);
+            match tokio::task::spawn_blocking(move || runner.on_banner(ip, port, &data)).await {
+                Ok(Ok(Some(findings))) => Some(Finding::Plugin(plugin::Finding { plugin: self.0.name.clone(), port, findings })),
+                Ok(Err(e)) => { warn!("{}: {:#}", self.0.name, e); None }
+                _ => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_port_selection() {
+        assert!(SmbProbe.wants(139, &[139]) && !SmbProbe.wants(139, &[139, 445]) && SmbProbe.wants(445, &[139, 445]));
+        assert!(SshProbe { host_key: false }.wants(22, &[22]) && !FtpProbe.wants(22, &[22]));
+        let snmp = UdpProbe::Snmp(vec!["public".into()]);
+        assert!(snmp.port() == 161 && snmp.wants(161, &[]));
+    }
+}
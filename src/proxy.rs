@@ -0,0 +1,171 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Usual ports of HTTP proxies (Squid, and everything on 8080) and SOCKS.
+pub const PORTS: &[u16] = &[3128, 8080, 1080];
+const SOCKS_PORT: u16 = 1080;
+
+/// Where --open-proxy asks proxies to connect to: a host the user controls,
+/// whose logs then show every proxy that relayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.rsplit_once(':').map(|(h, p)| (h, p.parse::<u16>())) {
+            Some((host, Ok(port))) if !host.is_empty() && port != 0 => Ok(Self { host: host.to_string(), port }),
+            _ => bail!("invalid --open-proxy '{}': expected HOST:PORT", s),
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// A proxy that opened a tunnel to the --open-proxy endpoint for anyone.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OpenProxy {
+    pub port: u16,
+    /// `http-connect`, `socks5` or `socks4a`
+    pub protocol: &'static str,
+    /// First line the endpoint sent back through the tunnel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+}
+
+/// HTTP CONNECT on the HTTP proxy ports, SOCKS5 then SOCKS4a on 1080, each
+/// on a connection of its own. Through a tunnel that opens goes one GET whose
+/// User-Agent names the proxy, so the endpoint's access log can confirm it.
+pub async fn check<F, Fut>(ip: Ipv4Addr, port: u16, connect: F, to: &Endpoint, wait: Duration) -> Option<OpenProxy>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let protocols: &[&'static str] = if port == SOCKS_PORT { &["socks5", "socks4a"] } else { &["http-connect"] };
+    for &protocol in protocols {
+        let attempt = async {
+            let mut stream = connect().await?;
+            let relayed = match protocol {
+                "socks5" => socks5(&mut stream, to).await?,
+                "socks4a" => {
+                    stream.write_all(&socks4a_request(to)).await?;
+                    let mut reply = [0u8; 8];
+                    stream.read_exact(&mut reply).await?;
+                    reply[1] == 0x5a
+                }
+                _ => http_connect(&mut stream, to).await?,
+            };
+            if !relayed { return Ok(None); }
+            let probe = format!("GET / HTTP/1.0\r\nHost: {}\r\nUser-Agent: PulseNet open-proxy check via {}:{}\r\n\r\n", to, ip, port);
+            stream.write_all(probe.as_bytes()).await?;
+            let mut line = String::new();
+            // The tunnel is the finding; the endpoint may well not speak first or at all
+            let reply = timeout(wait, BufReader::new(stream).take(512).read_line(&mut line)).await.ok().and_then(Result::ok)
+                .filter(|n| *n > 0)
+                .map(|_| line.trim_end().chars().filter(|c| !c.is_control()).collect());
+            io::Result::Ok(Some(OpenProxy { port, protocol, reply }))
+        };
+        if let Ok(Ok(Some(found))) = timeout(wait * 2, attempt).await { return Some(found); }
+    }
+    None
+}
+
+async fn http_connect(stream: &mut TcpStream, to: &Endpoint) -> io::Result<bool> {
+    stream.write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", to).as_bytes()).await?;
+    // Read the response head byte by byte so nothing from the tunnel is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") && head.len() < 8192 {
+        let mut b = [0u8; 1];
+        if stream.read(&mut b).await? == 0 { break; }
+        head.push(b[0]);
+    }
+    let status = String::from_utf8_lossy(&head).split_whitespace().nth(1).and_then(|c| c.parse::<u16>().ok());
+    Ok(head.starts_with(b"HTTP/") && status.is_some_and(|c| (200..300).contains(&c)))
+}
+
+/// No-authentication SOCKS5 CONNECT; a proxy that wants a login isn't open.
+async fn socks5(stream: &mut TcpStream, to: &Endpoint) -> io::Result<bool> {
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice != [5, 0] { return Ok(false); }
+    stream.write_all(&socks5_request(to)).await?;
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[..2] != [5, 0] { return Ok(false); }
+    // Skip the bound address so the tunnel starts clean
+    let len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        _ => return Ok(false),
+    };
+    let mut bound = vec![0u8; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(true)
+}
+
+fn socks5_request(to: &Endpoint) -> Vec<u8> {
+    let mut req = vec![5, 1, 0];
+    match to.host.parse::<Ipv4Addr>() {
+        Ok(ip) => {
+            req.push(1);
+            req.extend(ip.octets());
+        }
+        Err(_) => {
+            req.extend([3, to.host.len() as u8]);
+            req.extend(to.host.as_bytes());
+        }
+    }
+    req.extend(to.port.to_be_bytes());
+    req
+}
+
+/// SOCKS4 for an address, SOCKS4a (0.0.0.1 plus the name) for a host name.
+fn socks4a_request(to: &Endpoint) -> Vec<u8> {
+    let mut req = vec![4, 1];
+    req.extend(to.port.to_be_bytes());
+    let ip = to.host.parse::<Ipv4Addr>().ok();
+    req.extend(ip.unwrap_or(Ipv4Addr::new(0, 0, 0, 1)).octets());
+    req.extend(b"pulsenet\0");
+    if ip.is_none() {
+        req.extend(to.host.as_bytes());
+        req.push(0);
+    }
+    req
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_requests() {
+        let to = Endpoint::parse("canary.example.org:80").unwrap();
+        assert_eq!(to.to_string(), "canary.example.org:80");
+        assert!(Endpoint::parse("canary.example.org").is_err() && Endpoint::parse(":80").is_err());
+        let mut expected = vec![5, 1, 0, 3, 18];
+        expected.extend(b"canary.example.org");
+        expected.extend([0, 80]);
+        assert_eq!(socks5_request(&to), expected);
+        let mut expected = vec![4, 1, 0, 80, 0, 0, 0, 1];
+        expected.extend(b"pulsenet\0canary.example.org\0");
+        assert_eq!(socks4a_request(&to), expected);
+
+        let to = Endpoint::parse("198.51.100.9:8000").unwrap();
+        assert_eq!(socks5_request(&to), [5, 1, 0, 1, 198, 51, 100, 9, 0x1f, 0x40]);
+        assert_eq!(socks4a_request(&to), [&[4, 1, 0x1f, 0x40, 198, 51, 100, 9][..], b"pulsenet\0"].concat());
+    }
+}
@@ -0,0 +1,446 @@
+use crate::tls::{self, CertInfo};
+use rand::RngCore;
+use serde::Serialize;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio_rustls::rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::quic::{self, DirectionalKeys, KeyChange, Keys};
+use tokio_rustls::rustls::Side;
+
+pub const PORT: u16 = 443;
+/// Datagrams carrying a client Initial must be padded to at least this.
+const MIN_DATAGRAM: usize = 1200;
+/// A reserved version (RFC 9000, 15); servers answer it with Version Negotiation.
+const FORCE_NEGOTIATION: u32 = 0x1a2a3a4a;
+const V1: u32 = 0x0000_0001;
+const V2: u32 = 0x6b33_43cf;
+/// Long header packet types in v1
+const INITIAL: u8 = 0;
+const HANDSHAKE: u8 = 2;
+const RETRY: u8 = 3;
+/// Offered by ALPN: HTTP/3 and its last draft
+const ALPN: &[&str] = &["h3", "h3-29"];
+
+/// A QUIC endpoint on UDP/443.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct QuicInfo {
+    /// From Version Negotiation: `v1`, `v2`, `draft-29`, ...
+    pub versions: Vec<String>,
+    /// Protocol agreed by ALPN in the v1 handshake
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    /// ALPN settled on HTTP/3
+    pub http3: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertInfo>,
+    /// Why the server closed the handshake, e.g. when it refused every ALPN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closed: Option<String>,
+}
+
+/// Asks UDP/443 of `ip`, which `sock` is connected to, for Version
+/// Negotiation, which any QUIC server answers, and with v1 on the list runs
+/// the handshake far enough to see the ALPN result and the certificate. None
+/// when nothing answers.
+pub async fn probe(sock: UdpSocket, ip: Ipv4Addr, wait: Duration) -> io::Result<Option<QuicInfo>> {
+    let (dcid, scid) = (random_cid(), random_cid());
+    sock.send(&negotiation_request(&dcid, &scid)).await?;
+    let mut buf = [0u8; 1500];
+    let Ok(Ok(n)) = tokio::time::timeout(wait, sock.recv(&mut buf)).await else { return Ok(None) };
+    let Some(versions) = parse_negotiation(&buf[..n], &scid) else { return Ok(None) };
+    let mut info = QuicInfo {
+        versions: versions.iter().filter_map(|&v| version_name(v)).collect(),
+        alpn: None, http3: false, certificate: None, closed: None,
+    };
+    if versions.contains(&V1) {
+        tokio::time::timeout(wait, handshake(&sock, ip, &mut info)).await.ok();
+        info.http3 = info.alpn.as_deref().is_some_and(|p| p.starts_with("h3"));
+    }
+    Ok(Some(info))
+}
+
+/// Sends the client Initial (again after a Retry) and reads Initial and
+/// Handshake packets until the certificate shows up. The handshake is left
+/// unfinished; the server gives up on it after its idle timeout.
+async fn handshake(sock: &UdpSocket, ip: Ipv4Addr, info: &mut QuicInfo) -> io::Result<()> {
+    let suite = TLS13_AES_128_GCM_SHA256.tls13().and_then(|s| s.quic_suite()).ok_or(io::ErrorKind::Unsupported)?;
+    let (mut dcid, scid) = (random_cid(), random_cid());
+    let config = Arc::new(tls::client_config(ALPN)?);
+    let mut conn = quic::ClientConnection::new(config, quic::Version::V1, ServerName::IpAddress(ip.into()), transport_parameters(&scid))
+        .map_err(io::Error::other)?;
+    let mut hello = Vec::new();
+    conn.write_hs(&mut hello);
+    let mut initial = suite.keys(&dcid, Side::Client, quic::Version::V1);
+    sock.send(&initial_packet(&initial.local, &dcid, &scid, &[], &hello)?).await?;
+
+    let mut handshake: Option<Keys> = None;
+    let mut streams = [CryptoStream::default(), CryptoStream::default()];
+    let mut retried = false;
+    let mut buf = [0u8; 1500];
+    loop {
+        let n = sock.recv(&mut buf).await?;
+        let mut at = 0;
+        while let Some(packet) = Packet::parse(&buf[at..n]) {
+            let len = packet.len;
+            match packet.kind {
+                RETRY if !retried => {
+                    // Start over towards the connection ID the server picked, with its token
+                    retried = true;
+                    dcid = packet.scid.clone();
+                    initial = suite.keys(&dcid, Side::Client, quic::Version::V1);
+                    let token = packet.token.clone();
+                    sock.send(&initial_packet(&initial.local, &dcid, &scid, &token, &hello)?).await?;
+                    break;
+                }
+                INITIAL | HANDSHAKE => {
+                    let space = (packet.kind == HANDSHAKE) as usize;
+                    let keys = if space == 0 { Some(&initial.remote) } else { handshake.as_ref().map(|k| &k.remote) };
+                    let Some(frames) = keys.and_then(|k| open(&mut buf[at..at + len], packet.pn_offset, k)) else { break };
+                    let (data, closed) = parse_frames(&frames);
+                    if let Some(reason) = closed {
+                        info.closed = Some(reason);
+                        return Ok(());
+                    }
+                    for (offset, bytes) in data { streams[space].insert(offset, bytes); }
+                    let ready = streams[space].take();
+                    if !ready.is_empty() {
+                        if let Err(e) = conn.read_hs(&ready) {
+                            info.closed = Some(e.to_string());
+                            return Ok(());
+                        }
+                        let mut out = Vec::new();
+                        while let Some(change) = conn.write_hs(&mut out) {
+                            if let KeyChange::Handshake { keys } = change { handshake = Some(keys); }
+                        }
+                    }
+                    if info.alpn.is_none() { info.alpn = conn.alpn_protocol().map(|p| String::from_utf8_lossy(p).into_owned()); }
+                    if let Some(der) = conn.peer_certificates().and_then(|c| c.first()) {
+                        info.certificate = tls::parse_certificate(der);
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+            at += len;
+        }
+    }
+}
+
+/// Handshake bytes of one packet number space, put back in order.
+#[derive(Default)]
+struct CryptoStream {
+    next: u64,
+    pending: Vec<(u64, Vec<u8>)>,
+}
+
+impl CryptoStream {
+    fn insert(&mut self, offset: u64, data: Vec<u8>) {
+        self.pending.push((offset, data));
+    }
+
+    /// The contiguous bytes from `next` on that haven't been taken yet.
+    fn take(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        loop {
+            let next = self.next;
+            let Some(i) = self.pending.iter().position(|(o, d)| *o <= next && o + d.len() as u64 > next) else { break };
+            let (offset, data) = self.pending.swap_remove(i);
+            out.extend_from_slice(&data[(next - offset) as usize..]);
+            self.next = offset + data.len() as u64;
+        }
+        self.pending.retain(|(o, d)| o + d.len() as u64 > self.next);
+        out
+    }
+}
+
+/// The header fields of one long header packet in a datagram.
+struct Packet {
+    kind: u8,
+    scid: Vec<u8>,
+    token: Vec<u8>,
+    /// Offset of the (protected) packet number
+    pn_offset: usize,
+    /// Bytes up to the end of this packet; coalesced packets follow it
+    len: usize,
+}
+
+impl Packet {
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let mut r = Reader(buf, 0);
+        let first = r.byte()?;
+        if first & 0x80 == 0 || r.u32()? != V1 { return None; }
+        let kind = (first >> 4) & 0x03;
+        let n = r.byte()? as usize;
+        r.take(n)?;
+        let n = r.byte()? as usize;
+        let scid = r.take(n)?.to_vec();
+        if kind == RETRY {
+            // Token runs up to the 16-byte integrity tag, which isn't checked
+            let token = buf.get(r.1..buf.len().checked_sub(16)?)?.to_vec();
+            return Some(Self { kind, scid, token, pn_offset: 0, len: buf.len() });
+        }
+        let token = if kind == INITIAL {
+            let n = r.varint()? as usize;
+            r.take(n)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        let length = r.varint()? as usize;
+        let len = r.1 + length;
+        (len <= buf.len()).then_some(Self { kind, scid, token, pn_offset: r.1, len })
+    }
+}
+
+/// Removes header and packet protection in place; returns the frames.
+fn open(packet: &mut [u8], pn_offset: usize, keys: &DirectionalKeys) -> Option<Vec<u8>> {
+    let sample: Vec<u8> = packet.get(pn_offset + 4..pn_offset + 4 + keys.header.sample_len())?.to_vec();
+    let (head, rest) = packet.split_at_mut(pn_offset);
+    keys.header.decrypt_in_place(&sample, &mut head[0], &mut rest[..4]).ok()?;
+    let pn_len = (packet[0] & 0x03) as usize + 1;
+    let pn = packet[pn_offset..pn_offset + pn_len].iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+    let (header, payload) = packet.split_at_mut(pn_offset + pn_len);
+    keys.packet.decrypt_in_place(pn, header, payload).ok().map(<[u8]>::to_vec)
+}
+
+/// CRYPTO frame contents by offset, and the reason of a CONNECTION_CLOSE.
+fn parse_frames(frames: &[u8]) -> (Vec<(u64, Vec<u8>)>, Option<String>) {
+    let mut r = Reader(frames, 0);
+    let mut data = Vec::new();
+    while let Some(kind) = r.varint() {
+        match kind {
+            0x00 | 0x01 => {} // PADDING, PING
+            0x02 | 0x03 => {
+                // ACK: largest, delay, range count, first range, ranges, ECN counts
+                let parsed = (|| {
+                    r.varint()?;
+                    r.varint()?;
+                    let ranges = r.varint()?;
+                    r.varint()?;
+                    for _ in 0..ranges * 2 { r.varint()?; }
+                    if kind == 0x03 { for _ in 0..3 { r.varint()?; } }
+                    Some(())
+                })();
+                if parsed.is_none() { break; }
+            }
+            0x06 => {
+                let Some((offset, n)) = r.varint().zip(r.varint()) else { break };
+                let Some(bytes) = r.take(n as usize) else { break };
+                data.push((offset, bytes.to_vec()));
+            }
+            0x1c | 0x1d => {
+                let code = r.varint().unwrap_or(0);
+                if kind == 0x1c { r.varint(); }
+                let reason = r.varint().and_then(|n| r.take(n as usize)).map(String::from_utf8_lossy).unwrap_or_default();
+                // 0x0100 + a TLS alert: 0x178 is no_application_protocol
+                let text = match code {
+                    0x178 => "no application protocol".to_string(),
+                    0x100..=0x1ff => format!("TLS alert {}", code - 0x100),
+                    _ => format!("error 0x{:x}", code),
+                };
+                return (data, Some(if reason.is_empty() { text } else { format!("{}: {}", text, reason) }));
+            }
+            _ => break,
+        }
+    }
+    (data, None)
+}
+
+/// A client Initial carrying `hello` in a CRYPTO frame, padded to a full datagram.
+fn initial_packet(keys: &DirectionalKeys, dcid: &[u8], scid: &[u8], token: &[u8], hello: &[u8]) -> io::Result<Vec<u8>> {
+    const PN_LEN: usize = 2;
+    let mut header = vec![0xc0 | (PN_LEN as u8 - 1)];
+    header.extend(V1.to_be_bytes());
+    header.push(dcid.len() as u8);
+    header.extend(dcid);
+    header.push(scid.len() as u8);
+    header.extend(scid);
+    put_varint(&mut header, token.len() as u64);
+    header.extend(token);
+    let tag = keys.packet.tag_len();
+    // The two-byte length encoding is fixed, so the header size is known up front
+    let payload_len = MIN_DATAGRAM.saturating_sub(header.len() + 2 + PN_LEN + tag).max(hello.len() + 8);
+    header.extend((0x4000 | (PN_LEN + payload_len + tag) as u16).to_be_bytes());
+    let pn_offset = header.len();
+    header.extend([0; PN_LEN]);
+
+    let mut payload = vec![0x06, 0x00];
+    put_varint(&mut payload, hello.len() as u64);
+    payload.extend(hello);
+    payload.resize(payload_len, 0); // PADDING frames
+    let tag = keys.packet.encrypt_in_place(0, &header, &mut payload).map_err(io::Error::other)?;
+    let mut packet = header;
+    packet.extend(payload);
+    packet.extend(tag.as_ref());
+
+    let sample = packet[pn_offset + 4..pn_offset + 4 + keys.header.sample_len()].to_vec();
+    let (head, rest) = packet.split_at_mut(pn_offset);
+    keys.header.encrypt_in_place(&sample, &mut head[0], &mut rest[..PN_LEN]).map_err(io::Error::other)?;
+    Ok(packet)
+}
+
+/// A long header packet with a version no server speaks, padded like an Initial.
+fn negotiation_request(dcid: &[u8], scid: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0xc0];
+    packet.extend(FORCE_NEGOTIATION.to_be_bytes());
+    packet.push(dcid.len() as u8);
+    packet.extend(dcid);
+    packet.push(scid.len() as u8);
+    packet.extend(scid);
+    packet.resize(MIN_DATAGRAM, 0);
+    packet
+}
+
+/// Versions listed in a Version Negotiation packet addressed to `scid`.
+fn parse_negotiation(buf: &[u8], scid: &[u8]) -> Option<Vec<u32>> {
+    let mut r = Reader(buf, 0);
+    if r.byte()? & 0x80 == 0 || r.u32()? != 0 { return None; }
+    let n = r.byte()? as usize;
+    if r.take(n)? != scid { return None; }
+    let n = r.byte()? as usize;
+    r.take(n)?;
+    let mut versions = Vec::new();
+    while let Some(v) = r.u32() { versions.push(v); }
+    Some(versions)
+}
+
+/// None for the reserved greasing versions servers mix into the list.
+fn version_name(v: u32) -> Option<String> {
+    Some(match v {
+        _ if v & 0x0f0f_0f0f == 0x0a0a_0a0a => return None,
+        V1 => "v1".into(),
+        V2 => "v2".into(),
+        0xff00_0001..=0xff00_00ff => format!("draft-{}", v & 0xff),
+        _ => format!("0x{:08x}", v),
+    })
+}
+
+/// Just the client's initial_source_connection_id, which v1 requires, and
+/// a short max_idle_timeout so the server drops the half-open handshake soon.
+fn transport_parameters(scid: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (id, value) in [(0x0f, scid.to_vec()), (0x01, vec![0x44, 0xb8])] { // 1200ms
+        put_varint(&mut out, id);
+        put_varint(&mut out, value.len() as u64);
+        out.extend(value);
+    }
+    out
+}
+
+fn random_cid() -> Vec<u8> {
+    let mut cid = vec![0u8; 8];
+    rand::thread_rng().fill_bytes(&mut cid);
+    cid
+}
+
+fn put_varint(out: &mut Vec<u8>, v: u64) {
+    match v {
+        0..=0x3f => out.push(v as u8),
+        0x40..=0x3fff => out.extend((0x4000 | v as u16).to_be_bytes()),
+        0x4000..=0x3fff_ffff => out.extend((0x8000_0000 | v as u32).to_be_bytes()),
+        _ => out.extend((0xc000_0000_0000_0000 | v).to_be_bytes()),
+    }
+}
+
+struct Reader<'a>(&'a [u8], usize);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let out = self.0.get(self.1..self.1.checked_add(n)?)?;
+        self.1 += n;
+        Some(out)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// RFC 9000 variable-length integer: the top two bits give its size.
+    fn varint(&mut self) -> Option<u64> {
+        let first = self.byte()?;
+        let rest = self.take((1 << (first >> 6)) - 1)?;
+        Some(rest.iter().fold((first & 0x3f) as u64, |acc, b| acc << 8 | *b as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn initial_keys(dcid: &[u8]) -> (Keys, Keys) {
+        let suite = TLS13_AES_128_GCM_SHA256.tls13().and_then(|s| s.quic_suite()).unwrap();
+        (suite.keys(dcid, Side::Client, quic::Version::V1), suite.keys(dcid, Side::Server, quic::Version::V1))
+    }
+    #[test]
+    fn test_varints() {
+        // RFC 9000 A.1 examples
+        let mut out = Vec::new();
+        for v in [37, 15293, 494878333, 151288809941952652] { put_varint(&mut out, v); }
+        assert_eq!(out[..3], [0x25, 0x7b, 0xbd]);
+        let mut r = Reader(&out, 0);
+        assert_eq!([r.varint(), r.varint(), r.varint(), r.varint()], [Some(37), Some(15293), Some(494878333), Some(151288809941952652)]);
+        assert_eq!(r.varint(), None);
+    }
+    #[test]
+    fn test_version_negotiation() {
+        let mut vn = vec![0x80, 0, 0, 0, 0, 0, 2, 0xab, 0xcd];
+        vn.extend([0, 0, 0, 1, 0x6b, 0x33, 0x43, 0xcf, 0x1a, 0x2a, 0x3a, 0x4a, 0xff, 0, 0, 0x1d]);
+        let versions = parse_negotiation(&vn, &[]).unwrap();
+        assert_eq!(versions.iter().filter_map(|&v| version_name(v)).collect::<Vec<_>>(), vec!["v1", "v2", "draft-29"]);
+        // Addressed to another connection
+        assert!(parse_negotiation(&vn, &[1]).is_none());
+    }
+    #[test]
+    fn test_initial_packet() {
+        // What the client seals, the server's initial keys (same DCID) open
+        let dcid = [0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+        let (client, server) = initial_keys(&dcid);
+        let mut packet = initial_packet(&client.local, &dcid, b"scid", b"tok", b"hello").unwrap();
+        assert_eq!(packet.len(), MIN_DATAGRAM);
+        let header = Packet::parse(&packet).unwrap();
+        assert_eq!((header.kind, header.token.as_slice(), header.len), (INITIAL, &b"tok"[..], MIN_DATAGRAM));
+        let frames = open(&mut packet, header.pn_offset, &server.remote).unwrap();
+        assert_eq!(parse_frames(&frames), (vec![(0, b"hello".to_vec())], None));
+        let mut tampered = initial_packet(&client.local, &dcid, b"scid", b"tok", b"hello").unwrap();
+        tampered[100] ^= 1;
+        assert!(open(&mut tampered, header.pn_offset, &server.remote).is_none());
+    }
+    #[test]
+    fn test_retry_packet() {
+        let mut retry = vec![0xf0];
+        retry.extend(V1.to_be_bytes());
+        retry.extend([4, 1, 2, 3, 4, 3, 0xaa, 0xbb, 0xcc]);
+        retry.extend(b"token");
+        retry.extend([0; 16]);
+        let packet = Packet::parse(&retry).unwrap();
+        assert_eq!((packet.kind, packet.scid.as_slice(), packet.token.as_slice(), packet.len), (RETRY, &[0xaa, 0xbb, 0xcc][..], &b"token"[..], retry.len()));
+        // Too short to hold the integrity tag
+        assert!(Packet::parse(&retry[..20]).is_none());
+    }
+    #[test]
+    fn test_crypto_reassembly() {
+        let mut stream = CryptoStream::default();
+        stream.insert(3, b"lo".to_vec());
+        assert!(stream.take().is_empty());
+        stream.insert(0, b"hel".to_vec());
+        assert_eq!(stream.take(), b"hello");
+        // A retransmission overlapping what was taken only adds the new bytes
+        stream.insert(4, b"o!".to_vec());
+        stream.insert(0, b"hel".to_vec());
+        assert_eq!(stream.take(), b"!");
+        assert!(stream.pending.is_empty());
+    }
+    #[test]
+    fn test_connection_close() {
+        assert_eq!(parse_frames(&[0x1c, 0x41, 0x78, 0x06, 0x00]).1.as_deref(), Some("no application protocol"));
+        let mut close = vec![0x1d, 0x41, 0x28, 0x03];
+        close.extend(b"bad");
+        assert_eq!(parse_frames(&close).1.as_deref(), Some("TLS alert 40: bad"));
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{bail, Result};
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the controller re-evaluates the send rate.
+const WINDOW: Duration = Duration::from_secs(1);
+/// Minimum probes per window before a decision is made.
+const MIN_WINDOW_SAMPLES: u32 = 20;
+/// Error ratio increase over the baseline that counts as congestion.
+const CONGESTION_MARGIN: f64 = 0.15;
+const INCREASE_STEP: f64 = 0.10;
+const DECREASE_FACTOR: f64 = 0.5;
+
+struct Feedback {
+    window_start: Instant,
+    ok: u32,
+    errors: u32,
+    /// EWMA of the error ratio seen while not congested. Random internet
+    /// targets mostly time out, so congestion is judged relative to this.
+    baseline: Option<f64>,
+}
+
+/// Congestion-control style send rate: additive increase while the
+/// timeout/unreachable ratio stays near its baseline, multiplicative
+/// decrease when it spikes. Also paces dispatch at the current rate.
+pub struct AdaptiveRate {
+    rate: AtomicU32,
+    min: u32,
+    max: u32,
+    feedback: Mutex<Feedback>,
+    next_slot: Mutex<Instant>,
+}
+
+impl AdaptiveRate {
+    pub fn new(initial: u32, min: u32, max: u32) -> Self {
+        let min = min.max(1);
+        Self {
+            rate: AtomicU32::new(initial.clamp(min, max.max(min))),
+            min,
+            max: max.max(min),
+            feedback: Mutex::new(Feedback { window_start: Instant::now(), ok: 0, errors: 0, baseline: None }),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn current(&self) -> u32 { self.rate.load(Ordering::Relaxed) }
+
+    pub async fn until_ready(&self) {
+        let interval = Duration::from_secs_f64(1.0 / self.current() as f64);
+        let slot = {
+            let mut next = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+
+    /// Feed back a probe outcome; `error` is true for timeouts/unreachable.
+    pub fn record(&self, error: bool) {
+        let mut fb = self.feedback.lock().unwrap();
+        if error { fb.errors += 1; } else { fb.ok += 1; }
+        let total = fb.ok + fb.errors;
+        if fb.window_start.elapsed() < WINDOW || total < MIN_WINDOW_SAMPLES { return; }
+
+        let ratio = fb.errors as f64 / total as f64;
+        let rate = self.current() as f64;
+        let new_rate = match fb.baseline {
+            Some(base) if ratio > base + CONGESTION_MARGIN => rate * DECREASE_FACTOR,
+            Some(base) => {
+                fb.baseline = Some(base * 0.8 + ratio * 0.2);
+                rate + (rate * INCREASE_STEP).max(1.0)
+            }
+            None => {
+                fb.baseline = Some(ratio);
+                rate
+            }
+        };
+        self.rate.store((new_rate as u32).clamp(self.min, self.max), Ordering::Relaxed);
+        fb.ok = 0;
+        fb.errors = 0;
+        fb.window_start = Instant::now();
+    }
+}
+
+/// Politeness cap per destination subnet on top of the global rate, from
+/// `10/s@/24` (rate per second or `/m` per minute, subnet defaults to /24).
+pub struct NetRate {
+    prefix: u8,
+    limiter: DefaultKeyedRateLimiter<u32>,
+}
+
+impl NetRate {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid --rate-per-net '{}', expected e.g. 10/s@/24", spec);
+        let (rate, net) = spec.split_once('@').unwrap_or((spec, "/24"));
+        let (count, unit) = rate.split_once('/').unwrap_or((rate, "s"));
+        let count = count.trim().parse::<u32>().ok().and_then(NonZeroU32::new).ok_or_else(invalid)?;
+        let quota = match unit.trim() {
+            "s" | "sec" => Quota::per_second(count),
+            "m" | "min" => Quota::per_minute(count),
+            _ => return Err(invalid()),
+        };
+        let prefix = net.trim().trim_start_matches('/').parse::<u8>().map_err(|_| invalid())?;
+        if !(8..=32).contains(&prefix) { bail!("--rate-per-net subnet must be between /8 and /32"); }
+        Ok(Self { prefix, limiter: RateLimiter::keyed(quota) })
+    }
+
+    fn key(&self, ip: Ipv4Addr) -> u32 {
+        u32::from(ip) & (u32::MAX << (32 - self.prefix as u32))
+    }
+
+    pub async fn until_ready(&self, ip: Ipv4Addr) {
+        self.limiter.until_key_ready(&self.key(ip)).await;
+    }
+}
+
+/// Random gaps between dispatches (--jitter), across the whole scan or per
+/// destination subnet, so probes don't go out in evenly paced bursts.
+pub struct Jitter {
+    min: Duration,
+    max: Duration,
+    /// None: one gap sequence for every target
+    prefix: Option<u8>,
+    next_slot: Mutex<HashMap<u32, Instant>>,
+}
+
+fn parse_delay(s: &str, default_unit: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n.parse().ok()?;
+    match if unit.is_empty() { default_unit } else { unit } {
+        "ms" => Some(Duration::from_millis(n)),
+        "s" => Some(Duration::from_secs(n)),
+        _ => None,
+    }
+}
+
+/// `500ms`, `90s`, `30m` or `2h`; a bare number takes `default_unit`.
+pub fn parse_duration(s: &str, default_unit: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (n, secs) = if let Some(n) = s.strip_suffix('h') {
+        (n, 3600)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60)
+    } else {
+        return parse_delay(s, default_unit);
+    };
+    // Too many hours to count is as invalid as no number at all
+    n.parse::<u64>().ok()?.checked_mul(secs).map(Duration::from_secs)
+}
+
+impl Jitter {
+    /// `50-500ms`, `1-3s` or `200ms` (meaning 0-200ms), optionally `@/24` for
+    /// a separate gap sequence per subnet.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid --jitter '{}', expected e.g. 50-500ms or 1-3s@/24", spec);
+        let (range, net) = match spec.split_once('@') {
+            Some((range, net)) => (range, Some(net)),
+            None => (spec, None),
+        };
+        let (lo, hi) = range.split_once('-').unwrap_or(("0", range));
+        // The unit on the upper bound applies to a bare lower bound
+        let unit = hi.trim().trim_start_matches(|c: char| c.is_ascii_digit());
+        let (min, max) = parse_delay(lo, unit).zip(parse_delay(hi, unit)).ok_or_else(invalid)?;
+        if min > max || max.is_zero() { return Err(invalid()); }
+        let prefix = match net {
+            Some(net) => {
+                let prefix = net.trim().trim_start_matches('/').parse::<u8>().map_err(|_| invalid())?;
+                if !(8..=32).contains(&prefix) { bail!("--jitter subnet must be between /8 and /32"); }
+                Some(prefix)
+            }
+            None => None,
+        };
+        Ok(Self { min, max, prefix, next_slot: Mutex::new(HashMap::new()) })
+    }
+
+    fn key(&self, ip: Ipv4Addr) -> u32 {
+        self.prefix.map_or(0, |prefix| u32::from(ip) & (u32::MAX << (32 - prefix as u32)))
+    }
+
+    /// Waits for this target's turn; the next one (in its subnet) goes out a
+    /// random gap later.
+    pub async fn wait(&self, ip: Ipv4Addr) {
+        let gap = rand::thread_rng().gen_range(self.min..=self.max);
+        let slot = {
+            let mut slots = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            // Subnets that have gone quiet don't need their slot any more
+            if slots.len() > 100_000 { slots.retain(|_, slot| *slot > now); }
+            let next = slots.entry(self.key(ip)).or_insert(now);
+            let slot = (*next).max(now);
+            *next = slot + gap;
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// Subnet size watched for an IPS engaging.
+const DEFENSE_PREFIX: u8 = 24;
+/// Answers other than a rejection a subnet must have given before a run of
+/// rejections counts as sudden; a network that refuses everything from the
+/// start is just closed.
+const CALM_BEFORE: u32 = 10;
+/// Consecutive targets rejecting every port that make a RST storm.
+const STORM_RUN: u32 = 20;
+/// ICMP admin-prohibited targets in one subnet that engage the backoff alone.
+const PROHIBITED_BURST: u32 = 5;
+/// Dispatch gap for a slowed subnet.
+const SLOWED_GAP: Duration = Duration::from_secs(1);
+
+/// What --ips-backoff did to a subnet that started rejecting probes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reaction {
+    Slowed,
+    Skipped,
+}
+
+impl Reaction {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Slowed => "slowed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Default)]
+struct NetWatch {
+    answered: u32,
+    streak: u32,
+    prohibited: u32,
+    reaction: Option<Reaction>,
+    next_slot: Option<Instant>,
+}
+
+/// Watches each /24 for the signs of an IPS engaging mid-scan: a sudden run
+/// of targets that RST every port, or a burst of ICMP admin-prohibited. Such
+/// a subnet is then slowed to one target per second, or skipped.
+pub struct Defense {
+    skip: bool,
+    nets: Mutex<HashMap<u32, NetWatch>>,
+}
+
+impl Defense {
+    pub fn new(skip: bool) -> Self {
+        Self { skip, nets: Mutex::new(HashMap::new()) }
+    }
+
+    fn key(ip: Ipv4Addr) -> u32 {
+        u32::from(ip) & (u32::MAX << (32 - DEFENSE_PREFIX as u32))
+    }
+
+    /// One target's outcome: `rejected` when every port answered with a RST,
+    /// `prohibited` when an ICMP admin-prohibited came back. Returns the
+    /// subnet and the reaction when this outcome engages the backoff.
+    pub fn record(&self, ip: Ipv4Addr, rejected: bool, prohibited: bool) -> Option<(ipnet::Ipv4Net, Reaction)> {
+        let mut nets = self.nets.lock().unwrap();
+        // Subnets with nothing pending are cheap to forget in a random scan
+        if nets.len() > 100_000 { nets.retain(|_, w| w.reaction.is_some() || w.streak > 0 || w.prohibited > 0); }
+        let key = Self::key(ip);
+        let watch = nets.entry(key).or_default();
+        if watch.reaction.is_some() { return None; }
+        if prohibited { watch.prohibited += 1; }
+        if rejected {
+            watch.streak += 1;
+        } else if !prohibited {
+            watch.answered += 1;
+            watch.streak = 0;
+        }
+        let storm = watch.answered >= CALM_BEFORE && watch.streak >= STORM_RUN;
+        if !storm && watch.prohibited < PROHIBITED_BURST { return None; }
+        let reaction = if self.skip { Reaction::Skipped } else { Reaction::Slowed };
+        watch.reaction = Some(reaction);
+        Some((ipnet::Ipv4Net::new(Ipv4Addr::from(key), DEFENSE_PREFIX).ok()?, reaction))
+    }
+
+    pub fn skips(&self, ip: Ipv4Addr) -> bool {
+        self.nets.lock().unwrap().get(&Self::key(ip)).is_some_and(|w| w.reaction == Some(Reaction::Skipped))
+    }
+
+    /// Waits for a slowed subnet's next slot; false when its subnet is skipped.
+    pub async fn admit(&self, ip: Ipv4Addr) -> bool {
+        let slot = {
+            let mut nets = self.nets.lock().unwrap();
+            let Some(watch) = nets.get_mut(&Self::key(ip)) else { return true };
+            match watch.reaction {
+                None => return true,
+                Some(Reaction::Skipped) => return false,
+                Some(Reaction::Slowed) => {
+                    let now = Instant::now();
+                    let slot = watch.next_slot.unwrap_or(now).max(now);
+                    watch.next_slot = Some(slot + SLOWED_GAP);
+                    slot
+                }
+            }
+        };
+        tokio::time::sleep_until(slot.into()).await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulates one full window with the given outcome counts (err >= 1).
+    fn feed(ctl: &AdaptiveRate, ok: u32, err: u32) {
+        {
+            let mut fb = ctl.feedback.lock().unwrap();
+            fb.ok = ok;
+            fb.errors = err - 1;
+            fb.window_start -= WINDOW;
+        }
+        ctl.record(true);
+    }
+
+    #[test]
+    fn test_ramp_up_and_back_off() {
+        let ctl = AdaptiveRate::new(100, 10, 1000);
+        feed(&ctl, 50, 50); // establishes baseline
+        assert_eq!(ctl.current(), 100);
+        feed(&ctl, 50, 50);
+        assert_eq!(ctl.current(), 110);
+        feed(&ctl, 0, 100); // error ratio spikes
+        assert_eq!(ctl.current(), 55);
+    }
+
+    #[test]
+    fn test_net_rate_spec() {
+        let net = NetRate::parse("10/s@/24").unwrap();
+        assert_eq!(net.key(Ipv4Addr::new(203, 0, 113, 77)), u32::from(Ipv4Addr::new(203, 0, 113, 0)));
+        assert_eq!(NetRate::parse("30/m@16").unwrap().prefix, 16);
+        assert_eq!(NetRate::parse("5").unwrap().prefix, 24);
+        assert!(NetRate::parse("0/s").is_err() && NetRate::parse("10/h").is_err() && NetRate::parse("10/s@/4").is_err());
+        // The first probe of a subnet goes out right away, the quota is per subnet
+        assert!(net.limiter.check_key(&net.key(Ipv4Addr::new(198, 51, 100, 1))).is_ok());
+    }
+
+    #[test]
+    fn test_jitter_spec() {
+        let j = Jitter::parse("50-500ms").unwrap();
+        assert_eq!((j.min, j.max, j.prefix), (Duration::from_millis(50), Duration::from_millis(500), None));
+        let j = Jitter::parse("1-3s@/24").unwrap();
+        assert_eq!((j.min, j.max, j.prefix), (Duration::from_secs(1), Duration::from_secs(3), Some(24)));
+        assert_eq!(j.key(Ipv4Addr::new(203, 0, 113, 77)), u32::from(Ipv4Addr::new(203, 0, 113, 0)));
+        assert_eq!(Jitter::parse("200ms").unwrap().min, Duration::ZERO);
+        assert_eq!(Jitter::parse("500ms-2s").unwrap().max, Duration::from_secs(2));
+        for bad in ["500-50ms", "0ms", "fast", "1-3h", "10-20ms@/4"] {
+            assert!(Jitter::parse(bad).is_err(), "{}", bad);
+        }
+        assert_eq!(parse_duration("30m", "s"), Some(Duration::from_secs(1800)));
+        assert_eq!((parse_duration("2h", "s"), parse_duration("90", "s")), (Some(Duration::from_secs(7200)), Some(Duration::from_secs(90))));
+        assert_eq!(parse_duration("250ms", "s"), Some(Duration::from_millis(250)));
+        assert_eq!((parse_duration("99999999999999999h", "s"), parse_duration("99999999999999999999m", "s")), (None, None));
+    }
+
+    #[test]
+    fn test_defense_engages_on_sudden_rejections() {
+        let defense = Defense::new(false);
+        let ip = |host: u8| Ipv4Addr::new(198, 51, 100, host);
+        // A subnet refusing everything from the start is only closed
+        let closed = Defense::new(true);
+        for host in 0..40 { assert_eq!(closed.record(Ipv4Addr::new(192, 0, 2, host), true, false), None); }
+        for host in 0..CALM_BEFORE as u8 { assert_eq!(defense.record(ip(host), false, false), None); }
+        for host in 0..STORM_RUN as u8 - 1 { assert_eq!(defense.record(ip(100 + host), true, false), None); }
+        let (net, reaction) = defense.record(ip(200), true, false).unwrap();
+        assert_eq!((net.to_string(), reaction), ("198.51.100.0/24".to_string(), Reaction::Slowed));
+        assert_eq!(defense.record(ip(201), true, false), None);
+        let skip = Defense::new(true);
+        for host in 0..PROHIBITED_BURST as u8 { skip.record(Ipv4Addr::new(203, 0, 113, host), false, true); }
+        assert!(skip.skips(Ipv4Addr::new(203, 0, 113, 99)) && !skip.skips(ip(1)));
+    }
+}
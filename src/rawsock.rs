@@ -0,0 +1,81 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+/// Opens a raw IPv4 socket for `proto`. Needs root or CAP_NET_RAW.
+pub fn open_raw(proto: Protocol) -> io::Result<Socket> {
+    Socket::new(Domain::IPV4, Type::RAW, Some(proto))
+}
+
+/// Whether this process may open raw sockets (privilege detection for raw-socket modes).
+pub fn has_raw_access() -> bool {
+    cfg!(unix) && open_raw(Protocol::ICMPV4).is_ok()
+}
+
+/// Local address the kernel would use to reach `dst`. Connecting a UDP
+/// socket only consults the routing table, nothing is sent.
+pub fn local_addr_for(dst: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    let sock = UdpSocket::bind("0.0.0.0:0")?;
+    sock.connect(SocketAddr::new(dst.into(), 9))?;
+    match sock.local_addr()? {
+        SocketAddr::V4(a) => Ok(*a.ip()),
+        SocketAddr::V6(_) => Err(io::Error::other("no IPv4 route")),
+    }
+}
+
+/// RFC 1071 internet checksum.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 { sum = (sum & 0xffff) + (sum >> 16); }
+    !(sum as u16)
+}
+
+/// Checksum of a TCP/UDP segment including the IPv4 pseudo-header.
+pub fn transport_checksum(src: Ipv4Addr, dst: Ipv4Addr, proto: u8, segment: &[u8]) -> u16 {
+    let mut buf = Vec::with_capacity(12 + segment.len());
+    buf.extend_from_slice(&src.octets());
+    buf.extend_from_slice(&dst.octets());
+    buf.extend_from_slice(&[0, proto]);
+    buf.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    buf.extend_from_slice(segment);
+    checksum(&buf)
+}
+
+/// The fields of a received IPv4 packet the probes care about.
+pub struct Ipv4Packet<'a> {
+    pub ttl: u8,
+    pub protocol: u8,
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub payload: &'a [u8],
+}
+
+pub fn parse_ipv4(buf: &[u8]) -> Option<Ipv4Packet<'_>> {
+    if buf.len() < 20 || buf[0] >> 4 != 4 { return None; }
+    let ihl = (buf[0] & 0x0f) as usize * 4;
+    let total = (u16::from_be_bytes([buf[2], buf[3]]) as usize).clamp(ihl, buf.len());
+    if ihl < 20 || buf.len() < ihl { return None; }
+    Some(Ipv4Packet {
+        ttl: buf[8],
+        protocol: buf[9],
+        src: Ipv4Addr::new(buf[12], buf[13], buf[14], buf[15]),
+        dst: Ipv4Addr::new(buf[16], buf[17], buf[18], buf[19]),
+        payload: &buf[ihl..total],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_checksum() {
+        // Example header from RFC 1071 discussions / Wikipedia
+        let hdr = [0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00,
+                   0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7];
+        assert_eq!(checksum(&hdr), 0xb861);
+        let pkt = parse_ipv4(&hdr).unwrap();
+        assert_eq!((pkt.ttl, pkt.protocol, pkt.dst), (64, 17, Ipv4Addr::new(192, 168, 0, 199)));
+    }
+}
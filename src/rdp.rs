@@ -0,0 +1,126 @@
+use crate::tls;
+use serde::Serialize;
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const PROTOCOL_RDP: u32 = 0;
+const PROTOCOL_SSL: u32 = 1;
+const PROTOCOL_HYBRID: u32 = 2;
+const PROTOCOL_HYBRID_EX: u32 = 8;
+const HYBRID_REQUIRED_BY_SERVER: u32 = 5;
+
+/// RDP security negotiation results.
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
+pub struct RdpInfo {
+    /// Security protocols the server accepted: `rdp`, `tls`, `credssp`, `credssp_early_auth`
+    pub protocols: Vec<&'static str>,
+    /// Network Level Authentication: plain RDP and TLS were both refused
+    pub nla_required: bool,
+    /// Certificate common name, usually the machine name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Negotiation {
+    Selected(u32),
+    Failed(u32),
+    /// Legacy server that ignores the negotiation request (standard RDP security only)
+    Legacy,
+}
+
+/// Sends X.224 connection requests offering different security protocols,
+/// one connection each, then reads the certificate over TLS.
+pub async fn inspect<F, Fut>(ip: Ipv4Addr, connect: F, wait: Duration) -> io::Result<RdpInfo>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let mut info = RdpInfo::default();
+    let connect = &connect;
+    let attempt = |requested: u32| async move {
+        let mut stream = timeout(wait, connect()).await.map_err(|_| io::ErrorKind::TimedOut)??;
+        let result = timeout(wait, negotiate(&mut stream, requested)).await.map_err(|_| io::ErrorKind::TimedOut)??;
+        Ok::<_, io::Error>((stream, result))
+    };
+
+    // Everything at once: the server picks its preferred protocol, and CredSSP runs inside TLS too
+    let (stream, best) = attempt(PROTOCOL_SSL | PROTOCOL_HYBRID | PROTOCOL_HYBRID_EX).await?;
+    if let Negotiation::Selected(p) = best {
+        if p & PROTOCOL_HYBRID != 0 { info.protocols.push("credssp"); }
+        if p & PROTOCOL_HYBRID_EX != 0 { info.protocols.extend(["credssp", "credssp_early_auth"]); }
+        if p != PROTOCOL_RDP && let Ok(Ok(tls_stream)) = timeout(wait, tls::connect(stream, ip, None)).await {
+            info.hostname = tls::peer_certificate(&tls_stream).and_then(|c| c.subject_cn);
+        }
+    }
+    let ssl = attempt(PROTOCOL_SSL).await.map(|(_, n)| n);
+    let rdp = attempt(PROTOCOL_RDP).await.map(|(_, n)| n);
+    if matches!(ssl, Ok(Negotiation::Selected(_))) { info.protocols.push("tls"); }
+    if matches!(rdp, Ok(Negotiation::Selected(_) | Negotiation::Legacy)) || best == Negotiation::Legacy { info.protocols.push("rdp"); }
+    info.protocols.sort();
+    info.protocols.dedup();
+    info.nla_required = matches!(ssl, Ok(Negotiation::Failed(HYBRID_REQUIRED_BY_SERVER)))
+        && matches!(rdp, Ok(Negotiation::Failed(HYBRID_REQUIRED_BY_SERVER)));
+    Ok(info)
+}
+
+/// TPKT + X.224 Connection Request carrying an RDP_NEG_REQ.
+fn connection_request(requested: u32) -> Vec<u8> {
+    let cookie = b"Cookie: mstshash=pulsenet\r\n";
+    let mut x224 = vec![0u8, 0xe0, 0, 0, 0, 0, 0];
+    x224.extend_from_slice(cookie);
+    x224.extend_from_slice(&[0x01, 0x00, 0x08, 0x00]);
+    x224.extend_from_slice(&requested.to_le_bytes());
+    x224[0] = (x224.len() - 1) as u8;
+    let mut pkt = vec![3, 0];
+    pkt.extend_from_slice(&((x224.len() + 4) as u16).to_be_bytes());
+    pkt.extend(x224);
+    pkt
+}
+
+async fn negotiate<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, requested: u32) -> io::Result<Negotiation> {
+    stream.write_all(&connection_request(requested)).await?;
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[2], header[3]]) as usize;
+    if header[0] != 3 || !(11..=512).contains(&len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a TPKT response"));
+    }
+    let mut body = vec![0u8; len - 4];
+    stream.read_exact(&mut body).await?;
+    parse_confirm(&body).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an X.224 connection confirm"))
+}
+
+fn parse_confirm(x224: &[u8]) -> Option<Negotiation> {
+    if x224.len() < 7 || x224[1] & 0xf0 != 0xd0 { return None; }
+    let Some(neg) = x224.get(7..15) else { return Some(Negotiation::Legacy) };
+    let value = u32::from_le_bytes([neg[4], neg[5], neg[6], neg[7]]);
+    match neg[0] {
+        0x02 => Some(Negotiation::Selected(value)),
+        0x03 => Some(Negotiation::Failed(value)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_request_and_confirm() {
+        let req = connection_request(PROTOCOL_SSL | PROTOCOL_HYBRID);
+        assert_eq!(u16::from_be_bytes([req[2], req[3]]) as usize, req.len());
+        assert_eq!(req[4] as usize, req.len() - 5);
+        assert_eq!(&req[req.len() - 8..], &[0x01, 0, 0x08, 0, 0x03, 0, 0, 0]);
+
+        let confirm = [0x0e, 0xd0, 0, 0, 0x12, 0x34, 0, 0x02, 0x1f, 0x08, 0, 0x02, 0, 0, 0];
+        assert_eq!(parse_confirm(&confirm), Some(Negotiation::Selected(PROTOCOL_HYBRID)));
+        let failure = [0x0e, 0xd0, 0, 0, 0x12, 0x34, 0, 0x03, 0, 0x08, 0, 0x05, 0, 0, 0];
+        assert_eq!(parse_confirm(&failure), Some(Negotiation::Failed(HYBRID_REQUIRED_BY_SERVER)));
+        assert_eq!(parse_confirm(&[0x06, 0xd0, 0, 0, 0x12, 0x34, 0]), Some(Negotiation::Legacy));
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+
+/// One hit as read back from a results log.
+#[derive(Debug, Default, PartialEq)]
+pub struct Host {
+    pub timestamp: String,
+    pub ip: String,
+    pub ports: Vec<u16>,
+    pub latency: Option<u64>,
+    pub country: Option<String>,
+    pub asn: Option<u64>,
+    /// Probe results in text form (SSH banner, TLS findings, ...)
+    pub details: Vec<String>,
+}
+
+/// Reads hits from a text, JSON, MessagePack or CBOR results log. Per-port
+/// rows (--all-ports) are folded into their host; lines in other formats are
+/// skipped.
+pub fn load(path: &str) -> Result<Vec<Host>> {
+    let content = std::fs::read(path).with_context(|| format!("failed to read results '{}'", path))?;
+    let parsed: Vec<Option<Host>> = match content.first().copied().and_then(crate::binary::Format::detect) {
+        Some(format) => crate::binary::Records::new(&content[..], format)
+            .map(|r| r.map(|doc| from_json(&doc)))
+            .collect::<std::io::Result<_>>()
+            .with_context(|| format!("'{}' is corrupt", path))?,
+        None => String::from_utf8_lossy(&content).lines().map(str::trim).filter(|l| !l.is_empty())
+            .map(|line| if line.starts_with('{') { serde_json::from_str(line).ok().as_ref().and_then(from_json) } else { parse_text(line) })
+            .collect(),
+    };
+    let mut hosts: BTreeMap<Ipv4Addr, Host> = BTreeMap::new();
+    for parsed in parsed {
+        let Some(mut host) = parsed else { continue };
+        let open = std::mem::take(&mut host.ports);
+        let Ok(ip) = host.ip.parse::<Ipv4Addr>() else { continue };
+        // Later lines (a rescan, the next --all-ports row) update the host
+        let entry = hosts.entry(ip).or_default();
+        let ports = std::mem::take(&mut entry.ports);
+        if host.details.is_empty() && !entry.details.is_empty() {
+            entry.timestamp = host.timestamp;
+            entry.latency = entry.latency.or(host.latency);
+        } else {
+            *entry = host;
+        }
+        entry.ports = ports;
+        entry.ports.extend(open);
+        entry.ports.sort_unstable();
+        entry.ports.dedup();
+    }
+    // Per-port rows for hosts without an open port aren't hits
+    let hosts: Vec<Host> = hosts.into_values().filter(|h| !h.ports.is_empty() || !h.details.is_empty()).collect();
+    if hosts.is_empty() { bail!("no hits found in '{}'", path); }
+    Ok(hosts)
+}
+
+/// A JSON result line (or --all-ports row) as a host with its open ports.
+pub fn from_json(doc: &Value) -> Option<Host> {
+    let text = |v: &Value, key: &str| v.get(key).and_then(Value::as_str).map(str::to_string);
+    let mut host = Host {
+        timestamp: text(doc, "timestamp").unwrap_or_default(),
+        ip: text(doc, "ip")?,
+        country: doc.get("geo").and_then(|g| text(g, "country")),
+        asn: doc.get("asn").and_then(Value::as_u64),
+        ..Host::default()
+    };
+    let open = |p: &Value| p.get("state").and_then(Value::as_str) == Some("open");
+    // A hit line carries a ports array, an --all-ports row is a single port
+    let ports: Vec<&Value> = match doc.get("ports").and_then(Value::as_array) {
+        Some(ports) => ports.iter().collect(),
+        None => vec![doc],
+    };
+    host.ports = ports.iter().filter(|p| open(p)).filter_map(|p| p.get("port")?.as_u64()?.try_into().ok()).collect();
+    host.latency = ports.iter().filter(|p| open(p)).filter_map(|p| p.get("latency_ms")?.as_u64()).min();
+    for (key, value) in doc.as_object()? {
+        if !["schema_version", "timestamp", "ip", "ports", "geo", "asn", "port", "state", "latency_ms", "attempts"].contains(&key.as_str()) {
+            host.details.push(format!("{}: {}", key, value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())));
+        }
+    }
+    Some(host)
+}
+
+/// `[ts] ip, Ports: 22,80, Latency: 3ms, SSH: ...`, `[ts] ip, MAC: ...` or
+/// `[ts] ip, Port: 80, State: open, Latency: 3ms`.
+fn parse_text(line: &str) -> Option<Host> {
+    let (timestamp, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let mut fields = rest.split(", ");
+    let mut host = Host { timestamp: timestamp.to_string(), ip: fields.next()?.to_string(), ..Host::default() };
+    let mut port = None;
+    for field in fields {
+        if let Some(list) = field.strip_prefix("Ports: ") {
+            host.ports.extend(list.split(',').filter_map(|p| p.parse::<u16>().ok()));
+        } else if let Some(p) = field.strip_prefix("Port: ") {
+            port = p.parse::<u16>().ok();
+        } else if field == "State: open" {
+            host.ports.extend(port);
+        } else if field.starts_with("State: ") {
+            // closed or filtered row
+        } else if let Some(ms) = field.strip_prefix("Latency: ") {
+            host.latency = ms.trim_end_matches("ms").parse().ok();
+        } else if let Some(last) = host.details.last_mut().filter(|_| !field.contains(": ") && field != "Open resolver") {
+            // Values that themselves contain ", " (TLS findings, SNMP descriptions)
+            last.push_str(", ");
+            last.push_str(field);
+        } else {
+            host.details.push(field.to_string());
+        }
+    }
+    Some(host)
+}
+
+/// Hosts and open ports that appeared or disappeared between two runs.
+pub fn diff(previous: &[Host], current: &[Host]) -> Value {
+    let index = |hosts: &[Host]| hosts.iter().map(|h| (h.ip.clone(), h.ports.clone())).collect::<BTreeMap<_, _>>();
+    let (before, after) = (index(previous), index(current));
+    let changes = |from: &BTreeMap<String, Vec<u16>>, to: &BTreeMap<String, Vec<u16>>| -> Vec<Value> {
+        to.iter().filter_map(|(ip, ports)| {
+            let known = from.get(ip)?;
+            let ports: Vec<u16> = ports.iter().filter(|p| !known.contains(p)).copied().collect();
+            (!ports.is_empty()).then(|| serde_json::json!({ "ip": ip, "ports": ports }))
+        }).collect()
+    };
+    serde_json::json!({
+        "new_hosts": after.keys().filter(|ip| !before.contains_key(*ip)).collect::<Vec<_>>(),
+        "gone_hosts": before.keys().filter(|ip| !after.contains_key(*ip)).collect::<Vec<_>>(),
+        "opened": changes(&before, &after),
+        "closed": changes(&after, &before),
+    })
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em;color:#222}h1{color:#0a7d55}\
+table{border-collapse:collapse;margin-bottom:2em}th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#f0f0f0;cursor:pointer;user-select:none}th:hover{background:#e0e0e0}\
+.bar{background:#0a7d55;height:14px}.chart td{border:none;padding:2px 8px}.muted{color:#888}";
+
+/// Clicking a header sorts by that column, numerically when every cell is a number.
+const SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach((th,i)=>th.onclick=()=>{\
+const body=th.closest('table').tBodies[0],rows=[...body.rows],dir=th.dataset.dir==='asc'?-1:1;th.dataset.dir=dir>0?'asc':'desc';\
+const key=r=>r.cells[i].dataset.sort??r.cells[i].textContent,num=rows.every(r=>key(r)===''||!isNaN(key(r)));\
+rows.sort((a,b)=>dir*(num?(+key(a)||0)-(+key(b)||0):key(a).localeCompare(key(b))));rows.forEach(r=>body.appendChild(r))});";
+
+/// Standalone HTML report: summary, per-port chart, hosts table and the configuration.
+pub fn render_html(hosts: &[Host], config: &Value, source: &str) -> String {
+    let mut per_port: BTreeMap<u16, usize> = BTreeMap::new();
+    for port in hosts.iter().flat_map(|h| &h.ports) { *per_port.entry(*port).or_default() += 1; }
+    let mut top: Vec<(u16, usize)> = per_port.into_iter().collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    top.truncate(25);
+    let max = top.first().map(|t| t.1).unwrap_or(1);
+
+    let mut html = String::new();
+    let _ = write!(html, "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>PulseNet report</title><style>{}</style></head><body>", STYLE);
+    let _ = write!(html, "<h1>PulseNet report</h1><p>{} hosts from <code>{}</code>, generated {}</p>",
+        hosts.len(), escape(source), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
+
+    html.push_str("<h2>Open ports</h2><table class=\"chart\">");
+    for (port, count) in &top {
+        let _ = write!(html, "<tr><td>{}</td><td style=\"width:400px\"><div class=\"bar\" style=\"width:{}%\"></div></td><td>{}</td></tr>",
+            port, count * 100 / max, count);
+    }
+    html.push_str("</table>");
+
+    html.push_str("<h2>Hosts</h2><table class=\"sortable\"><thead><tr><th>Time</th><th>IP</th><th>Ports</th><th>Latency (ms)</th><th>Country</th><th>ASN</th><th>Details</th></tr></thead><tbody>");
+    for h in hosts {
+        let sort_ip = h.ip.parse::<Ipv4Addr>().map(u32::from).unwrap_or(0);
+        let _ = write!(html, "<tr><td>{}</td><td data-sort=\"{}\">{}</td><td data-sort=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&h.timestamp), sort_ip, escape(&h.ip),
+            h.ports.first().copied().unwrap_or(0), h.ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", "),
+            h.latency.map(|l| l.to_string()).unwrap_or_default(),
+            escape(h.country.as_deref().unwrap_or("")), h.asn.map(|a| format!("AS{}", a)).unwrap_or_default(),
+            h.details.iter().map(|d| escape(d)).collect::<Vec<_>>().join("<br>"));
+    }
+    html.push_str("</tbody></table>");
+
+    html.push_str("<h2>Configuration</h2><table class=\"sortable\"><thead><tr><th>Setting</th><th>Value</th></tr></thead><tbody>");
+    for (key, value) in config.as_object().into_iter().flatten().filter(|(_, v)| !v.is_null()) {
+        let value = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+        let _ = write!(html, "<tr><td>{}</td><td>{}</td></tr>", escape(key), escape(&value));
+    }
+    let _ = writeln!(html, "</tbody></table><p class=\"muted\">Effective settings (defaults, config file, environment, flags) when the report was generated.</p><script>{}</script></body></html>", SCRIPT);
+    html
+}
+
+fn markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Summary list plus one table row per host, for tickets, wikis and report appendices.
+pub fn render_markdown(title: &str, summary: &[(&str, String)], hosts: &[Host]) -> String {
+    let mut md = format!("## {}\n\n", title);
+    for (label, value) in summary { let _ = writeln!(md, "- **{}:** {}", label, markdown_cell(value)); }
+    md.push_str("\n| IP | Ports | Latency (ms) | Country | ASN | Details |\n|---|---|---|---|---|---|\n");
+    for h in hosts {
+        let _ = writeln!(md, "| {} | {} | {} | {} | {} | {} |",
+            h.ip, h.ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", "),
+            h.latency.map(|l| l.to_string()).unwrap_or_default(),
+            markdown_cell(h.country.as_deref().unwrap_or("")), h.asn.map(|a| format!("AS{}", a)).unwrap_or_default(),
+            markdown_cell(&h.details.join("; ")));
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_log_lines() {
+        let host = parse_text("[2026-10-15 08:43:37] 10.0.0.5, Ports: 22,443, Latency: 4ms, SSH: SSH-2.0-OpenSSH_9.6, TLS 443: TLS 1.2 [self-signed certificate]").unwrap();
+        assert_eq!((host.ip.as_str(), host.ports.as_slice(), host.latency), ("10.0.0.5", &[22, 443][..], Some(4)));
+        assert_eq!(host.details, ["SSH: SSH-2.0-OpenSSH_9.6", "TLS 443: TLS 1.2 [self-signed certificate]"]);
+        let host = parse_text("[2026-10-15 08:43:37] 10.0.0.5, Port: 80, State: open, Latency: 3ms").unwrap();
+        assert_eq!(host.ports, [80]);
+
+        let json = r#"{"timestamp":"t","ip":"10.0.0.6","ports":[{"port":80,"state":"open","latency_ms":7,"attempts":1},{"port":22,"state":"closed","attempts":1}],"asn":64500,"ssh":{"banner":"x"}}"#;
+        let host = from_json(&serde_json::from_str(json).unwrap()).unwrap();
+        assert_eq!((host.ports.as_slice(), host.latency, host.asn), (&[80][..], Some(7), Some(64500)));
+        assert_eq!(host.details, [r#"ssh: {"banner":"x"}"#]);
+    }
+    #[test]
+    fn test_render_escapes() {
+        let hosts = [Host { ip: "10.0.0.5".into(), ports: vec![80], details: vec!["HTTP 80: <script>".into()], ..Host::default() }];
+        let html = render_html(&hosts, &serde_json::json!({ "rate": 100 }), "log");
+        assert!(html.contains("HTTP 80: &lt;script&gt;") && html.contains("<td>rate</td><td>100</td>"));
+        let md = render_markdown("Scan", &[("Hits", "1".into())], &hosts);
+        assert!(md.contains("- **Hits:** 1\n") && md.contains("| 10.0.0.5 | 80 |  |  |  | HTTP 80: <script> |"));
+    }
+    #[test]
+    fn test_diff_runs() {
+        let host = |ip: &str, ports: &[u16]| Host { ip: ip.into(), ports: ports.to_vec(), ..Host::default() };
+        let changes = diff(&[host("10.0.0.1", &[22, 80]), host("10.0.0.2", &[443])], &[host("10.0.0.1", &[80, 8080]), host("10.0.0.3", &[22])]);
+        assert_eq!(changes, serde_json::json!({
+            "new_hosts": ["10.0.0.3"], "gone_hosts": ["10.0.0.2"],
+            "opened": [{ "ip": "10.0.0.1", "ports": [8080] }], "closed": [{ "ip": "10.0.0.1", "ports": [22] }],
+        }));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{dns, eyeballs, http, tls};
+use anyhow::{Context, Result};
+use rand::Rng;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+/// Per-upstream limit for one query, connection setup included.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A DNS server to send queries to.
+#[derive(Debug, PartialEq)]
+pub enum Upstream {
+    /// Plain DNS over UDP, retried over TCP when the answer is truncated
+    Udp(SocketAddr),
+    /// DNS over TLS (RFC 7858)
+    Tls(SocketAddr),
+    /// DNS over HTTPS (RFC 8484)
+    Https(http::Url),
+}
+
+impl Upstream {
+    /// `1.1.1.1`, `1.1.1.1:5353`, `[2606:4700::1111]:53` or `tls://1.1.1.1[:853]`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (tls, addr) = match spec.strip_prefix("tls://") {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let port = if tls { 853 } else { 53 };
+        let addr = match addr.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, port),
+            Err(_) => addr.parse().with_context(|| format!("invalid resolver '{}' (expected an IP address)", spec))?,
+        };
+        Ok(if tls { Self::Tls(addr) } else { Self::Udp(addr) })
+    }
+}
+
+/// Where host names are looked up: the system resolver, or the servers given
+/// with --resolver/--doh, tried in order until one answers.
+#[derive(Debug)]
+pub struct Resolver {
+    upstreams: Vec<Upstream>,
+}
+
+impl Resolver {
+    /// Comma separated lists; DoH endpoints are asked first.
+    pub fn new(resolvers: Option<&str>, doh: Option<&str>) -> Result<Self> {
+        let mut upstreams = Vec::new();
+        for url in list(doh) {
+            upstreams.push(Upstream::Https(http::Url::parse(url).with_context(|| format!("invalid DoH URL '{}'", url))?));
+        }
+        for spec in list(resolvers) {
+            upstreams.push(Upstream::parse(spec)?);
+        }
+        Ok(Self { upstreams })
+    }
+
+    /// The system resolver, as without --resolver and --doh.
+    pub fn system() -> Self {
+        Self { upstreams: Vec::new() }
+    }
+
+    /// Connects to `host` (an address, or a name looked up here) on `port`,
+    /// racing its addresses like a scanned name. For the services results go to.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let addrs = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => eyeballs::Host::resolve(host, self).await?.addrs,
+        };
+        let (stream, _) = eyeballs::connect(&addrs, port, TcpStream::connect, eyeballs::CONNECTION_ATTEMPT_DELAY).await?;
+        Ok(stream)
+    }
+
+    /// Addresses of `name`, both families.
+    pub async fn lookup(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        if self.upstreams.is_empty() {
+            return Ok(tokio::net::lookup_host((name, 0)).await?.map(|a| a.ip()).collect());
+        }
+        let (v4, v6) = tokio::join!(self.query(name, dns::TYPE_A), self.query(name, dns::TYPE_AAAA));
+        let mut addrs: Vec<IpAddr> = Vec::new();
+        let mut last_error = None;
+        for answer in [v4, v6] {
+            match answer {
+                Ok(msg) => addrs.extend(msg.answers.iter().filter_map(|r| match r.data {
+                    dns::RData::A(a) => Some(IpAddr::V4(a)),
+                    dns::RData::Aaaa(a) => Some(IpAddr::V6(a)),
+                    _ => None,
+                })),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if addrs.is_empty() => Err(e),
+            _ if addrs.is_empty() => Err(io::Error::new(io::ErrorKind::NotFound, "no A or AAAA records")),
+            _ => Ok(addrs),
+        }
+    }
+
+    async fn query(&self, name: &str, qtype: u16) -> io::Result<dns::Message> {
+        let id: u16 = rand::thread_rng().r#gen();
+        let query = dns::build_query(id, name, qtype, true);
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no resolvers");
+        for upstream in &self.upstreams {
+            let answer = match tokio::time::timeout(QUERY_TIMEOUT, exchange(upstream, &query)).await {
+                Ok(answer) => answer,
+                Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "resolver timed out")),
+            };
+            match answer.and_then(|buf| check_answer(&buf, id)) {
+                Ok(msg) => return Ok(msg),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+fn list(s: Option<&str>) -> impl Iterator<Item = &str> {
+    s.unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn check_answer(buf: &[u8], id: u16) -> io::Result<dns::Message> {
+    let msg = dns::parse(buf).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response"))?;
+    if msg.id != id || !msg.is_response() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected DNS response"));
+    }
+    match msg.rcode() {
+        // NXDOMAIN is an answer, not a reason to ask the next server
+        0 | 3 => Ok(msg),
+        code => Err(io::Error::other(format!("resolver returned rcode {}", code))),
+    }
+}
+
+async fn exchange(upstream: &Upstream, query: &[u8]) -> io::Result<Vec<u8>> {
+    match upstream {
+        Upstream::Udp(addr) => {
+            let sock = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+            sock.connect(addr).await?;
+            sock.send(query).await?;
+            let mut buf = vec![0u8; 65535];
+            let n = sock.recv(&mut buf).await?;
+            buf.truncate(n);
+            if dns::parse(&buf).is_some_and(|msg| msg.truncated()) {
+                return exchange_stream(TcpStream::connect(addr).await?, query).await;
+            }
+            Ok(buf)
+        }
+        Upstream::Tls(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            let ip = addr.ip().to_string();
+            exchange_stream(tls::connect(stream, std::net::Ipv4Addr::UNSPECIFIED, Some(&ip)).await?, query).await
+        }
+        Upstream::Https(url) => {
+            let headers = vec![("Content-Type", "application/dns-message".to_string()), ("Accept", "application/dns-message".to_string())];
+            // The DoH server's own name can't be looked up through itself; boxed
+            // because fetching connects through a resolver in turn
+            let resp = Box::pin(http::fetch(&Resolver::system(), url, "POST", "", headers, query)).await?;
+            if resp.status != 200 { return Err(io::Error::other(format!("DoH server returned HTTP {}", resp.status))); }
+            Ok(resp.body)
+        }
+    }
+}
+
+/// DNS over a byte stream (TCP, TLS): each message prefixed with its length.
+async fn exchange_stream<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, query: &[u8]) -> io::Result<Vec<u8>> {
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await?;
+    let len = stream.read_u16().await? as usize;
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_upstreams() {
+        assert_eq!(Upstream::parse("1.1.1.1").unwrap(), Upstream::Udp("1.1.1.1:53".parse().unwrap()));
+        assert_eq!(Upstream::parse("tls://9.9.9.9").unwrap(), Upstream::Tls("9.9.9.9:853".parse().unwrap()));
+        assert_eq!(Upstream::parse("[2606:4700::1111]:5353").unwrap(), Upstream::Udp("[2606:4700::1111]:5353".parse().unwrap()));
+        assert!(Upstream::parse("dns.google").is_err());
+        let resolver = Resolver::new(Some("8.8.8.8"), Some("https://cloudflare-dns.com/dns-query")).unwrap();
+        assert!(matches!(&resolver.upstreams[..], [Upstream::Https(url), Upstream::Udp(_)] if url.path == "/dns-query"));
+    }
+    #[test]
+    fn test_connect_resolves_through_upstream() {
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            // Answers A queries with 127.0.0.1 and AAAA queries with nothing
+            let dns = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let resolver = Resolver::new(Some(&dns.local_addr().unwrap().to_string()), None).unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 512];
+                loop {
+                    let (n, from) = dns.recv_from(&mut buf).await.unwrap();
+                    let mut reply = buf[..n].to_vec();
+                    reply[2..4].copy_from_slice(&[0x81, 0x80]);
+                    if u16::from_be_bytes([reply[n - 4], reply[n - 3]]) == dns::TYPE_A {
+                        reply[7] = 1;
+                        reply.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+                    }
+                    dns.send_to(&reply, from).await.unwrap();
+                }
+            });
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let stream = resolver.connect("collector.invalid", port).await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+            assert!(Resolver::system().connect("127.0.0.1", port).await.is_ok());
+        });
+    }
+}
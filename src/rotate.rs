@@ -0,0 +1,176 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// When the results log is rotated: past a size (`100MB`) or an age (`1d`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    Size(u64),
+    Every(Duration),
+}
+
+impl Policy {
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (num, unit) = s.split_at(split);
+        let n: u64 = num.parse().ok().filter(|&n| n > 0)
+            .with_context(|| format!("invalid --log-rotate '{}': expected a size like 100MB or an interval like 1d", s))?;
+        Ok(match unit.to_ascii_lowercase().as_str() {
+            "b" => Self::Size(n),
+            "kb" | "k" => Self::Size(n << 10),
+            "mb" | "m" => Self::Size(n << 20),
+            "gb" | "g" => Self::Size(n << 30),
+            "h" => Self::Every(Duration::from_secs(n * 3600)),
+            "d" => Self::Every(Duration::from_secs(n * 86400)),
+            "min" => Self::Every(Duration::from_secs(n * 60)),
+            _ => bail!("invalid --log-rotate '{}': unit must be KB, MB, GB, min, h or d", s),
+        })
+    }
+}
+
+#[derive(clap::ValueEnum, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+        }
+    }
+}
+
+/// Append-only log that moves itself to `<log>.1` (then `.2`, ...) when the
+/// policy triggers, keeping at most `keep` rotated files.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    opened: Instant,
+    policy: Policy,
+    keep: usize,
+    compression: Compression,
+    /// Compressing a large log takes seconds, so it happens off the scan path
+    compressing: Option<JoinHandle<()>>,
+}
+
+impl RotatingFile {
+    pub fn open(path: &str, policy: Policy, keep: usize, compression: Compression) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("failed to open '{}'", path))?;
+        let written = file.metadata()?.len();
+        Ok(Self { path: path.into(), file, written, opened: Instant::now(), policy, keep, compression, compressing: None })
+    }
+
+    fn due(&self) -> bool {
+        match self.policy {
+            Policy::Size(max) => self.written >= max,
+            Policy::Every(age) => self.written > 0 && self.opened.elapsed() >= age,
+        }
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}{}", n, self.compression.suffix()));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if let Some(handle) = self.compressing.take() { let _ = handle.join(); }
+        if self.keep == 0 {
+            self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = std::fs::rename(self.rotated(n), self.rotated(n + 1));
+            }
+            let mut plain = self.path.clone().into_os_string();
+            plain.push(".1");
+            let plain = PathBuf::from(plain);
+            std::fs::rename(&self.path, &plain)?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            if self.compression != Compression::None {
+                let (target, compression) = (self.rotated(1), self.compression);
+                self.compressing = Some(std::thread::spawn(move || {
+                    if let Err(e) = compress(&plain, &target, compression) {
+                        warn!("Failed to compress rotated log '{}': {}", plain.display(), e);
+                    }
+                }));
+            }
+        }
+        self.written = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+fn compress(from: &Path, to: &Path, compression: Compression) -> io::Result<()> {
+    let mut input = File::open(from)?;
+    let output = File::create(to)?;
+    match compression {
+        Compression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut enc)?;
+            enc.finish()?;
+        }
+        Compression::Zstd => zstd::stream::copy_encode(&mut input, output, 0)?,
+        Compression::None => return Ok(()),
+    }
+    std::fs::remove_file(from)
+}
+
+impl Write for RotatingFile {
+    /// Rotates only after a line ends, so no line is split across files.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        if buf[..n].ends_with(b"\n") && self.due() { self.rotate()?; }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for RotatingFile {
+    fn drop(&mut self) {
+        if let Some(handle) = self.compressing.take() { let _ = handle.join(); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_rotation() {
+        assert_eq!(Policy::parse("100MB").unwrap(), Policy::Size(100 << 20));
+        assert_eq!(Policy::parse("1d").unwrap(), Policy::Every(Duration::from_secs(86400)));
+        assert!(Policy::parse("10x").is_err() && Policy::parse("MB").is_err());
+
+        let dir = std::env::temp_dir().join(format!("pulsenet-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("scan.log");
+        let mut file = RotatingFile::open(log.to_str().unwrap(), Policy::Size(10), 2, Compression::Gzip).unwrap();
+        for i in 0..4 { writeln!(file, "line {:05}", i).unwrap(); }
+        drop(file);
+        let mut names: Vec<String> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        let mut decoded = String::new();
+        io::Read::read_to_string(&mut flate2::read::GzDecoder::new(File::open(dir.join("scan.log.1.gz")).unwrap()), &mut decoded).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["scan.log", "scan.log.1.gz", "scan.log.2.gz"]);
+        assert_eq!(decoded, "line 00003\n");
+    }
+}
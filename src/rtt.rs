@@ -0,0 +1,88 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const GLOBAL_WINDOW: usize = 512;
+const NET_WINDOW: usize = 32;
+/// Samples needed before an estimate is trusted over the static timeout.
+const MIN_SAMPLES: usize = 16;
+const NET_MIN_SAMPLES: usize = 4;
+const RTT_MULTIPLIER: u32 = 3;
+
+#[derive(Default)]
+struct Window { samples: VecDeque<u32>, cap: usize }
+
+impl Window {
+    fn with_cap(cap: usize) -> Self { Self { samples: VecDeque::with_capacity(cap), cap } }
+    fn push(&mut self, ms: u32) {
+        if self.samples.len() == self.cap { self.samples.pop_front(); }
+        self.samples.push_back(ms);
+    }
+    fn p95(&self) -> u32 {
+        let mut sorted: Vec<u32> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted[(sorted.len() * 95 / 100).min(sorted.len() - 1)]
+    }
+}
+
+/// Rolling RTT estimate, tracked globally and per destination /16, used to
+/// derive probe timeouts (3x p95) instead of a fixed budget.
+pub struct RttEstimator {
+    global: Mutex<Window>,
+    nets: Mutex<HashMap<u16, Window>>,
+    min: Duration,
+    max: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self { global: Mutex::new(Window::with_cap(GLOBAL_WINDOW)), nets: Mutex::new(HashMap::new()), min, max }
+    }
+
+    fn net_key(ip: Ipv4Addr) -> u16 {
+        let o = ip.octets();
+        u16::from_be_bytes([o[0], o[1]])
+    }
+
+    pub fn record(&self, ip: Ipv4Addr, rtt: Duration) {
+        let ms = rtt.as_millis().min(u32::MAX as u128) as u32;
+        self.global.lock().unwrap().push(ms);
+        self.nets.lock().unwrap()
+            .entry(Self::net_key(ip))
+            .or_insert_with(|| Window::with_cap(NET_WINDOW))
+            .push(ms);
+    }
+
+    /// Suggested timeout for a probe to `ip`, or None while there isn't enough data yet.
+    pub fn timeout_for(&self, ip: Ipv4Addr) -> Option<Duration> {
+        let p95 = {
+            let nets = self.nets.lock().unwrap();
+            match nets.get(&Self::net_key(ip)) {
+                Some(w) if w.samples.len() >= NET_MIN_SAMPLES => Some(w.p95()),
+                _ => None,
+            }
+        }.or_else(|| {
+            let g = self.global.lock().unwrap();
+            (g.samples.len() >= MIN_SAMPLES).then(|| g.p95())
+        })?;
+        Some((Duration::from_millis(p95 as u64) * RTT_MULTIPLIER).clamp(self.min, self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_estimate_prefers_subnet() {
+        let est = RttEstimator::new(Duration::from_millis(50), Duration::from_millis(2000));
+        let near = Ipv4Addr::new(10, 1, 0, 1);
+        assert_eq!(est.timeout_for(near), None);
+        for _ in 0..NET_MIN_SAMPLES { est.record(near, Duration::from_millis(40)); }
+        assert_eq!(est.timeout_for(near), Some(Duration::from_millis(120)));
+        // Unknown /16 with too few global samples still has no estimate
+        assert_eq!(est.timeout_for(Ipv4Addr::new(20, 1, 0, 1)), None);
+        for _ in 0..MIN_SAMPLES { est.record(near, Duration::from_millis(1000)); }
+        assert_eq!(est.timeout_for(Ipv4Addr::new(20, 1, 0, 1)), Some(Duration::from_millis(2000)));
+    }
+}
@@ -0,0 +1,124 @@
+use serde_json::{json, Value};
+
+/// `schema_version` of every JSON result line. Fields are only ever added
+/// within a version; renaming, removing or retyping one bumps it.
+pub const VERSION: u32 = 1;
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn list(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn text(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+/// JSON Schema (draft 2020-12) of the lines in a JSON results log: a hit, or
+/// with --all-ports one row per probed port. Optional fields are left out
+/// rather than written as null, and consumers should ignore fields they don't
+/// know, so newer minor additions don't break them.
+pub fn json_schema() -> Value {
+    let string = json!({ "type": "string" });
+    let port = json!({ "type": "integer", "minimum": 0, "maximum": 65535 });
+    let uint = json!({ "type": "integer", "minimum": 0 });
+    let boolean = json!({ "type": "boolean" });
+    let port_record = object(&["port", "state", "attempts"], json!({
+        "port": port,
+        "state": { "enum": ["open", "closed", "filtered", "open_filtered"] },
+        "latency_ms": uint,
+        "attempts": uint,
+        "error": object(&["kind"], json!({ "kind": text("io::ErrorKind, e.g. HostUnreachable"), "errno": { "type": "integer" } })),
+        "icmp": object(&["code", "reason", "from"], json!({ "code": uint, "reason": string, "from": text("Router or firewall that sent it") })),
+        "family": { "enum": ["ipv4", "ipv6"] },
+    }));
+    let certificate = object(&["issuer", "not_after", "self_signed", "expired"], json!({
+        "subject_cn": string, "issuer": string, "not_after": string, "san": list(string.clone()), "self_signed": boolean, "expired": boolean,
+    }));
+    let hit = object(&["schema_version", "timestamp", "ip", "ports"], json!({
+        "schema_version": { "const": VERSION },
+        "timestamp": text("Local time, YYYY-MM-DD HH:MM:SS"),
+        "ip": { "type": "string", "format": "ipv4" },
+        "hostname": text("Name the target was given as in --file"),
+        "labels": list(string.clone()),
+        "ports": list(json!({ "$ref": "#/$defs/port" })),
+        "geo": object(&[], json!({ "country": string, "city": string, "latitude": { "type": "number" }, "longitude": { "type": "number" } })),
+        "asn": uint,
+        "as_org": string,
+        "cloud": object(&["provider"], json!({ "provider": string, "region": string, "service": string })),
+        "cdn": object(&["provider", "evidence"], json!({ "provider": string, "evidence": list(json!({ "enum": ["header", "certificate", "ip"] })) })),
+        "suspect": list(json!({ "enum": ["all_ports_open", "uniform_latency", "tiny_window", "stalled"] })),
+        "whois": object(&["range", "server"], json!({ "range": string, "netname": string, "org": string, "server": string })),
+        "os_guess": object(&["family", "ttl", "initial_ttl", "window", "options"], json!({
+            "family": string, "ttl": uint, "initial_ttl": uint, "window": uint, "options": text("TCP option layout, e.g. M1460,S,T,N,W7"),
+        })),
+        "trace": list(object(&["ttl"], json!({ "ttl": uint, "ip": string, "rtt_ms": uint }))),
+        "mac": string,
+        "snmp": object(&["community", "version"], json!({ "community": string, "version": string, "sys_descr": string, "sys_name": string })),
+        "open_resolver": object(&["query", "answers"], json!({ "query": string, "answers": list(string.clone()) })),
+        "open_proxy": list(object(&["port", "protocol"], json!({
+            "port": port, "protocol": { "enum": ["http-connect", "socks5", "socks4a"] }, "reply": text("First line the endpoint sent through the tunnel"),
+        }))),
+        "quic": object(&["versions", "http3"], json!({
+            "versions": list(string.clone()), "alpn": string, "http3": boolean, "certificate": { "$ref": "#/$defs/certificate" }, "closed": string,
+        })),
+        "ssh": object(&["banner"], json!({ "banner": string, "host_key_type": string, "host_key_fingerprint": string })),
+        "rdp": object(&["protocols", "nla_required"], json!({
+            "protocols": list(json!({ "enum": ["rdp", "tls", "credssp", "credssp_early_auth"] })), "nla_required": boolean, "hostname": string,
+        })),
+        "smb": object(&["smbv1", "signing_required"], json!({
+            "dialect": string, "smbv1": boolean, "signing_required": boolean, "netbios_name": string, "netbios_domain": string,
+            "dns_name": string, "dns_domain": string, "os_version": string,
+        })),
+        "ftp": object(&["banner", "anonymous_login"], json!({ "banner": string, "anonymous_login": boolean })),
+        "telnet": object(&["banner", "options"], json!({
+            "banner": text("Greeting and login prompt without control characters, lines joined with ' | '"), "options": list(string.clone()),
+        })),
+        "tls": list(object(&["port", "versions", "ciphers", "findings"], json!({
+            "port": port,
+            "versions": list(string.clone()),
+            "ciphers": { "type": "object", "additionalProperties": string },
+            "alpn": list(string.clone()),
+            "certificate": { "$ref": "#/$defs/certificate" },
+            "findings": list(string.clone()),
+        }))),
+        "http": list(object(&["port", "scheme", "status"], json!({
+            "port": port, "scheme": { "enum": ["http", "https"] }, "status": uint, "server": string, "title": string,
+            "favicon_hash": { "type": "integer" }, "technologies": list(string.clone()), "websocket": list(string.clone()),
+        }))),
+        "plugins": list(object(&["plugin", "port", "findings"], json!({ "plugin": string, "port": port, "findings": {} }))),
+        "payloads": list(object(&["name", "port", "proto", "length"], json!({
+            "name": string, "port": port, "proto": { "enum": ["tcp", "udp"] }, "length": uint, "text": string, "hex": string,
+        }))),
+        "script": { "description": "Whatever the --script on_hit hook attached" },
+    }));
+    let mut row = port_record.clone();
+    row["required"] = json!(["schema_version", "timestamp", "ip", "port", "state", "attempts"]);
+    row["properties"]["schema_version"] = json!({ "const": VERSION });
+    row["properties"]["timestamp"] = string.clone();
+    row["properties"]["ip"] = json!({ "type": "string", "format": "ipv4" });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("https://github.com/ZoniBoy00/PulseNet/schema/v{}/result.json", VERSION),
+        "title": "PulseNet result line",
+        "oneOf": [{ "$ref": "#/$defs/hit" }, { "$ref": "#/$defs/port_row" }],
+        "$defs": { "hit": hit, "port_row": row, "port": port_record, "certificate": certificate },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_schema_covers_required_fields() {
+        let schema = json_schema();
+        let hit = &schema["$defs"]["hit"];
+        for field in hit["required"].as_array().unwrap() {
+            assert!(hit["properties"].get(field.as_str().unwrap()).is_some(), "{}", field);
+        }
+        assert_eq!(hit["properties"]["schema_version"]["const"], VERSION);
+        assert_eq!(schema["$defs"]["port_row"]["properties"]["state"]["enum"][3], "open_filtered");
+    }
+}
@@ -0,0 +1,204 @@
+use crate::bind::SourceBinding;
+use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
+use mlua::{Function, Lua, LuaOptions, LuaSerdeExt, StdLib, Value as LuaValue};
+use serde_json::Value;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::timeout;
+
+/// Bytes `probe()` reads back at most.
+const MAX_RESPONSE: usize = 16 * 1024;
+
+/// Admits an address `probe()` was asked for and waits for its turn, or says
+/// why it can't be reached. Set by the scan, which owns the filters and the rate.
+pub type Gate = Box<dyn Fn(Ipv4Addr) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// What `on_hit` decided about a hit.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Keep,
+    /// Returned `false`: the hit is left out of every output
+    Drop,
+    /// Returned a table: kept, with the table stored as `script`
+    Annotate(Value),
+}
+
+/// A Lua script with optional hooks, called NSE-style during the scan:
+/// - `select_ports(ip)`: list of ports to probe on this host instead of --ports
+/// - `on_banner(ip, port, data)`: called with each open port's first response,
+///   a returned value is stored in `plugins` under the script's name
+/// - `on_hit(result)`: the full result; `false` drops it, a table annotates it
+///
+/// Scripts can talk to hosts with `probe(ip, port, payload[, timeout_ms])`,
+/// which returns the response or nil and an error. Only the string, table,
+/// math, utf8 and coroutine libraries are loaded unless `full_libs` is set.
+pub struct Script {
+    pub name: String,
+    lua: Lua,
+    gate: Arc<OnceLock<Gate>>,
+}
+
+fn lua_err(e: mlua::Error) -> anyhow::Error {
+    anyhow!("{}", e)
+}
+
+/// Request/response for scripts. Hooks run off the async workers, so this
+/// blocks on the scan's runtime until the exchange is over.
+fn probe(gate: &OnceLock<Gate>, binding: &SourceBinding, ip: String, port: u16, payload: &[u8], wait: Duration) -> Result<Vec<u8>, String> {
+    let ip: Ipv4Addr = ip.parse().map_err(|_| "invalid IPv4 address".to_string())?;
+    let gate = gate.get().ok_or("probe() isn't available before the scan starts")?;
+    let runtime = tokio::runtime::Handle::try_current().map_err(|e| e.to_string())?;
+    runtime.block_on(async {
+        gate(ip).await?;
+        exchange(binding, SocketAddr::new(ip.into(), port), payload, wait).await.map_err(|e| e.to_string())
+    })
+}
+
+async fn exchange(binding: &SourceBinding, addr: SocketAddr, payload: &[u8], wait: Duration) -> std::io::Result<Vec<u8>> {
+    let mut stream = timeout(wait, binding.connect(addr)).await.map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+    stream.write_all(payload).await?;
+    let mut data = vec![0; MAX_RESPONSE];
+    let mut len = 0;
+    while len < MAX_RESPONSE {
+        match timeout(wait, stream.read(&mut data[len..])).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => len += n,
+            Ok(Err(e)) => return Err(e),
+            // Read timeout: the peer has said what it's going to say
+            Err(_) => break,
+        }
+    }
+    data.truncate(len);
+    Ok(data)
+}
+
+impl Script {
+    /// `full_libs` adds `io`, `os` and `package`, which reach the local system.
+    pub fn load(path: &str, default_timeout: Duration, binding: Arc<SourceBinding>, full_libs: bool) -> Result<Self> {
+        let source = std::fs::read_to_string(path).with_context(|| format!("failed to read script '{}'", path))?;
+        let libs = if full_libs { StdLib::ALL_SAFE } else { StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE };
+        let lua = Lua::new_with(libs, LuaOptions::default()).map_err(lua_err)?;
+        if !full_libs {
+            // The base library can still read and run files
+            for name in ["dofile", "loadfile"] { lua.globals().raw_remove(name).map_err(lua_err)?; }
+        }
+        let gate: Arc<OnceLock<Gate>> = Arc::default();
+        let probe_gate = Arc::clone(&gate);
+        let probe_fn = lua.create_function(move |lua, (ip, port, payload, timeout_ms): (String, u16, mlua::LuaString, Option<u64>)| {
+            let wait = timeout_ms.map_or(default_timeout, Duration::from_millis);
+            match probe(&probe_gate, &binding, ip, port, &payload.as_bytes(), wait) {
+                Ok(data) => Ok((LuaValue::String(lua.create_string(data)?), LuaValue::Nil)),
+                Err(e) => Ok((LuaValue::Nil, LuaValue::String(lua.create_string(e)?))),
+            }
+        }).map_err(lua_err)?;
+        lua.globals().set("probe", probe_fn).map_err(lua_err)?;
+        lua.load(&source).set_name(path).exec().map_err(lua_err).with_context(|| format!("failed to run script '{}'", path))?;
+        let name = std::path::Path::new(path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        Ok(Self { name, lua, gate })
+    }
+
+    /// Lets `probe()` connect to what `gate` admits; until then it refuses.
+    pub fn set_gate(&self, gate: Gate) {
+        let _ = self.gate.set(gate);
+    }
+
+    fn hook(&self, name: &str) -> Option<Function> {
+        self.lua.globals().get::<Option<Function>>(name).ok().flatten()
+    }
+
+    pub fn has_banner_hook(&self) -> bool {
+        self.hook("on_banner").is_some()
+    }
+
+    pub fn select_ports(&self, ip: Ipv4Addr) -> Result<Option<Vec<u16>>> {
+        let Some(f) = self.hook("select_ports") else { return Ok(None) };
+        f.call::<Option<Vec<u16>>>(ip.to_string()).map_err(lua_err).context("select_ports failed")
+    }
+
+    pub fn on_banner(&self, ip: Ipv4Addr, port: u16, data: &[u8]) -> Result<Option<Value>> {
+        let Some(f) = self.hook("on_banner") else { return Ok(None) };
+        let data = self.lua.create_string(data).map_err(lua_err)?;
+        let ret: LuaValue = f.call((ip.to_string(), port, data)).map_err(lua_err).context("on_banner failed")?;
+        if ret.is_nil() { return Ok(None); }
+        self.lua.from_value(ret).map_err(lua_err).map(Some)
+    }
+
+    pub fn on_hit(&self, result: &Value) -> Result<Verdict> {
+        let Some(f) = self.hook("on_hit") else { return Ok(Verdict::Keep) };
+        let arg = self.lua.to_value(result).map_err(lua_err)?;
+        match f.call::<LuaValue>(arg).map_err(lua_err).context("on_hit failed")? {
+            LuaValue::Boolean(false) => Ok(Verdict::Drop),
+            ret @ LuaValue::Table(_) => Ok(Verdict::Annotate(self.lua.from_value(ret).map_err(lua_err)?)),
+            _ => Ok(Verdict::Keep),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_hooks() {
+        let path = std::env::temp_dir().join(format!("pulsenet-script-{}.lua", std::process::id()));
+        std::fs::write(&path, r#"
+            function select_ports(ip)
+                if ip:match("^10%.") then return {22, 2222} end
+            end
+            function on_banner(ip, port, data)
+                local version = data:match("^SSH%-2%.0%-(%S+)")
+                if version then return { software = version } end
+            end
+            function on_hit(r)
+                if #r.ports == 1 and r.ports[1].port == 80 then return false end
+                if r.geo and r.geo.country == "FI" then return { tag = "domestic" } end
+            end
+        "#).unwrap();
+        let script = Script::load(path.to_str().unwrap(), Duration::from_millis(100), Arc::new(SourceBinding::new(None, None).unwrap()), false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(script.select_ports(Ipv4Addr::new(10, 1, 2, 3)).unwrap(), Some(vec![22, 2222]));
+        assert_eq!(script.select_ports(Ipv4Addr::new(1, 1, 1, 1)).unwrap(), None);
+        let banner = script.on_banner(Ipv4Addr::new(10, 1, 2, 3), 22, b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        assert_eq!(banner, Some(serde_json::json!({ "software": "OpenSSH_9.6" })));
+        let hit = |ports: Value, geo: Value| {
+            let mut hit = serde_json::json!({ "ip": "1.2.3.4", "ports": ports });
+            if !geo.is_null() { hit["geo"] = geo; }
+            hit
+        };
+        assert_eq!(script.on_hit(&hit(serde_json::json!([{ "port": 80 }]), Value::Null)).unwrap(), Verdict::Drop);
+        assert_eq!(script.on_hit(&hit(serde_json::json!([{ "port": 443 }]), serde_json::json!({ "country": "FI" }))).unwrap(),
+            Verdict::Annotate(serde_json::json!({ "tag": "domestic" })));
+        assert_eq!(script.on_hit(&hit(serde_json::json!([{ "port": 443 }]), Value::Null)).unwrap(), Verdict::Keep);
+    }
+    #[test]
+    fn test_probe() {
+        let path = std::env::temp_dir().join(format!("pulsenet-probe-{}.lua", std::process::id()));
+        std::fs::write(&path, r#"
+            function on_banner(ip, port, data)
+                local reply = probe(ip, port, "ping")
+                local _, denied = probe("192.0.2.1", port, "ping")
+                return { reply = reply, denied = denied, sandboxed = os == nil and io == nil and dofile == nil }
+            end
+        "#).unwrap();
+        let script = Arc::new(Script::load(path.to_str().unwrap(), Duration::from_millis(500), Arc::new(SourceBinding::new(None, None).unwrap()), false).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        script.set_gate(Box::new(|ip| Box::pin(async move {
+            if ip.is_loopback() { Ok(()) } else { Err(format!("{} is outside the scope", ip)) }
+        })));
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let banner = rt.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(async move {
+                let (mut conn, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4];
+                conn.read_exact(&mut buf).await.unwrap();
+                conn.write_all(b"pong").await.unwrap();
+            });
+            tokio::task::spawn_blocking(move || script.on_banner(Ipv4Addr::LOCALHOST, port, b"hello")).await.unwrap()
+        }).unwrap();
+        assert_eq!(banner, Some(serde_json::json!({ "reply": "pong", "denied": "192.0.2.1 is outside the scope", "sandboxed": true })));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::report;
+use anyhow::Result;
+use serde_json::Value;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tracing::debug;
+
+const MAX_REQUEST: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `serve`: answers `GET /` with the HTML report of `input`, loaded from the
+/// file as it is at that moment, so a page reload follows a scan in progress.
+pub async fn serve(listener: TcpListener, input: String, config: Value) -> Result<()> {
+    let shared = Arc::new((input, config));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &shared.0, &shared.1).await { debug!(%peer, "report request failed: {}", e); }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, input: &str, config: &Value) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = timeout(READ_TIMEOUT, stream.read(&mut buf)).await.map_err(|_| io::ErrorKind::TimedOut)??;
+        if n == 0 || head.len() > MAX_REQUEST { return Ok(()); }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let line = String::from_utf8_lossy(&head);
+    let mut parts = line.split_whitespace();
+    let (status, kind, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/")) => match report::load(input) {
+            Ok(hosts) => ("200 OK", "text/html; charset=utf-8", report::render_html(&hosts, config, input)),
+            Err(e) => ("500 Internal Server Error", "text/plain; charset=utf-8", format!("{:#}\n", e)),
+        },
+        (Some("GET"), _) => ("404 Not Found", "text/plain; charset=utf-8", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "only GET\n".to_string()),
+    };
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n", status, kind, body.len());
+    stream.write_all(response.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_serves_report() {
+        let path = std::env::temp_dir().join(format!("pulsenet-serve-{}.log", std::process::id()));
+        std::fs::write(&path, "{\"timestamp\":\"2026-10-15 10:00:00\",\"ip\":\"203.0.113.7\",\"ports\":[{\"port\":443,\"state\":\"open\",\"latency_ms\":12}]}\n").unwrap();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(serve(listener, path.to_string_lossy().into_owned(), serde_json::json!({})));
+            let get = |target: &'static str| async move {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                stream.write_all(format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", target).as_bytes()).await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                response
+            };
+            let page = get("/").await;
+            assert!(page.starts_with("HTTP/1.1 200 OK"));
+            assert!(page.contains("203.0.113.7"));
+            assert!(get("/nope").await.starts_with("HTTP/1.1 404"));
+        });
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::resolver::Resolver;
+use crate::writer::LineWriter;
+use crate::{history, http, parquet, pg, report, rotate, Args, CheckResult, FoundFormat, OutputFormat, PortRow, ScanResult, Stats};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use futures::future::BoxFuture;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One finished target as the sinks see it.
+pub struct Outcome<'a> {
+    pub ip: Ipv4Addr,
+    pub check: &'a CheckResult,
+    /// Set when the target is a hit
+    pub hit: Option<&'a ScanResult>,
+    /// The hit rendered with --template
+    pub line: Option<&'a str>,
+}
+
+/// A destination for scan results. Every sink sees every outcome, in order;
+/// most only act on hits.
+pub trait OutputSink: Send {
+    /// How warnings refer to the sink
+    fn name(&self) -> String;
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>>;
+    /// Writes trailers, flushes and closes once the scan is over.
+    fn finish<'a>(self: Box<Self>, stats: &'a Stats) -> BoxFuture<'a, Result<()>>;
+}
+
+/// A `--sink` destination.
+#[derive(Debug, PartialEq)]
+pub enum Spec {
+    /// Results log in a format: `text:FILE`, `json:FILE`, `markdown:FILE`, `msgpack:FILE`, `cbor:FILE`, `parquet:FILE`
+    Log(OutputFormat, String),
+    /// Clean hit list in --found-format: `found:FILE`
+    Found(String),
+    /// JSON lines on standard output: `stdout`
+    Stdout,
+    /// POST each hit as JSON: `http(s)://...`
+    Webhook(String),
+    Sqlite(String),
+    Postgres(String),
+}
+
+impl Spec {
+    pub fn parse(s: &str) -> Result<Self> {
+        if s == "stdout" { return Ok(Self::Stdout); }
+        if s.starts_with("http://") || s.starts_with("https://") {
+            http::Url::parse(s).with_context(|| format!("invalid webhook URL '{}'", s))?;
+            return Ok(Self::Webhook(s.to_string()));
+        }
+        if pg::is_postgres_url(s) { return Ok(Self::Postgres(s.to_string())); }
+        if let Some(path) = history::sqlite_path(s) { return Ok(Self::Sqlite(path.to_string())); }
+        let Some((kind, path)) = s.split_once(':').filter(|(_, p)| !p.is_empty()) else {
+            bail!("invalid --sink '{}': expected text:FILE, json:FILE, markdown:FILE, msgpack:FILE, cbor:FILE, parquet:FILE, found:FILE, stdout, a webhook URL, sqlite://FILE or postgres://...", s);
+        };
+        Ok(match kind {
+            "text" => Self::Log(OutputFormat::Text, path.to_string()),
+            "json" => Self::Log(OutputFormat::Json, path.to_string()),
+            "markdown" => Self::Log(OutputFormat::Markdown, path.to_string()),
+            "msgpack" => Self::Log(OutputFormat::Msgpack, path.to_string()),
+            "cbor" => Self::Log(OutputFormat::Cbor, path.to_string()),
+            "parquet" => Self::Log(OutputFormat::Parquet, path.to_string()),
+            "found" => Self::Found(path.to_string()),
+            _ => bail!("invalid --sink '{}': unknown kind '{}'", s, kind),
+        })
+    }
+}
+
+/// The sinks --output and --found-output imply, then every --sink.
+pub async fn open_all(args: &Args, resolver: &Arc<Resolver>) -> Result<Vec<Box<dyn OutputSink>>> {
+    let format = if args.json { OutputFormat::Json } else { args.format };
+    let mut specs = vec![match Spec::parse(&args.output) {
+        Ok(spec @ (Spec::Sqlite(_) | Spec::Postgres(_))) => spec,
+        _ => Spec::Log(format, args.output.clone()),
+    }];
+    if !args.no_found_output { specs.push(Spec::Found(args.found_output.clone())); }
+    for spec in &args.sink { specs.push(Spec::parse(spec)?); }
+
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+    for (i, spec) in specs.into_iter().enumerate() {
+        sinks.push(match spec {
+            Spec::Log(OutputFormat::Parquet, path) => {
+                if i == 0 && args.log_rotate.is_some() { bail!("--log-rotate can't split a parquet file"); }
+                Box::new(ParquetFile::create(path, args.all_ports)?)
+            }
+            Spec::Log(format, path) => {
+                // --log-rotate applies to the --output log
+                let out = match &args.log_rotate {
+                    // Rotation happens at line ends, which binary records don't have
+                    Some(_) if i == 0 && format.binary().is_some() => bail!("--log-rotate can't split a msgpack or cbor log"),
+                    Some(policy) if i == 0 => LineWriter::start(Box::new(rotate::RotatingFile::open(&path, rotate::Policy::parse(policy)?, args.log_keep, args.log_compress)?), None),
+                    _ => LineWriter::append(&path, None)?,
+                };
+                Box::new(LogFile { path, out, format, all_ports: args.all_ports, ports: args.ports.clone(), started: Local::now(), markdown_hosts: Vec::new() })
+            }
+            Spec::Found(path) => {
+                let out = LineWriter::append(&path, (args.found_format == FoundFormat::Csv).then_some("ip,port"))?;
+                Box::new(FoundFile { path, out, format: args.found_format })
+            }
+            Spec::Stdout => Box::new(Stdout),
+            Spec::Webhook(url) => Box::new(Webhook { url: http::Url::parse(&url)?, raw: url, resolver: Arc::clone(resolver) }),
+            Spec::Sqlite(path) => Box::new(Sqlite(history::SqliteSink::open(&path, &args.redacted()?)?)),
+            Spec::Postgres(url) => Box::new(Postgres(pg::PgSink::connect(&url, &args.redacted()?).await?)),
+        });
+    }
+    Ok(sinks)
+}
+
+/// The results log: a line per hit in text or JSON, a row per probed port with
+/// --all-ports, or a Markdown report written when the scan ends.
+struct LogFile {
+    path: String,
+    out: LineWriter,
+    format: OutputFormat,
+    all_ports: bool,
+    ports: String,
+    started: chrono::DateTime<Local>,
+    markdown_hosts: Vec<report::Host>,
+}
+
+impl OutputSink for LogFile {
+    fn name(&self) -> String { format!("'{}'", self.path) }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (ip, check) = (out.ip, out.check);
+            if let Some(res) = out.hit {
+                let lat = check.latency().unwrap_or(0);
+                let port_list = check.open_ports().map(|p| p.port.to_string()).collect::<Vec<_>>().join(",");
+                if self.format == OutputFormat::Markdown {
+                    self.markdown_hosts.extend(report::from_json(&serde_json::to_value(res)?).map(|host| report::Host {
+                        details: check.mac.iter().map(|m| format!("MAC: {}", m)).chain(check.details()).collect(),
+                        ..host
+                    }));
+                } else if self.all_ports && check.mac.is_none() {
+                    // Rows for every port are written below
+                } else if let Some(binary) = self.format.binary() {
+                    self.out.record(binary.encode(&serde_json::to_value(res)?)).await?;
+                } else if self.format == OutputFormat::Json {
+                    self.out.line(serde_json::to_string(res)?).await?;
+                } else if let Some(line) = out.line {
+                    self.out.line(line.to_string()).await?;
+                } else if let Some(ref mac) = check.mac {
+                    self.out.line(format!("[{}] {}, MAC: {}", res.timestamp, ip, mac)).await?;
+                } else {
+                    let details: String = check.details().iter().map(|d| format!(", {}", d)).collect();
+                    self.out.line(format!("[{}] {}, Ports: {}, Latency: {}ms{}", res.timestamp, ip, port_list, lat, details)).await?;
+                }
+            }
+            if self.all_ports && self.format != OutputFormat::Markdown {
+                let ts_full = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                for probe in &check.ports {
+                    let row = || PortRow { schema_version: crate::schema::VERSION, timestamp: ts_full.clone(), ip: ip.to_string(), port: probe.record() };
+                    if let Some(binary) = self.format.binary() {
+                        self.out.record(binary.encode(&serde_json::to_value(row())?)).await?;
+                    } else if self.format == OutputFormat::Json {
+                        self.out.line(serde_json::to_string(&row())?).await?;
+                    } else {
+                        let lat = probe.latency.map(|l| format!(", Latency: {}ms", l)).unwrap_or_default();
+                        let error = probe.os_error.as_ref().map(|e| format!(", Error: {}", e)).unwrap_or_default();
+                        self.out.line(format!("[{}] {}, Port: {}, State: {}{}{}", ts_full, ip, probe.port, probe.state(), lat, error)).await?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, stats: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if self.format == OutputFormat::Markdown {
+                let avg = if stats.found > 0 { stats.total_latency / stats.found as u128 } else { 0 };
+                let summary = [
+                    ("Started", self.started.format("%Y-%m-%d %H:%M:%S").to_string()),
+                    ("Duration", format!("{}s", (Local::now() - self.started).num_seconds())),
+                    ("Ports", self.ports.clone()),
+                    ("Targets scanned", stats.total_processed.to_string()),
+                    ("Hits", stats.found.to_string()),
+                    ("Average latency", format!("{}ms", avg)),
+                    ("Ports open / closed / filtered", format!("{} / {} / {}", stats.ports_open, stats.ports_closed, stats.ports_filtered)),
+                    ("Timeouts / refused / unreachable", format!("{} / {} / {}", stats.timeouts, stats.refused, stats.unreachable)),
+                ];
+                // A writer that already stopped reports its error from finish below
+                let _ = self.out.line(report::render_markdown("PulseNet scan", &summary, &self.markdown_hosts)).await;
+            }
+            Ok(self.out.finish().await?)
+        })
+    }
+}
+
+/// Columns of the Parquet log: a row per probed port, the host's fields
+/// repeated on each. Whatever else a hit carries goes into `extra` as JSON.
+const PARQUET_COLUMNS: &[(&str, parquet::Kind, bool)] = &[
+    ("timestamp", parquet::Kind::String, false),
+    ("ip", parquet::Kind::String, false),
+    ("port", parquet::Kind::Int32, true),
+    ("state", parquet::Kind::String, true),
+    ("latency_ms", parquet::Kind::Int64, true),
+    ("attempts", parquet::Kind::Int32, true),
+    ("error", parquet::Kind::String, true),
+    ("hostname", parquet::Kind::String, true),
+    ("country", parquet::Kind::String, true),
+    ("asn", parquet::Kind::Int64, true),
+    ("as_org", parquet::Kind::String, true),
+    ("cloud", parquet::Kind::String, true),
+    ("cdn", parquet::Kind::String, true),
+    ("extra", parquet::Kind::String, true),
+];
+
+/// The results log as a Parquet file. Parquet can't be appended to, so each
+/// run replaces the file, and the footer only lands when the scan ends.
+struct ParquetFile {
+    path: String,
+    out: parquet::Writer<std::io::BufWriter<std::fs::File>>,
+    all_ports: bool,
+}
+
+impl ParquetFile {
+    fn create(path: String, all_ports: bool) -> Result<Self> {
+        let file = std::fs::File::create(&path).with_context(|| format!("failed to create '{}'", path))?;
+        Ok(Self { out: parquet::Writer::new(std::io::BufWriter::new(file), PARQUET_COLUMNS)?, path, all_ports })
+    }
+
+    /// Rows of one hit or --all-ports target, from its JSON form.
+    fn write(&mut self, mut doc: serde_json::Value) -> Result<()> {
+        use parquet::Cell;
+        use serde_json::Value;
+        let Some(fields) = doc.as_object_mut() else { return Ok(()) };
+        let ports = match fields.remove("ports") {
+            Some(Value::Array(ports)) => ports,
+            _ => Vec::new(),
+        };
+        let host: Vec<Option<Value>> = ["timestamp", "ip", "hostname", "geo", "asn", "as_org", "cloud", "cdn", "schema_version"]
+            .iter().map(|key| fields.remove(*key)).collect();
+        let extra = (!fields.is_empty()).then(|| doc.to_string());
+        fn text(v: Option<&Value>) -> Cell<'_> { v.and_then(Value::as_str).map_or(Cell::Null, Cell::Str) }
+        fn int(v: Option<&Value>) -> Cell<'_> { v.and_then(Value::as_i64).map_or(Cell::Null, Cell::Int) }
+        let nested = |i: usize, key: &str| host[i].as_ref().and_then(|v| v.get(key));
+        // A MAC-only hit from the ARP sweep has no ports but still gets a row
+        let none = [Value::Null];
+        let ports = if ports.is_empty() { &none[..] } else { &ports[..] };
+        for port in ports {
+            self.out.push(&[
+                text(host[0].as_ref()), text(host[1].as_ref()),
+                int(port.get("port")), text(port.get("state")), int(port.get("latency_ms")), int(port.get("attempts")),
+                text(port.get("error").and_then(|e| e.get("kind"))),
+                text(host[2].as_ref()), text(nested(3, "country")), int(host[4].as_ref()), text(host[5].as_ref()),
+                text(nested(6, "provider")), text(nested(7, "provider")),
+                extra.as_deref().map_or(Cell::Null, Cell::Str),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for ParquetFile {
+    fn name(&self) -> String { format!("'{}'", self.path) }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some(res) = out.hit {
+                self.write(serde_json::to_value(res)?)?;
+            } else if self.all_ports {
+                let ports: Vec<_> = out.check.ports.iter().map(|p| p.record()).collect();
+                let ts = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                self.write(serde_json::json!({ "timestamp": ts, "ip": out.ip.to_string(), "ports": ports }))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, _: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let version = crate::schema::VERSION.to_string();
+            self.out.finish(&[("pulsenet.schema_version", &version)])?;
+            Ok(())
+        })
+    }
+}
+
+/// The clean hit list, one or more lines per hit in --found-format.
+struct FoundFile {
+    path: String,
+    out: LineWriter,
+    format: FoundFormat,
+}
+
+impl OutputSink for FoundFile {
+    fn name(&self) -> String { format!("'{}'", self.path) }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // Suspected honeypots and tarpits stay in the log only
+            if out.hit.is_some() && out.check.suspect.is_empty() {
+                let ports: Vec<u16> = out.check.open_ports().map(|p| p.port).collect();
+                for line in self.format.lines(out.ip, &ports) { self.out.line(line).await?; }
+            }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, _: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { Ok(self.out.finish().await?) })
+    }
+}
+
+/// Hits as JSON lines for piping into another tool; pair with --quiet.
+struct Stdout;
+
+impl OutputSink for Stdout {
+    fn name(&self) -> String { "stdout".into() }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some(res) = out.hit { println!("{}", serde_json::to_string(res)?); }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, _: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// POSTs each hit as a JSON document; anything but a 2xx answer counts as a failed delivery.
+struct Webhook {
+    url: http::Url,
+    raw: String,
+    resolver: Arc<Resolver>,
+}
+
+impl OutputSink for Webhook {
+    fn name(&self) -> String { format!("webhook {}", self.raw) }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let Some(res) = out.hit else { return Ok(()) };
+            let body = serde_json::to_vec(res)?;
+            let headers = vec![("Content-Type", "application/json".to_string())];
+            let resp = tokio::time::timeout(WEBHOOK_TIMEOUT, http::fetch(&self.resolver, &self.url, "POST", "", headers, &body)).await
+                .context("timed out")??;
+            if !(200..300).contains(&resp.status) { bail!("HTTP {}", resp.status); }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, _: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+struct Sqlite(history::SqliteSink);
+
+impl OutputSink for Sqlite {
+    fn name(&self) -> String { "SQLite".into() }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some(res) = out.hit { self.0.record(out.ip, &serde_json::to_value(res)?)?; }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, stats: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.0.finish(stats.found) })
+    }
+}
+
+struct Postgres(pg::PgSink);
+
+impl OutputSink for Postgres {
+    fn name(&self) -> String { "PostgreSQL".into() }
+
+    fn record<'a>(&'a mut self, out: &'a Outcome<'a>) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if let Some(res) = out.hit { self.0.record(out.ip, &serde_json::to_value(res)?).await?; }
+            Ok(())
+        })
+    }
+
+    fn finish<'a>(self: Box<Self>, stats: &'a Stats) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.0.finish(stats.found).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_specs() {
+        assert_eq!(Spec::parse("json:hits.jsonl").unwrap(), Spec::Log(OutputFormat::Json, "hits.jsonl".into()));
+        assert_eq!(Spec::parse("found:C:/scans/ips.txt").unwrap(), Spec::Found("C:/scans/ips.txt".into()));
+        assert_eq!(Spec::parse("cbor:scan.cbor").unwrap(), Spec::Log(OutputFormat::Cbor, "scan.cbor".into()));
+        assert_eq!(Spec::parse("parquet:scan.parquet").unwrap(), Spec::Log(OutputFormat::Parquet, "scan.parquet".into()));
+        assert_eq!(Spec::parse("stdout").unwrap(), Spec::Stdout);
+        assert_eq!(Spec::parse("https://hooks.example.com/scan").unwrap(), Spec::Webhook("https://hooks.example.com/scan".into()));
+        assert_eq!(Spec::parse("sqlite://history.db").unwrap(), Spec::Sqlite("history.db".into()));
+        assert!(matches!(Spec::parse("postgres://u@db/scans").unwrap(), Spec::Postgres(_)));
+        assert!(Spec::parse("results.log").is_err());
+        assert!(Spec::parse("xml:out.xml").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::ber::{oid, tlv};
+use rand::RngCore;
+use serde::Serialize;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const SMB2_NEGOTIATE: u16 = 0;
+const SMB2_SESSION_SETUP: u16 = 1;
+const DIALECTS: [u16; 5] = [0x0202, 0x0210, 0x0300, 0x0302, 0x0311];
+const NTLMSSP: &[u8] = b"NTLMSSP\0";
+
+/// What an SMB server reveals before authentication.
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
+pub struct SmbInfo {
+    /// Highest SMB2/3 dialect the server picked, e.g. `3.1.1`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dialect: Option<String>,
+    /// The legacy SMB1 protocol is still accepted
+    pub smbv1: bool,
+    pub signing_required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netbios_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netbios_domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_domain: Option<String>,
+    /// Windows version from the NTLM challenge, e.g. `10.0.20348`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+}
+
+/// Negotiates SMB1 and SMB2/3 on separate connections and starts an NTLM
+/// session setup to read the host and domain names from the server's challenge.
+/// `netbios` wraps the session in a NetBIOS session request (port 139).
+pub async fn inspect<F, Fut>(connect: F, netbios: bool, wait: Duration) -> io::Result<SmbInfo>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let open = || async {
+        let mut stream = timeout(wait, connect()).await.map_err(|_| io::ErrorKind::TimedOut)??;
+        if netbios { timeout(wait, session_request(&mut stream)).await.map_err(|_| io::ErrorKind::TimedOut)??; }
+        Ok::<_, io::Error>(stream)
+    };
+    let mut info = SmbInfo::default();
+
+    let mut stream = open().await?;
+    let smb2 = timeout(wait, async {
+        let negotiated = exchange(&mut stream, &negotiate_request()).await?;
+        let (dialect, security_mode) = parse_negotiate(&negotiated)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an SMB2 negotiate response"))?;
+        let challenge = exchange(&mut stream, &session_setup_request()).await?;
+        Ok::<_, io::Error>((dialect, security_mode, challenge))
+    }).await;
+    if let Ok(Ok((dialect, security_mode, challenge))) = smb2 {
+        info.dialect = Some(dialect_name(dialect));
+        info.signing_required = security_mode & 0x02 != 0;
+        parse_challenge(&challenge, &mut info);
+    }
+
+    if let Ok(mut stream) = open().await {
+        info.smbv1 = matches!(timeout(wait, exchange(&mut stream, &smb1_negotiate_request())).await, Ok(Ok(resp)) if smb1_accepted(&resp));
+    }
+    if info.dialect.is_none() && !info.smbv1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no SMB dialect accepted"));
+    }
+    Ok(info)
+}
+
+/// NetBIOS session request to the generic `*SMBSERVER` name.
+async fn session_request<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) -> io::Result<()> {
+    let mut body = Vec::new();
+    for name in ["*SMBSERVER", "PULSENET"] {
+        body.push(32);
+        for c in format!("{:<16}", name).bytes().take(16) {
+            body.extend_from_slice(&[b'A' + (c >> 4), b'A' + (c & 0x0f)]);
+        }
+        body.push(0);
+    }
+    let mut pkt = vec![0x81, 0, 0, body.len() as u8];
+    pkt.extend(body);
+    stream.write_all(&pkt).await?;
+    let mut resp = [0u8; 4];
+    stream.read_exact(&mut resp).await?;
+    if resp[0] != 0x82 { return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "NetBIOS session rejected")); }
+    Ok(())
+}
+
+/// Sends one message with the 4-byte direct TCP / NetBIOS framing and reads the reply.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, msg: &[u8]) -> io::Result<Vec<u8>> {
+    let mut framed = (msg.len() as u32).to_be_bytes().to_vec();
+    framed.extend_from_slice(msg);
+    stream.write_all(&framed).await?;
+    let len = stream.read_u32().await? as usize & 0x00ff_ffff;
+    if len > 1 << 20 { return Err(io::Error::new(io::ErrorKind::InvalidData, "oversized SMB message")); }
+    let mut resp = vec![0u8; len];
+    stream.read_exact(&mut resp).await?;
+    Ok(resp)
+}
+
+fn smb2_header(command: u16, message_id: u64) -> Vec<u8> {
+    let mut h = vec![0xfe, b'S', b'M', b'B', 64, 0, 0, 0, 0, 0, 0, 0];
+    h.extend_from_slice(&command.to_le_bytes());
+    h.extend_from_slice(&31u16.to_le_bytes()); // credits requested
+    h.extend_from_slice(&[0; 8]); // flags, next command
+    h.extend_from_slice(&message_id.to_le_bytes());
+    h.extend_from_slice(&[0xff, 0xfe, 0, 0]); // process id
+    h.extend_from_slice(&[0; 4 + 8 + 16]); // tree id, session id, signature
+    h
+}
+
+fn negotiate_request() -> Vec<u8> {
+    let mut msg = smb2_header(SMB2_NEGOTIATE, 0);
+    let mut guid = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut guid);
+    msg.extend_from_slice(&36u16.to_le_bytes());
+    msg.extend_from_slice(&(DIALECTS.len() as u16).to_le_bytes());
+    msg.extend_from_slice(&1u16.to_le_bytes()); // signing enabled
+    msg.extend_from_slice(&[0, 0]);
+    msg.extend_from_slice(&0x7fu32.to_le_bytes()); // capabilities
+    msg.extend_from_slice(&guid);
+    let context_offset = (msg.len() + 8 + DIALECTS.len() * 2).next_multiple_of(8);
+    msg.extend_from_slice(&(context_offset as u32).to_le_bytes());
+    msg.extend_from_slice(&2u16.to_le_bytes());
+    msg.extend_from_slice(&[0, 0]);
+    for d in DIALECTS { msg.extend_from_slice(&d.to_le_bytes()); }
+    msg.resize(context_offset, 0);
+
+    // 3.1.1 requires the preauth integrity context; encryption is offered for completeness
+    let mut salt = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let mut preauth = vec![1, 0, 32, 0, 1, 0]; // one hash (SHA-512), 32-byte salt
+    preauth.extend_from_slice(&salt);
+    for (kind, data) in [(1u16, preauth), (2u16, vec![1, 0, 1, 0])] {
+        msg.resize(msg.len().next_multiple_of(8), 0);
+        msg.extend_from_slice(&kind.to_le_bytes());
+        msg.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&[0; 4]);
+        msg.extend(data);
+    }
+    msg
+}
+
+fn dialect_name(dialect: u16) -> String {
+    match dialect {
+        0x0202 => "2.0.2".into(),
+        0x0210 => "2.1".into(),
+        0x0300 => "3.0".into(),
+        0x0302 => "3.0.2".into(),
+        0x0311 => "3.1.1".into(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+/// (dialect, security mode) of an SMB2 NEGOTIATE response.
+fn parse_negotiate(resp: &[u8]) -> Option<(u16, u16)> {
+    if resp.get(..4)? != b"\xfeSMB" || u16::from_le_bytes([resp[12], resp[13]]) != SMB2_NEGOTIATE { return None; }
+    let body = resp.get(64..70)?;
+    let dialect = u16::from_le_bytes([body[4], body[5]]);
+    (dialect != 0x02ff).then_some((dialect, u16::from_le_bytes([body[2], body[3]])))
+}
+
+fn session_setup_request() -> Vec<u8> {
+    // SPNEGO NegTokenInit offering NTLMSSP with an NTLM NEGOTIATE message
+    let mut ntlm = NTLMSSP.to_vec();
+    ntlm.extend_from_slice(&1u32.to_le_bytes());
+    ntlm.extend_from_slice(&0xe208_8297u32.to_le_bytes());
+    ntlm.extend_from_slice(&[0; 16]); // domain and workstation fields
+    let mech_types = tlv(0xa0, &tlv(0x30, &oid(&[1, 3, 6, 1, 4, 1, 311, 2, 2, 10])));
+    let mech_token = tlv(0xa2, &tlv(0x04, &ntlm));
+    let init = tlv(0xa0, &tlv(0x30, &[mech_types, mech_token].concat()));
+    let token = tlv(0x60, &[oid(&[1, 3, 6, 1, 5, 5, 2]), init].concat());
+
+    let mut msg = smb2_header(SMB2_SESSION_SETUP, 1);
+    msg.extend_from_slice(&25u16.to_le_bytes());
+    msg.extend_from_slice(&[0, 1]); // flags, signing enabled
+    msg.extend_from_slice(&[0; 8]); // capabilities, channel
+    msg.extend_from_slice(&(64u16 + 24).to_le_bytes());
+    msg.extend_from_slice(&(token.len() as u16).to_le_bytes());
+    msg.extend_from_slice(&[0; 8]); // previous session id
+    msg.extend(token);
+    msg
+}
+
+/// Reads names and OS version from the NTLM CHALLENGE inside a session setup response.
+fn parse_challenge(resp: &[u8], info: &mut SmbInfo) -> Option<()> {
+    let start = resp.windows(NTLMSSP.len()).position(|w| w == NTLMSSP)?;
+    let msg = &resp[start..];
+    if u32::from_le_bytes(msg.get(8..12)?.try_into().ok()?) != 2 { return None; }
+    let u16_at = |i: usize| msg.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let flags = u32::from_le_bytes(msg.get(20..24)?.try_into().ok()?);
+    if flags & 0x0200_0000 != 0 && let Some(v) = msg.get(48..52) {
+        info.os_version = Some(format!("{}.{}.{}", v[0], v[1], u16::from_le_bytes([v[2], v[3]])));
+    }
+    let (len, offset) = (u16_at(40)? as usize, u32::from_le_bytes(msg.get(44..48)?.try_into().ok()?) as usize);
+    let mut av = msg.get(offset..offset + len)?;
+    while av.len() >= 4 {
+        let (id, n) = (u16::from_le_bytes([av[0], av[1]]), u16::from_le_bytes([av[2], av[3]]) as usize);
+        if id == 0 { break; }
+        let value = utf16le(av.get(4..4 + n)?);
+        match id {
+            1 => info.netbios_name = Some(value),
+            2 => info.netbios_domain = Some(value),
+            3 => info.dns_name = Some(value),
+            4 => info.dns_domain = Some(value),
+            _ => {}
+        }
+        av = &av[4 + n..];
+    }
+    Some(())
+}
+
+fn utf16le(b: &[u8]) -> String {
+    String::from_utf16_lossy(&b.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect::<Vec<_>>())
+}
+
+fn smb1_negotiate_request() -> Vec<u8> {
+    let mut msg = vec![0xff, b'S', b'M', b'B', 0x72, 0, 0, 0, 0, 0x18];
+    msg.extend_from_slice(&0xc801u16.to_le_bytes()); // unicode, NT status, extended security, long names
+    msg.extend_from_slice(&[0; 12]); // PID high, signature, reserved
+    msg.extend_from_slice(&[0xff, 0xff, 0xff, 0xfe, 0, 0, 0, 0]); // TID, PID, UID, MID
+    let dialect = b"\x02NT LM 0.12\0";
+    msg.push(0); // word count
+    msg.extend_from_slice(&(dialect.len() as u16).to_le_bytes());
+    msg.extend_from_slice(dialect);
+    msg
+}
+
+fn smb1_accepted(resp: &[u8]) -> bool {
+    resp.len() >= 35 && resp[..5] == [0xff, b'S', b'M', b'B', 0x72] && resp[5..9] == [0; 4]
+        && resp[32] >= 1 && u16::from_le_bytes([resp[33], resp[34]]) != 0xffff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_negotiate_request_layout() {
+        let msg = negotiate_request();
+        let context_offset = u32::from_le_bytes(msg[64 + 28..64 + 32].try_into().unwrap()) as usize;
+        assert_eq!(context_offset % 8, 0);
+        assert_eq!(u16::from_le_bytes([msg[context_offset], msg[context_offset + 1]]), 1);
+        assert_eq!(u16::from_le_bytes([msg[64 + 36 + 8], msg[64 + 36 + 9]]), 0x0311);
+    }
+    #[test]
+    fn test_parse_ntlm_challenge() {
+        let mut av = Vec::new();
+        for (id, value) in [(1u16, "FILESRV01"), (2, "CORP"), (4, "corp.local")] {
+            let v: Vec<u8> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            av.extend_from_slice(&id.to_le_bytes());
+            av.extend_from_slice(&(v.len() as u16).to_le_bytes());
+            av.extend(v);
+        }
+        av.extend_from_slice(&[0; 4]);
+        let mut msg = NTLMSSP.to_vec();
+        msg.extend_from_slice(&2u32.to_le_bytes());
+        msg.extend_from_slice(&[0; 8]); // target name
+        msg.extend_from_slice(&0x0280_0000u32.to_le_bytes()); // version + target info
+        msg.extend_from_slice(&[0; 16]); // challenge, reserved
+        msg.extend_from_slice(&(av.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(av.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&56u32.to_le_bytes());
+        msg.extend_from_slice(&[10, 0, 0x4c, 0x4f, 0, 0, 0, 15]); // 10.0.20300
+        msg.extend(av);
+        let mut resp = vec![0u8; 72]; // SMB2 header + session setup body before the token
+        resp.extend(msg);
+
+        let mut info = SmbInfo::default();
+        parse_challenge(&resp, &mut info).unwrap();
+        assert_eq!(info.netbios_name.as_deref(), Some("FILESRV01"));
+        assert_eq!(info.netbios_domain.as_deref(), Some("CORP"));
+        assert_eq!(info.dns_domain.as_deref(), Some("corp.local"));
+        assert_eq!(info.os_version.as_deref(), Some("10.0.20300"));
+    }
+}
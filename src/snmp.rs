@@ -0,0 +1,116 @@
+use crate::ber::{integer, oid, read_tlv, tlv};
+use serde::Serialize;
+use std::io;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+const SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+const SYS_NAME: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 5, 0];
+
+/// A device that answered an SNMP GetRequest with a guessable community.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SnmpInfo {
+    pub community: String,
+    pub version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sys_descr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sys_name: Option<String>,
+}
+
+/// Sends a GetRequest for sysDescr/sysName with every community (v2c and v1)
+/// at once over `sock`, connected to UDP/161, and returns the first answer within `wait`.
+pub async fn probe(sock: UdpSocket, communities: &[String], wait: Duration) -> io::Result<Option<SnmpInfo>> {
+    // Request id encodes which community/version was used: index * 2 + (v1 ? 1 : 0)
+    for (i, community) in communities.iter().enumerate() {
+        for v1 in [false, true] {
+            let id = (i * 2 + v1 as usize) as u32 + 1;
+            sock.send(&build_get(if v1 { 0 } else { 1 }, community, id, &[SYS_DESCR, SYS_NAME])).await?;
+        }
+    }
+    let mut buf = [0u8; 4096];
+    let deadline = tokio::time::Instant::now() + wait;
+    while let Ok(res) = tokio::time::timeout_at(deadline, sock.recv(&mut buf)).await {
+        let Ok(n) = res else { continue }; // ICMP port unreachable surfaces as an error; keep waiting for other replies
+        let Some(resp) = parse_response(&buf[..n]) else { continue };
+        let Some(idx) = (resp.request_id as usize).checked_sub(1) else { continue };
+        let Some(community) = communities.get(idx / 2) else { continue };
+        return Ok(Some(SnmpInfo {
+            community: community.clone(),
+            version: if idx % 2 == 1 { "v1" } else { "v2c" },
+            sys_descr: resp.value(SYS_DESCR),
+            sys_name: resp.value(SYS_NAME),
+        }));
+    }
+    Ok(None)
+}
+
+fn build_get(version: u32, community: &str, request_id: u32, oids: &[&[u32]]) -> Vec<u8> {
+    let varbinds: Vec<u8> = oids.iter().flat_map(|o| tlv(0x30, &[oid(o), vec![0x05, 0x00]].concat())).collect();
+    let pdu = tlv(0xa0, &[integer(request_id), integer(0), integer(0), tlv(0x30, &varbinds)].concat());
+    tlv(0x30, &[integer(version), tlv(0x04, community.as_bytes()), pdu].concat())
+}
+
+struct Response {
+    request_id: u32,
+    varbinds: Vec<(Vec<u8>, Option<String>)>,
+}
+
+impl Response {
+    fn value(&self, arcs: &[u32]) -> Option<String> {
+        let want = oid(arcs);
+        self.varbinds.iter().find(|(o, _)| *o == want[2..]).and_then(|(_, v)| v.clone())
+    }
+}
+
+fn parse_response(buf: &[u8]) -> Option<Response> {
+    let (0x30, msg, _) = read_tlv(buf)? else { return None };
+    let (0x02, _, rest) = read_tlv(msg)? else { return None };
+    let (0x04, _, rest) = read_tlv(rest)? else { return None };
+    let (0xa2, pdu, _) = read_tlv(rest)? else { return None }; // GetResponse
+    let (0x02, id, rest) = read_tlv(pdu)? else { return None };
+    let (0x02, _, rest) = read_tlv(rest)? else { return None }; // error-status
+    let (0x02, _, rest) = read_tlv(rest)? else { return None }; // error-index
+    let (0x30, mut list, _) = read_tlv(rest)? else { return None };
+    let request_id = id.iter().fold(0u32, |acc, &b| acc << 8 | b as u32);
+    let mut varbinds = Vec::new();
+    while let Some((0x30, vb, rest)) = read_tlv(list) {
+        list = rest;
+        let Some((0x06, name, value)) = read_tlv(vb) else { continue };
+        // Only OCTET STRING values are interesting; exceptions like noSuchObject map to None
+        let text = read_tlv(value)
+            .filter(|(tag, _, _)| *tag == 0x04)
+            .map(|(_, v, _)| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|s| !s.is_empty());
+        varbinds.push((name.to_vec(), text));
+    }
+    Some(Response { request_id, varbinds })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_get_request_encoding() {
+        let pkt = build_get(1, "public", 1, &[SYS_DESCR]);
+        let expected = [
+            0x30, 0x26, 0x02, 0x01, 0x01, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xa0, 0x19, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+            0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+        ];
+        assert_eq!(pkt, expected);
+        assert_eq!(integer(200), vec![0x02, 0x02, 0x00, 0xc8]);
+    }
+    #[test]
+    fn test_parse_get_response() {
+        // A response is the request with tag 0xa2 and values filled in
+        let descr = tlv(0x30, &[oid(SYS_DESCR), tlv(0x04, b"Cisco IOS Software")].concat());
+        let name = tlv(0x30, &[oid(SYS_NAME), vec![0x80, 0x00]].concat());
+        let pdu = tlv(0xa2, &[integer(3), integer(0), integer(0), tlv(0x30, &[descr, name].concat())].concat());
+        let msg = tlv(0x30, &[integer(1), tlv(0x04, b"public"), pdu].concat());
+        let resp = parse_response(&msg).unwrap();
+        assert_eq!(resp.request_id, 3);
+        assert_eq!(resp.value(SYS_DESCR).as_deref(), Some("Cisco IOS Software"));
+        assert_eq!(resp.value(SYS_NAME), None);
+    }
+}
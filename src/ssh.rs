@@ -0,0 +1,161 @@
+use base64::Engine;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::time::timeout;
+
+const MSG_KEXINIT: u8 = 20;
+const MSG_KEX_ECDH_INIT: u8 = 30;
+const MSG_KEX_ECDH_REPLY: u8 = 31;
+const CLIENT_ID: &str = "SSH-2.0-PulseNet";
+
+/// What an SSH server revealed before authentication.
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
+pub struct SshInfo {
+    /// Identification string, e.g. `SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.6`
+    pub banner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key_type: Option<String>,
+    /// OpenSSH-style `SHA256:...` fingerprint of the host key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host_key_fingerprint: Option<String>,
+}
+
+/// Reads the server identification and, with `host_key`, runs a
+/// curve25519 key exchange up to the server's reply to capture its host key.
+/// No keys are derived and nothing is authenticated.
+pub async fn inspect<S: AsyncRead + AsyncWrite + Unpin>(stream: S, host_key: bool, wait: Duration) -> io::Result<SshInfo> {
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(format!("{}\r\n", CLIENT_ID).as_bytes()).await?;
+    let banner = timeout(wait, read_identification(&mut stream)).await.map_err(|_| io::ErrorKind::TimedOut)??;
+    let mut info = SshInfo { banner, ..Default::default() };
+    if !host_key { return Ok(info); }
+
+    // A failed exchange (no curve25519 support, server hangs up) still leaves the banner
+    if let Ok(Ok(Some(key))) = timeout(wait, exchange(&mut stream)).await {
+        info.host_key_type = read_string(&key).map(|(t, _)| String::from_utf8_lossy(t).into_owned());
+        info.host_key_fingerprint = Some(fingerprint(&key));
+    }
+    Ok(info)
+}
+
+/// Servers may send other lines before the `SSH-` line (RFC 4253 4.2).
+async fn read_identification<R: AsyncBufReadExt + Unpin>(r: &mut R) -> io::Result<String> {
+    for _ in 0..20 {
+        let mut line = Vec::new();
+        if (&mut *r).take(1024).read_until(b'\n', &mut line).await? == 0 { break; }
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        if line.starts_with("SSH-") { return Ok(line); }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "no SSH identification string"))
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>) -> io::Result<Option<Vec<u8>>> {
+    stream.get_mut().write_all(&packet(&kexinit())).await?;
+    let mut ephemeral = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut ephemeral);
+    let mut init = vec![MSG_KEX_ECDH_INIT];
+    put_string(&mut init, &ephemeral);
+    let mut sent_init = false;
+    for _ in 0..8 {
+        let payload = read_packet(stream).await?;
+        match payload.first() {
+            Some(&MSG_KEXINIT) if !sent_init => {
+                stream.get_mut().write_all(&packet(&init)).await?;
+                sent_init = true;
+            }
+            Some(&MSG_KEX_ECDH_REPLY) => return Ok(read_string(&payload[1..]).map(|(k, _)| k.to_vec())),
+            Some(1) => return Ok(None), // SSH_MSG_DISCONNECT
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+fn kexinit() -> Vec<u8> {
+    let mut p = vec![MSG_KEXINIT];
+    let mut cookie = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut cookie);
+    p.extend_from_slice(&cookie);
+    for list in [
+        "curve25519-sha256,curve25519-sha256@libssh.org",
+        "ssh-ed25519,ecdsa-sha2-nistp256,rsa-sha2-512,rsa-sha2-256,ssh-rsa",
+        "chacha20-poly1305@openssh.com,aes128-ctr,aes256-gcm@openssh.com",
+        "chacha20-poly1305@openssh.com,aes128-ctr,aes256-gcm@openssh.com",
+        "hmac-sha2-256,hmac-sha1",
+        "hmac-sha2-256,hmac-sha1",
+        "none",
+        "none",
+        "",
+        "",
+    ] {
+        put_string(&mut p, list.as_bytes());
+    }
+    p.push(0); // first_kex_packet_follows
+    p.extend_from_slice(&[0; 4]);
+    p
+}
+
+/// Binary packet without encryption or MAC: length, padding length, payload, padding.
+fn packet(payload: &[u8]) -> Vec<u8> {
+    let mut pad = 8 - (5 + payload.len()) % 8;
+    if pad < 4 { pad += 8; }
+    let mut p = Vec::with_capacity(5 + payload.len() + pad);
+    p.extend_from_slice(&((1 + payload.len() + pad) as u32).to_be_bytes());
+    p.push(pad as u8);
+    p.extend_from_slice(payload);
+    p.extend(std::iter::repeat_n(0, pad));
+    p
+}
+
+async fn read_packet<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = r.read_u32().await? as usize;
+    if !(5..=256 * 1024).contains(&len) { return Err(io::Error::new(io::ErrorKind::InvalidData, "bad SSH packet length")); }
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body).await?;
+    let pad = body[0] as usize;
+    if pad + 1 > len { return Err(io::Error::new(io::ErrorKind::InvalidData, "bad SSH padding")); }
+    Ok(body[1..len - pad].to_vec())
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    buf.extend_from_slice(s);
+}
+
+fn read_string(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_be_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+    let s = buf.get(4..4 + len)?;
+    Some((s, &buf[4 + len..]))
+}
+
+pub fn fingerprint(key_blob: &[u8]) -> String {
+    format!("SHA256:{}", base64::engine::general_purpose::STANDARD_NO_PAD.encode(Sha256::digest(key_blob)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_packet_framing() {
+        let p = packet(&[MSG_KEXINIT, 1, 2]);
+        assert_eq!(p.len() % 8, 0);
+        assert!(p[4] >= 4);
+        let payload = tokio::runtime::Builder::new_current_thread().build().unwrap()
+            .block_on(read_packet(&mut &p[..])).unwrap();
+        assert_eq!(payload, vec![MSG_KEXINIT, 1, 2]);
+    }
+    #[test]
+    fn test_fingerprint_format() {
+        // ssh-ed25519 blob: string "ssh-ed25519", string <32 byte key>
+        let mut blob = Vec::new();
+        put_string(&mut blob, b"ssh-ed25519");
+        put_string(&mut blob, &[0u8; 32]);
+        assert_eq!(read_string(&blob).unwrap().0, b"ssh-ed25519");
+        let fp = fingerprint(&blob);
+        assert!(fp.starts_with("SHA256:") && fp.len() == 7 + 43);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::PortProbe;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// This many ports probed and every one open is a host answering any SYN.
+const ALL_OPEN_MIN: usize = 8;
+/// Open ports needed before identical latencies say anything.
+const UNIFORM_MIN: usize = 6;
+/// Below this a 0ms spread is ordinary (loopback, the local LAN).
+const UNIFORM_FLOOR_MS: u128 = 10;
+/// LaBrea advertises a window of a few bytes; real stacks offer kilobytes.
+const TARPIT_WINDOW: u32 = 128;
+/// Longest wait for the peer to acknowledge the tarpit check's bytes.
+const STALL_WAIT: Duration = Duration::from_secs(1);
+
+/// Honeypot signs in the connect results alone: every probed port open, or
+/// handshakes all taking exactly as long.
+pub fn passive(ports: &[PortProbe]) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+    if ports.len() >= ALL_OPEN_MIN && ports.iter().all(PortProbe::is_open) {
+        reasons.push("all_ports_open");
+    }
+    let latencies: Vec<u128> = ports.iter().filter_map(|p| p.latency).collect();
+    if latencies.len() >= UNIFORM_MIN
+        && let (Some(&min), Some(&max)) = (latencies.iter().min(), latencies.iter().max())
+        && min >= UNIFORM_FLOOR_MS && max == min
+    {
+        reasons.push("uniform_latency");
+    }
+    reasons
+}
+
+/// LaBrea-style tarpits on a fresh connection: a window of a few bytes, or
+/// two bytes that never get acknowledged. Linux only, it reads TCP_INFO.
+pub async fn tarpit(stream: TcpStream, wait: Duration) -> Option<&'static str> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    {
+        use tokio::io::AsyncWriteExt;
+        let mut stream = stream;
+        if tcp_info(&stream)?.tcpi_snd_wnd < TARPIT_WINDOW { return Some("tiny_window"); }
+        tokio::time::timeout(wait, stream.write_all(b"\r\n")).await.ok()?.ok()?;
+        let deadline = tokio::time::Instant::now() + wait.min(STALL_WAIT);
+        while tokio::time::Instant::now() < deadline {
+            if tcp_info(&stream)?.tcpi_unacked == 0 { return None; }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        Some("stalled")
+    }
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    {
+        let _ = (stream, wait);
+        None
+    }
+}
+
+/// None on kernels too old to report the send window.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn tcp_info(stream: &TcpStream) -> Option<libc::tcp_info> {
+    use std::os::fd::AsRawFd;
+    let mut info: libc::tcp_info = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_INFO, &mut info as *mut _ as *mut libc::c_void, &mut len)
+    };
+    let needed = std::mem::offset_of!(libc::tcp_info, tcpi_snd_wnd) + std::mem::size_of::<u32>();
+    (rc == 0 && len as usize >= needed).then_some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn open(port: u16, ms: u128) -> PortProbe {
+        PortProbe { port, latency: Some(ms), error: None, attempts: 1, icmp: None, family: None, os_error: None }
+    }
+    #[test]
+    fn test_passive() {
+        let every: Vec<_> = (1..=8).map(|p| open(p, 40)).collect();
+        assert_eq!(passive(&every), vec!["all_ports_open", "uniform_latency"]);
+        let varied: Vec<_> = (1..=8).map(|p| open(p, 30 + p as u128)).collect();
+        assert_eq!(passive(&varied), vec!["all_ports_open"]);
+        // Loopback answers everything in 0ms
+        let local: Vec<_> = (1..=6).map(|p| open(p, 0)).collect();
+        assert!(passive(&local).is_empty());
+        let mut few = vec![open(22, 40), open(80, 41)];
+        few.push(PortProbe { latency: None, error: Some(crate::ScanError::ConnectionRefused), ..open(443, 0) });
+        assert!(passive(&few).is_empty());
+    }
+}
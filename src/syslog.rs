@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+
+const FACILITY_LOCAL0: u8 = 16;
+pub const SEVERITY_NOTICE: u8 = 5;
+/// Private enterprise number placeholder used in examples of RFC 5424.
+const SD_ID: &str = "pulsenet@32473";
+
+enum Transport {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+/// RFC 5424 sender over UDP (`udp://host[:514]`) or a local socket (`/dev/log`).
+pub struct Syslog {
+    transport: Transport,
+    hostname: String,
+    pid: u32,
+}
+
+impl Syslog {
+    pub fn connect(spec: &str) -> Result<Self> {
+        let transport = if let Some(addr) = spec.strip_prefix("udp://") {
+            let addr = if addr.contains(':') { addr.to_string() } else { format!("{}:514", addr) };
+            let target = addr.to_socket_addrs().with_context(|| format!("invalid syslog address '{}'", spec))?
+                .find(|a| a.is_ipv4()).with_context(|| format!("no IPv4 address for syslog server '{}'", spec))?;
+            let sock = UdpSocket::bind("0.0.0.0:0")?;
+            sock.connect(target)?;
+            Transport::Udp(sock)
+        } else {
+            local(spec.strip_prefix("unix://").unwrap_or(spec))?
+        };
+        Ok(Self { transport, hostname: hostname(), pid: std::process::id() })
+    }
+
+    pub fn send(&self, severity: u8, msgid: &str, params: &[(&str, String)], msg: &str) -> io::Result<()> {
+        let line = format(FACILITY_LOCAL0, severity, &chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.6f%:z").to_string(),
+            &self.hostname, self.pid, msgid, params, msg);
+        match &self.transport {
+            Transport::Udp(sock) => sock.send(line.as_bytes()).map(drop),
+            #[cfg(unix)]
+            Transport::Unix(sock) => sock.send(line.as_bytes()).map(drop),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn local(path: &str) -> Result<Transport> {
+    let sock = std::os::unix::net::UnixDatagram::unbound()?;
+    sock.connect(path).with_context(|| format!("failed to connect to syslog socket '{}'", path))?;
+    Ok(Transport::Unix(sock))
+}
+
+#[cfg(not(unix))]
+fn local(path: &str) -> Result<Transport> {
+    anyhow::bail!("local syslog socket '{}' is only supported on Unix, use udp://host:514", path)
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname").ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD-ID k="v" ...] MSG`
+#[allow(clippy::too_many_arguments)]
+fn format(facility: u8, severity: u8, timestamp: &str, hostname: &str, pid: u32, msgid: &str, params: &[(&str, String)], msg: &str) -> String {
+    let sd = if params.is_empty() {
+        "-".to_string()
+    } else {
+        let escape = |v: &str| v.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+        let pairs: String = params.iter().map(|(k, v)| format!(" {}=\"{}\"", k, escape(v))).collect();
+        format!("[{}{}]", SD_ID, pairs)
+    };
+    format!("<{}>1 {} {} PulseNet {} {} {} {}", facility as u16 * 8 + severity as u16, timestamp, hostname, pid, msgid, sd, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_rfc5424_format() {
+        let line = format(FACILITY_LOCAL0, SEVERITY_NOTICE, "2026-10-15T08:50:00.000000+00:00", "scanner", 42, "hit",
+            &[("ip", "10.0.0.5".into()), ("title", "a \"b\" [c]".into())], "10.0.0.5, Ports: 22");
+        assert_eq!(line, r#"<133>1 2026-10-15T08:50:00.000000+00:00 scanner PulseNet 42 hit [pulsenet@32473 ip="10.0.0.5" title="a \"b\" [c\]"] 10.0.0.5, Ports: 22"#);
+    }
+}
@@ -0,0 +1,454 @@
+use crate::enrich::AsnDb;
+use crate::resolver::Resolver;
+use anyhow::{bail, Context, Result};
+use ipnet::Ipv4Net;
+use rand::Rng;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const RADB_WHOIS: (&str, u16) = ("whois.radb.net", 43);
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Parses `AS15169`, `as15169` or `15169`.
+pub fn parse_asn(s: &str) -> Result<u32> {
+    let s = s.trim();
+    let digits = s.strip_prefix("AS").or_else(|| s.strip_prefix("as")).unwrap_or(s);
+    digits.parse().with_context(|| format!("invalid ASN '{}'", s))
+}
+
+/// Resolves announced prefixes for each ASN in a comma separated list, from
+/// the local ASN database when one is loaded, otherwise from RADb route objects.
+pub async fn resolve_asn_prefixes(asns: &str, db: Option<&AsnDb>, resolver: &Resolver) -> Result<Vec<Ipv4Net>> {
+    let mut nets = Vec::new();
+    for s in asns.split(',').filter(|s| !s.trim().is_empty()) {
+        let asn = parse_asn(s)?;
+        let found = match db {
+            Some(db) => db.prefixes(asn)?,
+            None => fetch_radb_prefixes(asn, resolver).await?,
+        };
+        if found.is_empty() { bail!("no IPv4 prefixes found for AS{}", asn); }
+        nets.extend(found);
+    }
+    // Route objects overlap heavily (aggregates plus more-specifics)
+    Ok(Ipv4Net::aggregate(&nets))
+}
+
+async fn fetch_radb_prefixes(asn: u32, resolver: &Resolver) -> Result<Vec<Ipv4Net>> {
+    let query = async {
+        let mut stream = resolver.connect(RADB_WHOIS.0, RADB_WHOIS.1).await?;
+        stream.write_all(format!("-i origin AS{}\r\n", asn).as_bytes()).await?;
+        let mut body = String::new();
+        stream.read_to_string(&mut body).await?;
+        Ok::<_, std::io::Error>(body)
+    };
+    let body = tokio::time::timeout(WHOIS_TIMEOUT, query).await
+        .context("RADb query timed out")?
+        .with_context(|| format!("RADb query for AS{} failed", asn))?;
+    Ok(parse_route_objects(&body))
+}
+
+fn parse_route_objects(body: &str) -> Vec<Ipv4Net> {
+    body.lines()
+        .filter_map(|l| l.strip_prefix("route:"))
+        .filter_map(|v| v.trim().parse().ok())
+        .collect()
+}
+
+/// One `--cidr` entry: a prefix, or an address with nmap-style octet ranges
+/// (`192.168.1.1-50`, `10.0.0-3.1`, `192.168.1.*`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetSpec {
+    Net(Ipv4Net),
+    /// Inclusive bounds per octet
+    Octets([(u8, u8); 4]),
+}
+
+impl TargetSpec {
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.contains('/') {
+            return s.parse().map(Self::Net).with_context(|| format!("invalid CIDR range '{}'", s));
+        }
+        let invalid = || format!("invalid target '{}'", s);
+        let parts: Vec<&str> = s.split('.').collect();
+        if parts.len() != 4 { bail!("{}: expected four octets", invalid()); }
+        let mut octets = [(0, 0); 4];
+        for (octet, part) in octets.iter_mut().zip(parts) {
+            let (lo, hi) = match part {
+                "*" => ("0", "255"),
+                _ => part.split_once('-').unwrap_or((part, part)),
+            };
+            // `-50` and `200-` leave one end open, as in nmap
+            let lo: u8 = if lo.is_empty() { 0 } else { lo.parse().with_context(invalid)? };
+            let hi: u8 = if hi.is_empty() { 255 } else { hi.parse().with_context(invalid)? };
+            if lo > hi { bail!("{}: {} is after {}", invalid(), lo, hi); }
+            *octet = (lo, hi);
+        }
+        Ok(Self::Octets(octets))
+    }
+
+    /// Number of addresses covered. Prefixes leave out their network and
+    /// broadcast addresses; octet ranges are taken as written.
+    pub fn count(&self) -> u64 {
+        match *self {
+            Self::Net(net) if net.prefix_len() >= 31 => 1 << (32 - net.prefix_len()),
+            Self::Net(net) => (1 << (32 - net.prefix_len())) - 2,
+            Self::Octets(octets) => octets.iter().map(|&(lo, hi)| (hi - lo) as u64 + 1).product(),
+        }
+    }
+
+    /// The `i`th address in order, for `i` below `count`.
+    pub fn nth(&self, i: u64) -> Ipv4Addr {
+        match *self {
+            Self::Net(net) => {
+                let first = u32::from(net.network()) + (net.prefix_len() < 31) as u32;
+                Ipv4Addr::from(first + i as u32)
+            }
+            Self::Octets(octets) => {
+                let mut rest = i;
+                let mut out = [0u8; 4];
+                for (o, &(lo, hi)) in out.iter_mut().zip(&octets).rev() {
+                    let span = (hi - lo) as u64 + 1;
+                    *o = lo + (rest % span) as u8;
+                    rest /= span;
+                }
+                Ipv4Addr::from(out)
+            }
+        }
+    }
+}
+
+/// A random order of `0..n` handed out one index at a time, so large target
+/// sets can be shuffled without holding them: a keyed bijection of the
+/// smallest power of two covering `n`, skipping what lands past `n`.
+#[derive(Debug, Clone)]
+pub struct Permutation {
+    n: u64,
+    /// The power of two minus one
+    mask: u64,
+    shift: u32,
+    keys: [u64; 3],
+    next: u64,
+}
+
+impl Permutation {
+    pub fn new<R: Rng>(n: u64, rng: &mut R) -> Self {
+        let bits = 64 - n.saturating_sub(1).leading_zeros();
+        let mask = if bits == 0 { 0 } else { u64::MAX >> (64 - bits) };
+        // Multipliers are odd so multiplication stays invertible
+        Self { n, mask, shift: (bits / 2).max(1), keys: [rng.r#gen(), rng.r#gen::<u64>() | 1, rng.r#gen::<u64>() | 1], next: 0 }
+    }
+
+    /// Each step (xor, odd multiply, xorshift) is invertible within the mask.
+    fn mix(&self, x: u64) -> u64 {
+        let mut x = (x ^ self.keys[0]) & self.mask;
+        x = x.wrapping_mul(self.keys[1]) & self.mask;
+        x ^= x >> self.shift;
+        x = x.wrapping_mul(self.keys[2]) & self.mask;
+        x ^ (x >> self.shift)
+    }
+}
+
+impl Iterator for Permutation {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.n > 0 && self.next <= self.mask {
+            let x = self.mix(self.next);
+            self.next += 1;
+            if x < self.n { return Some(x); }
+        }
+        None
+    }
+}
+
+/// Where hits cluster: hits grouped per /24, each group as the smallest
+/// prefix covering its hits, busiest first. Lone hits are left out.
+pub fn clusters(hits: &[Ipv4Addr]) -> Vec<(Ipv4Net, usize)> {
+    let mut groups: std::collections::BTreeMap<u32, (u32, u32, usize)> = std::collections::BTreeMap::new();
+    for &ip in hits {
+        let ip = u32::from(ip);
+        let (lo, hi, n) = groups.entry(ip & 0xffff_ff00).or_insert((ip, ip, 0));
+        (*lo, *hi, *n) = ((*lo).min(ip), (*hi).max(ip), *n + 1);
+    }
+    let mut clusters: Vec<(Ipv4Net, usize)> = groups.into_values().filter(|&(_, _, n)| n > 1).map(|(lo, hi, n)| {
+        // Bits above the highest one that differs are shared by every hit
+        let prefix = (lo ^ hi).leading_zeros() as u8;
+        (Ipv4Net::new(Ipv4Addr::from(lo), prefix).expect("prefix is at most 32").trunc(), n)
+    }).collect();
+    clusters.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    clusters
+}
+
+/// One of `count` disjoint slices of the address space. Membership is a hash
+/// of the address, so instances given the same targets split them without
+/// coordinating, whatever order they generate them in.
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    pub fn new(index: u32, count: u32) -> Result<Self> {
+        if count == 0 || index >= count { bail!("--shard-index must be between 0 and {}", count.saturating_sub(1)); }
+        Ok(Self { index, count })
+    }
+
+    pub fn owns(&self, ip: Ipv4Addr) -> bool {
+        // murmur3 finalizer: neighbouring addresses land in different shards
+        let mut h = u32::from(ip);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2_ae35);
+        h ^= h >> 16;
+        h % self.count == self.index
+    }
+}
+
+/// Union of inclusive IPv4 ranges supporting uniform random picks
+/// without materializing every address.
+#[derive(Clone)]
+pub struct RangeSet {
+    ranges: Vec<(u32, u32)>,
+    /// Addresses covered by ranges[..=i]
+    cumulative: Vec<u64>,
+}
+
+impl RangeSet {
+    pub fn new(mut ranges: Vec<(u32, u32)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start as u64 <= last.1 as u64 + 1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        let mut total = 0u64;
+        let cumulative = merged.iter().map(|(s, e)| { total += (*e - *s) as u64 + 1; total }).collect();
+        Self { ranges: merged, cumulative }
+    }
+
+    pub fn total(&self) -> u64 { self.cumulative.last().copied().unwrap_or(0) }
+
+    pub fn pick<R: Rng>(&self, rng: &mut R) -> Ipv4Addr {
+        let n = rng.gen_range(0..self.total());
+        let idx = self.cumulative.partition_point(|&c| c <= n);
+        let before = if idx == 0 { 0 } else { self.cumulative[idx - 1] };
+        Ipv4Addr::from(self.ranges[idx].0 + (n - before) as u32)
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        let idx = self.ranges.partition_point(|&(_, end)| end < ip);
+        self.ranges.get(idx).is_some_and(|&(start, _)| start <= ip)
+    }
+
+    /// Addresses covered by both sets.
+    pub fn intersect(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some(&a), Some(&b)) = (self.ranges.get(i), other.ranges.get(j)) {
+            let (start, end) = (a.0.max(b.0), a.1.min(b.1));
+            if start <= end { ranges.push((start, end)); }
+            if a.1 < b.1 { i += 1 } else { j += 1 }
+        }
+        RangeSet::new(ranges)
+    }
+}
+
+/// Set of IPv4 addresses as a bitmap over the whole space, allocated per /16
+/// on first use: constant-time inserts and at most 8 KiB per touched /16.
+pub struct IpSet {
+    pages: Vec<Option<Box<[u64; 1024]>>>,
+}
+
+impl Default for IpSet {
+    fn default() -> Self {
+        Self { pages: (0..1 << 16).map(|_| None).collect() }
+    }
+}
+
+impl IpSet {
+    /// True if the address wasn't in the set yet.
+    pub fn insert(&mut self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        let page = self.pages[(ip >> 16) as usize].get_or_insert_with(|| Box::new([0; 1024]));
+        let (word, bit) = (((ip & 0xffff) >> 6) as usize, ip & 63);
+        let fresh = page[word] & (1 << bit) == 0;
+        page[word] |= 1 << bit;
+        fresh
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        self.pages[(ip >> 16) as usize].as_ref().is_some_and(|page| page[((ip & 0xffff) >> 6) as usize] & (1 << (ip & 63)) != 0)
+    }
+}
+
+/// Authorized address space from a scope file: one CIDR, address or
+/// `first-last` range per line, `#` comments. Any other line is an error,
+/// a guardrail shouldn't silently shrink.
+pub fn load_scope(path: &str) -> Result<RangeSet> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("failed to read scope file '{}'", path))?;
+    let mut ranges = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() { continue; }
+        let range = if let Ok(net) = line.parse::<Ipv4Net>() {
+            Some((u32::from(net.network()), u32::from(net.broadcast())))
+        } else if line.contains('-') {
+            parse_range(line).ok()
+        } else {
+            line.parse::<Ipv4Addr>().ok().map(|ip| (u32::from(ip), u32::from(ip)))
+        };
+        match range {
+            Some(range) => ranges.push(range),
+            None => bail!("invalid scope entry '{}' on line {} of '{}'", line, n + 1, path),
+        }
+    }
+    if ranges.is_empty() { bail!("scope file '{}' lists no addresses", path); }
+    Ok(RangeSet::new(ranges))
+}
+
+/// Parses `first-last` into an inclusive range of addresses as integers.
+pub fn parse_range(s: &str) -> Result<(u32, u32)> {
+    let (first, last) = s.split_once('-').with_context(|| format!("invalid range '{}' (expected first-last)", s))?;
+    let parse = |ip: &str| ip.trim().parse::<Ipv4Addr>().map(u32::from).with_context(|| format!("invalid address '{}' in range '{}'", ip.trim(), s));
+    let (first, last) = (parse(first)?, parse(last)?);
+    if first > last { bail!("range '{}' ends before it starts", s); }
+    Ok((first, last))
+}
+
+/// IPv4 ranges assigned/allocated to `cc` in an RIR delegated stats file
+/// (`registry|cc|ipv4|start|count|date|status[|opaque-id]`).
+pub fn parse_rir_delegations(content: &str, cc: &str) -> Vec<(u32, u32)> {
+    content.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let f: Vec<&str> = l.split('|').collect();
+            if f.len() < 7 || f[2] != "ipv4" || !f[1].eq_ignore_ascii_case(cc) { return None; }
+            if !matches!(f[6], "allocated" | "assigned") { return None; }
+            let start = u32::from(f[3].parse::<Ipv4Addr>().ok()?);
+            let count: u64 = f[4].parse().ok()?;
+            if count == 0 { return None; }
+            Some((start, (start as u64 + count - 1).min(u32::MAX as u64) as u32))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_asn_and_routes() {
+        assert_eq!(parse_asn("AS15169").unwrap(), 15169);
+        assert_eq!(parse_asn("13335").unwrap(), 13335);
+        assert!(parse_asn("ASX").is_err());
+        let body = "route:          8.8.8.0/24\norigin:         AS15169\n\nroute6:         2001:4860::/32\nroute:  8.8.4.0/24\n";
+        let nets = parse_route_objects(body);
+        assert_eq!(nets, vec!["8.8.8.0/24".parse::<Ipv4Net>().unwrap(), "8.8.4.0/24".parse().unwrap()]);
+    }
+    #[test]
+    fn test_target_specs() {
+        let addrs = |s: &str| {
+            let spec = TargetSpec::parse(s).unwrap();
+            (0..spec.count()).map(|i| spec.nth(i).to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(addrs("192.168.1.1-3"), ["192.168.1.1", "192.168.1.2", "192.168.1.3"]);
+        assert_eq!(addrs("10.0.0-1.7"), ["10.0.0.7", "10.0.1.7"]);
+        assert_eq!(addrs("192.0.2.0/30"), ["192.0.2.1", "192.0.2.2"]);
+        assert_eq!(addrs("198.51.100.9"), ["198.51.100.9"]);
+        let star = addrs("192.168.1.*");
+        assert_eq!((star.len(), star[0].as_str(), star[255].as_str()), (256, "192.168.1.0", "192.168.1.255"));
+        assert_eq!(addrs("10.0.0.-2").len(), 3);
+        assert_eq!(addrs("198.51.100.8/31"), ["198.51.100.8", "198.51.100.9"]);
+        assert_eq!(addrs("10.0-1.2.*").len(), 512);
+        for bad in ["192.168.1", "192.168.1.50-1", "192.168.1.256", "10.0.0.0/33", "example.com"] {
+            assert!(TargetSpec::parse(bad).is_err(), "{}", bad);
+        }
+    }
+    #[test]
+    fn test_permutation() {
+        let mut rng = crate::seeded_rng(Some(7));
+        for n in [0, 1, 2, 5, 256, 1000] {
+            let mut order: Vec<u64> = Permutation::new(n, &mut rng).collect();
+            let shuffled = order.windows(2).any(|w| w[0] > w[1]);
+            order.sort_unstable();
+            assert_eq!(order, (0..n).collect::<Vec<_>>());
+            // A handful of items can come out in order by chance
+            assert!(shuffled || n < 256, "{}", n);
+        }
+    }
+    #[test]
+    fn test_hit_clusters() {
+        let ips: Vec<Ipv4Addr> = ["185.1.2.3", "185.1.2.60", "185.1.2.17", "10.0.0.1", "10.0.0.129", "192.0.2.7", "10.0.1.5"]
+            .iter().map(|s| s.parse().unwrap()).collect();
+        let found: Vec<(String, usize)> = clusters(&ips).into_iter().map(|(net, n)| (net.to_string(), n)).collect();
+        assert_eq!(found, [("185.1.2.0/26".to_string(), 3), ("10.0.0.0/24".to_string(), 2)]);
+    }
+    #[test]
+    fn test_shards_partition() {
+        let shards: Vec<Shard> = (0..10).map(|i| Shard::new(i, 10).unwrap()).collect();
+        let mut sizes = [0; 10];
+        let spec = TargetSpec::parse("10.0.0-15.*").unwrap();
+        for ip in (0..spec.count()).map(|i| spec.nth(i)) {
+            let owners: Vec<usize> = (0..10).filter(|&i| shards[i].owns(ip)).collect();
+            assert_eq!(owners.len(), 1);
+            sizes[owners[0]] += 1;
+        }
+        assert!(sizes.iter().all(|&n| (300..520).contains(&n)), "{:?}", sizes);
+        assert!(Shard::new(10, 10).is_err() && Shard::new(0, 0).is_err());
+    }
+    #[test]
+    fn test_country_ranges() {
+        let rir = "2|ripencc|1700000000|3|19830705|20240101|+0100\n\
+                   ripencc|FI|ipv4|2.248.0.0|256|20100719|allocated|abc\n\
+                   ripencc|SE|ipv4|2.249.0.0|256|20100719|allocated|abc\n\
+                   ripencc|FI|ipv4|2.248.1.0|256|20100719|assigned|abc\n\
+                   ripencc|FI|ipv6|2001:14b8::|29|20100719|allocated|abc\n";
+        let ranges = parse_rir_delegations(rir, "fi");
+        assert_eq!(ranges.len(), 2);
+        let set = RangeSet::new(ranges);
+        assert_eq!(set.total(), 512);
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let o = set.pick(&mut rng).octets();
+            assert_eq!((o[0], o[1]), (2, 248));
+            assert!(o[2] <= 1);
+        }
+    }
+    #[test]
+    fn test_ip_set() {
+        let mut set = IpSet::default();
+        assert!(set.insert(Ipv4Addr::new(10, 0, 0, 1)) && set.insert(Ipv4Addr::new(10, 0, 0, 65)) && set.insert(Ipv4Addr::new(255, 255, 255, 255)));
+        assert!(!set.insert(Ipv4Addr::new(10, 0, 0, 1)) && !set.insert(Ipv4Addr::new(255, 255, 255, 255)));
+        assert!(set.contains(Ipv4Addr::new(10, 0, 0, 65)) && !set.contains(Ipv4Addr::new(10, 0, 0, 2)) && !set.contains(Ipv4Addr::new(8, 8, 8, 8)));
+        assert_eq!(set.pages.iter().filter(|p| p.is_some()).count(), 2);
+    }
+    #[test]
+    fn test_scope_ranges() {
+        let path = std::env::temp_dir().join(format!("pulsenet-scope-{}.txt", std::process::id()));
+        std::fs::write(&path, "# engagement 42
+10.0.0.0/24
+192.0.2.10-192.0.2.20 # DMZ
+198.51.100.7
+").unwrap();
+        let scope = load_scope(path.to_str().unwrap()).unwrap();
+        std::fs::write(&path, "10.0.0.0/24
+10.0.1.0/33
+").unwrap();
+        assert!(load_scope(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(scope.total(), 256 + 11 + 1);
+        assert!(scope.contains(Ipv4Addr::new(192, 0, 2, 20)) && !scope.contains(Ipv4Addr::new(192, 0, 2, 21)));
+        assert!(!scope.contains(Ipv4Addr::new(9, 255, 255, 255)));
+        assert_eq!(parse_range("203.0.113.10 - 203.0.113.200").unwrap(), (0xcb00710a, 0xcb0071c8));
+        assert!(parse_range("203.0.113.200-203.0.113.10").is_err() && parse_range("203.0.113.10-200").is_err());
+        let nets = RangeSet::new(vec![(u32::from(Ipv4Addr::new(10, 0, 0, 128)), u32::from(Ipv4Addr::new(10, 0, 3, 0)))]);
+        assert_eq!(scope.intersect(&nets).total(), 128);
+    }
+}
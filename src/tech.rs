@@ -0,0 +1,75 @@
+use crate::http::Response;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Rules shipped with the binary, used unless --tech-rules points elsewhere.
+const DEFAULT_RULES: &str = include_str!("tech_rules.json");
+
+/// Case-insensitive substring matches; any single match tags the technology.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    /// Header name to a value substring; an empty value only requires the header
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Cookie names set by the response
+    #[serde(default)]
+    cookies: Vec<String>,
+    /// Snippets of the response body
+    #[serde(default)]
+    html: Vec<String>,
+}
+
+/// Wappalyzer-style technology rules keyed by technology name.
+#[derive(Debug)]
+pub struct TechRules(BTreeMap<String, Rule>);
+
+impl TechRules {
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let rules = match path {
+            Some(p) => {
+                let content = std::fs::read_to_string(p).with_context(|| format!("failed to read tech rules '{}'", p))?;
+                serde_json::from_str(&content).with_context(|| format!("invalid tech rules in '{}'", p))?
+            }
+            None => serde_json::from_str(DEFAULT_RULES).context("invalid built-in tech rules")?,
+        };
+        Ok(Self(rules))
+    }
+
+    pub fn detect(&self, resp: &Response) -> Vec<String> {
+        let body = String::from_utf8_lossy(&resp.body).to_ascii_lowercase();
+        let cookies: Vec<&str> = resp.headers.iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|(_, v)| v.split('=').next().map(str::trim))
+            .collect();
+        self.0.iter().filter(|(_, rule)| {
+            rule.headers.iter().any(|(name, pattern)| resp.headers.iter().any(|(k, v)| {
+                k.eq_ignore_ascii_case(name) && v.to_ascii_lowercase().contains(&pattern.to_ascii_lowercase())
+            }))
+                || rule.cookies.iter().any(|c| cookies.iter().any(|name| name.eq_ignore_ascii_case(c)))
+                || rule.html.iter().any(|snippet| body.contains(&snippet.to_ascii_lowercase()))
+        }).map(|(name, _)| name.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_detect_builtin_rules() {
+        let rules = TechRules::load(None).unwrap();
+        let resp = Response {
+            status: 200,
+            headers: vec![
+                ("Server".into(), "nginx/1.24.0".into()),
+                ("X-Powered-By".into(), "PHP/8.2".into()),
+                ("Set-Cookie".into(), "grafana_session=abc; Path=/".into()),
+            ],
+            body: b"<link href='/wp-content/themes/x.css'>".to_vec(),
+        };
+        assert_eq!(rules.detect(&resp), ["Grafana", "PHP", "WordPress", "nginx"]);
+        let bare = Response { status: 404, headers: Vec::new(), body: Vec::new() };
+        assert!(rules.detect(&bare).is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use serde::Serialize;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout_at, Instant};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+/// Banner bytes kept, and characters in the sanitized banner.
+const MAX_BANNER: usize = 4096;
+const MAX_TEXT: usize = 1024;
+const PROMPTS: &[&str] = &["login:", "username:", "user name:", "password:", "user:"];
+
+/// What a Telnet service shows before anyone logs in.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TelnetInfo {
+    /// Greeting and login prompt, stripped of escape sequences and control
+    /// characters, lines joined with ` | `
+    pub banner: String,
+    /// Options the server asked for or offered, in order, e.g. `echo`, `terminal-type`
+    pub options: Vec<String>,
+}
+
+/// Answers option negotiation the way a dumb client would: it will suppress
+/// go-ahead and let the server echo and suppress go-ahead, and refuses the
+/// rest, so no terminal type, window size or environment is ever sent.
+/// Reads until a login prompt, `wait` without data, or the banner limit.
+pub async fn grab<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, wait: Duration) -> io::Result<TelnetInfo> {
+    let deadline = Instant::now() + wait * 3;
+    let mut parser = Parser::default();
+    let mut buf = [0u8; 1024];
+    while parser.data.len() < MAX_BANNER {
+        let n = match timeout_at(deadline.min(Instant::now() + wait), stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => n,
+            Ok(Err(e)) if parser.data.is_empty() && parser.options.is_empty() => return Err(e),
+            _ => break,
+        };
+        let replies = parser.feed(&buf[..n]);
+        if !replies.is_empty() { stream.write_all(&replies).await?; }
+        let tail = String::from_utf8_lossy(&parser.data[parser.data.len().saturating_sub(32)..]).to_lowercase();
+        if PROMPTS.iter().any(|p| tail.trim_end().ends_with(p)) { break; }
+    }
+    if parser.data.is_empty() && parser.options.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no Telnet banner"));
+    }
+    Ok(TelnetInfo { banner: sanitize(&parser.data), options: parser.options })
+}
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    Verb(u8),
+    Sub,
+    SubIac,
+}
+
+/// Splits the stream into banner bytes and IAC commands, across reads.
+#[derive(Default)]
+struct Parser {
+    state: State,
+    data: Vec<u8>,
+    options: Vec<String>,
+}
+
+impl Parser {
+    /// Takes in received bytes and returns the negotiation replies to send.
+    fn feed(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut replies = Vec::new();
+        for &b in input {
+            self.state = match (&self.state, b) {
+                (State::Data, IAC) => State::Iac,
+                // IAC IAC is a literal 255
+                (State::Data, _) | (State::Iac, IAC) => {
+                    if self.data.len() < MAX_BANNER { self.data.push(b); }
+                    State::Data
+                }
+                (State::Iac, DO | DONT | WILL | WONT) => State::Verb(b),
+                (State::Iac, SB) => State::Sub,
+                // NOP, go-ahead, data mark and the like carry no operand
+                (State::Iac, _) => State::Data,
+                (&State::Verb(verb), option) => {
+                    let reply = match verb {
+                        DO => Some(if option == SUPPRESS_GO_AHEAD { WILL } else { WONT }),
+                        WILL => Some(if matches!(option, ECHO | SUPPRESS_GO_AHEAD) { DO } else { DONT }),
+                        // Everything is off already; acknowledging would only loop
+                        _ => None,
+                    };
+                    if let Some(reply) = reply {
+                        replies.extend([IAC, reply, option]);
+                        let name = option_name(option);
+                        if !self.options.contains(&name) { self.options.push(name); }
+                    }
+                    State::Data
+                }
+                (State::Sub, IAC) => State::SubIac,
+                (State::Sub, _) => State::Sub,
+                (State::SubIac, SE) => State::Data,
+                (State::SubIac, _) => State::Sub,
+            };
+        }
+        replies
+    }
+}
+
+fn option_name(option: u8) -> String {
+    match option {
+        0 => "binary",
+        1 => "echo",
+        3 => "suppress-go-ahead",
+        5 => "status",
+        6 => "timing-mark",
+        24 => "terminal-type",
+        31 => "window-size",
+        32 => "terminal-speed",
+        33 => "remote-flow-control",
+        34 => "linemode",
+        35 => "x-display-location",
+        36 => "environment",
+        37 => "authentication",
+        38 => "encryption",
+        39 => "new-environment",
+        _ => return format!("option-{}", option),
+    }
+    .to_string()
+}
+
+/// Drops ANSI escape sequences and every control character, so a banner can
+/// go to a terminal or a log line as is: blank lines go, the rest are joined.
+fn sanitize(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(data);
+    let mut clean = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediates up to a final byte
+                Some('[') => while chars.next().is_some_and(|c| !('\x40'..='\x7e').contains(&c)) {},
+                // OSC and other strings, up to BEL or ST
+                Some(']' | 'P' | '^' | '_') => while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) { break; }
+                },
+                _ => {}
+            },
+            '\n' => clean.push('\n'),
+            c if c.is_control() => {}
+            c => clean.push(c),
+        }
+    }
+    let banner = clean.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(" | ");
+    banner.chars().take(MAX_TEXT).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_negotiation_and_sanitize() {
+        let mut parser = Parser::default();
+        // DO TTYPE, WILL ECHO, WILL SGA, then half of DO NAWS
+        let replies = parser.feed(&[IAC, DO, 24, IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD, IAC, DO]);
+        assert_eq!(replies, [IAC, WONT, 24, IAC, DO, ECHO, IAC, DO, SUPPRESS_GO_AHEAD]);
+        let replies = parser.feed(&[31, b'\x1b', b'[', b'2', b'J', IAC, SB, 24, 1, IAC, SE, b'R', b'1', IAC, IAC, b'\r', b'\n']);
+        assert_eq!(replies, [IAC, WONT, 31]);
+        assert_eq!(parser.options, ["terminal-type", "echo", "suppress-go-ahead", "window-size"]);
+        assert_eq!(parser.data, b"\x1b[2JR1\xff\r\n");
+
+        let banner = b"\x1b[1;32mCisco Router\x1b[0m\r\n\r\n\x1b]0;title\x07User Access Verification\x07\r\n\x00\r\nUsername: ";
+        assert_eq!(sanitize(banner), "Cisco Router | User Access Verification | Username:");
+        assert_eq!(sanitize(b"\xc2\x9b\x08login:\x7f"), "login:");
+    }
+}
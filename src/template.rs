@@ -0,0 +1,78 @@
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    /// Dotted path into the result, e.g. `geo.country` or `http.0.title`
+    Field(Vec<String>),
+}
+
+/// `--template` line format: `{field}` placeholders over the JSON result,
+/// `{{`/`}}` for literal braces. Missing fields render as `-`.
+#[derive(Debug)]
+pub struct Template(Vec<Part>);
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => { chars.next(); literal.push('{'); }
+                '}' if chars.peek() == Some(&'}') => { chars.next(); literal.push('}'); }
+                '{' => {
+                    let mut name = String::new();
+                    let closed = chars.by_ref().any(|c| c == '}' || { name.push(c); false });
+                    if !closed || name.trim().is_empty() || name.contains('{') { bail!("invalid placeholder in template '{}'", s); }
+                    if !literal.is_empty() { parts.push(Part::Literal(std::mem::take(&mut literal))); }
+                    parts.push(Part::Field(name.trim().split('.').map(str::to_string).collect()));
+                }
+                '}' => bail!("unmatched '}}' in template '{}'", s),
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() { parts.push(Part::Literal(literal)); }
+        Ok(Self(parts))
+    }
+
+    pub fn render(&self, fields: &Value) -> String {
+        self.0.iter().map(|part| match part {
+            Part::Literal(s) => s.clone(),
+            Part::Field(path) => {
+                let value = path.iter().try_fold(fields, |v, key| match v {
+                    Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                    _ => v.get(key),
+                });
+                value.map(scalar).unwrap_or_else(|| "-".to_string())
+            }
+        }).collect()
+    }
+}
+
+fn scalar(v: &Value) -> String {
+    match v {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(scalar).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_render() {
+        let t = Template::parse("{ip}:{port} {latency}ms {{{geo.country}}} {http.0.title} {missing}").unwrap();
+        let fields = serde_json::json!({
+            "ip": "1.2.3.4", "port": 443, "latency": 12,
+            "geo": { "country": "FI" }, "http": [{ "title": "Login" }],
+        });
+        assert_eq!(t.render(&fields), "1.2.3.4:443 12ms {FI} Login -");
+        assert!(Template::parse("{ip").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("ip}").is_err());
+    }
+}
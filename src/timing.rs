@@ -0,0 +1,63 @@
+use toml::Table;
+
+/// nmap-style timing templates (-T0 to -T5), from slowest to fastest.
+#[derive(clap::ValueEnum, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Template {
+    Paranoid,
+    Sneaky,
+    Polite,
+    Normal,
+    Aggressive,
+    Insane,
+}
+
+impl Template {
+    /// (rate, workers, timeout ms, retries)
+    fn values(self) -> (u32, usize, u64, u32) {
+        match self {
+            Self::Paranoid => (1, 1, 5000, 3),
+            Self::Sneaky => (5, 4, 3000, 2),
+            Self::Polite => (50, 16, 2000, 1),
+            Self::Normal => (500, 64, 1500, 0),
+            Self::Aggressive => (2000, 256, 800, 1),
+            Self::Insane => (10_000, 1024, 300, 0),
+        }
+    }
+
+    /// Settings layer applied below the config file, so a value set there,
+    /// in the environment or on the command line still wins.
+    pub fn settings(self) -> Table {
+        let (rate, workers, timeout, retries) = self.values();
+        let mut table = Table::new();
+        table.insert("rate".into(), i64::from(rate).into());
+        table.insert("workers".into(), (workers as i64).into());
+        table.insert("timeout".into(), (timeout as i64).into());
+        table.insert("retries".into(), i64::from(retries).into());
+        let jitter = match self {
+            Self::Paranoid => Some("1-5s"),
+            Self::Sneaky => Some("100-1000ms"),
+            Self::Polite => Some("0-400ms"),
+            _ => None,
+        };
+        if let Some(jitter) = jitter { table.insert("jitter".into(), jitter.into()); }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+    #[test]
+    fn test_template_below_explicit_flags() {
+        let matches = crate::Args::command().try_get_matches_from(["pulsenet", "--timing", "polite", "--rate", "7"]).unwrap();
+        let cli = crate::Args::from_arg_matches(&matches).unwrap();
+        let args = crate::config::resolve(&cli, &matches, &[("--timing", Template::Polite.settings())]).unwrap();
+        assert_eq!((args.rate, args.workers, args.timeout, args.retries), (7, 16, 2000, 1));
+        assert_eq!(args.jitter.as_deref(), Some("0-400ms"));
+        // Normal is the built-in defaults
+        let defaults = crate::Args::from_arg_matches(&crate::Args::command().get_matches_from(["pulsenet"])).unwrap();
+        assert_eq!(Template::Normal.values(), (defaults.rate, defaults.workers, defaults.timeout, defaults.retries));
+    }
+}
@@ -0,0 +1,130 @@
+use serde::Serialize;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use x509_parser::prelude::*;
+
+/// The parts of a server certificate worth recording.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CertInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject_cn: Option<String>,
+    pub issuer: String,
+    pub not_after: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub san: Vec<String>,
+    pub self_signed: bool,
+    /// Past `not_after` at scan time
+    pub expired: bool,
+}
+
+/// Scanning inspects certificates instead of trusting them: any chain is accepted,
+/// only the handshake signatures are checked.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(&self, _: &CertificateDer<'_>, _: &[CertificateDer<'_>], _: &ServerName<'_>, _: &[u8], _: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// TLS client handshake over an established stream. Without `sni` the
+/// connection is made by IP and no server name is sent.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin>(stream: S, ip: Ipv4Addr, sni: Option<&str>) -> io::Result<TlsStream<S>> {
+    let name = match sni {
+        Some(host) => ServerName::try_from(host.to_string()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        None => ServerName::IpAddress(std::net::IpAddr::V4(ip).into()),
+    };
+    TlsConnector::from(Arc::new(client_config(&[])?)).connect(name, stream).await
+}
+
+/// The protocol the server picks from `offer` by ALPN, None when it doesn't
+/// negotiate one. A full handshake, so it works for TLS 1.3 too, where the
+/// answer is encrypted.
+pub async fn alpn(stream: TcpStream, offer: &[&str]) -> io::Result<Option<String>> {
+    let name = ServerName::IpAddress(stream.peer_addr()?.ip().into());
+    let tls = TlsConnector::from(Arc::new(client_config(offer)?)).connect(name, stream).await?;
+    Ok(tls.get_ref().1.alpn_protocol().map(|p| String::from_utf8_lossy(p).into_owned()))
+}
+
+/// Accepts any certificate and offers `alpn`.
+pub fn client_config(alpn: &[&str]) -> io::Result<ClientConfig> {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let mut config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+    Ok(config)
+}
+
+pub fn peer_certificate<S>(stream: &TlsStream<S>) -> Option<CertInfo> {
+    let (_, conn) = stream.get_ref();
+    parse_certificate(conn.peer_certificates()?.first()?)
+}
+
+pub fn parse_certificate(der: &[u8]) -> Option<CertInfo> {
+    let (_, cert) = X509Certificate::from_der(der).ok()?;
+    let subject_cn = cert.subject().iter_common_name().next().and_then(|cn| cn.as_str().ok()).map(str::to_string);
+    let san = cert.subject_alternative_name().ok().flatten().map(|ext| {
+        ext.value.general_names.iter().filter_map(|n| match n {
+            GeneralName::DNSName(d) => Some(d.to_string()),
+            GeneralName::IPAddress(b) if b.len() == 4 => Some(Ipv4Addr::new(b[0], b[1], b[2], b[3]).to_string()),
+            _ => None,
+        }).collect()
+    }).unwrap_or_default();
+    let not_after = chrono::DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)?;
+    Some(CertInfo {
+        subject_cn,
+        issuer: cert.issuer().to_string(),
+        not_after: not_after.to_rfc3339(),
+        san,
+        self_signed: cert.subject() == cert.issuer(),
+        expired: not_after < chrono::Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    #[test]
+    fn test_parse_self_signed_certificate() {
+        // openssl req -x509 -subj "/CN=WIN-RDP01" -addext "subjectAltName=DNS:win-rdp01.corp.local,IP:10.0.0.5"
+        let pem = [
+        "MIIBpTCCAUqgAwIBAgIUDouxwZD5pVZlFXMp35fyNPcwniswCgYIKoZIzj0EAwIwFDESMBAGA1UEAwwJV0lOLVJEUDAxMB4XDTI2",
+        "MTAxNTA4MzQ0MloXDTM2MTAxMjA4MzQ0MlowFDESMBAGA1UEAwwJV0lOLVJEUDAxMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE",
+        "AHbUEsVAgu8L0gQgvEKJPuHF5EBtg0cPLMDa3fOa/fMmbah5qLbCziOLbCd5h5oE3QCJRr13D3smoNkW6PeORKN6MHgwHQYDVR0O",
+        "BBYEFLAs3mGHig3g2/FX7B3d2gQgth1DMB8GA1UdIwQYMBaAFLAs3mGHig3g2/FX7B3d2gQgth1DMA8GA1UdEwEB/wQFMAMBAf8w",
+        "JQYDVR0RBB4wHIIUd2luLXJkcDAxLmNvcnAubG9jYWyHBAoAAAUwCgYIKoZIzj0EAwIDSQAwRgIhAIG2J+jQUQU/yAZouPbzdk3Y",
+        "xouMtby80IU06KAipGjQAiEAwgZeZFatMp/dkI7BhgObH6OZVd4iwfU8JWW1VbSHc70=",
+        ].concat();
+        let der = base64::engine::general_purpose::STANDARD.decode(pem).unwrap();
+        let info = parse_certificate(&der).unwrap();
+        assert_eq!(info.subject_cn.as_deref(), Some("WIN-RDP01"));
+        assert_eq!(info.issuer, "CN=WIN-RDP01");
+        assert!(info.not_after.starts_with("2036-10-12T08:34:42"));
+        assert_eq!(info.san, vec!["win-rdp01.corp.local", "10.0.0.5"]);
+        assert!(info.self_signed && !info.expired);
+    }
+}
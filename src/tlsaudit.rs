@@ -0,0 +1,326 @@
+use crate::tls::{self, CertInfo};
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const TLS10: u16 = 0x0301;
+const TLS11: u16 = 0x0302;
+const TLS12: u16 = 0x0303;
+const TLS13: u16 = 0x0304;
+
+/// Suites offered in TLS 1.0-1.2 hellos: modern AEAD/CBC first, then the weak ones.
+const MODERN_SUITES: &[u16] = &[0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8, 0xc009, 0xc013, 0xc00a, 0xc014, 0x009c, 0x009d, 0x002f, 0x0035];
+const WEAK_SUITES: &[u16] = &[0x000a, 0xc012, 0x0005, 0x0004, 0xc011, 0x0009, 0x0003, 0x0008, 0x0001, 0x0002, 0x003b];
+const TLS13_SUITES: &[u16] = &[0x1301, 0x1302, 0x1303];
+/// ALPN protocols asked about: HTTP/2 (which gRPC runs over), gRPC's old
+/// experimental id, HTTP/1.x and SPDY.
+const ALPN: &[&str] = &["h2", "grpc-exp", "http/1.1", "http/1.0", "spdy/3.1"];
+
+/// Protocol and cipher support of one TLS port.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TlsAudit {
+    pub port: u16,
+    pub versions: Vec<&'static str>,
+    /// Suite the server picked for each supported version
+    pub ciphers: BTreeMap<&'static str, String>,
+    /// ALPN protocols the server accepts, in the order it prefers them;
+    /// `h2` marks an HTTP/2 (or gRPC) endpoint
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alpn: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertInfo>,
+    /// Weak configuration items, empty when nothing stood out
+    pub findings: Vec<String>,
+}
+
+struct ServerHello {
+    version: u16,
+    cipher: u16,
+    certificate: Option<Vec<u8>>,
+}
+
+/// Offers each protocol version in its own handshake (one connection each),
+/// then retries the best legacy version with only weak suites. Fails when no
+/// version gets a ServerHello, i.e. the port doesn't speak TLS.
+pub async fn audit<F, Fut>(port: u16, connect: F, wait: Duration) -> io::Result<TlsAudit>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let attempt = |hello: Vec<u8>| {
+        let connect = &connect;
+        async move {
+            let mut stream = timeout(wait, connect()).await.map_err(|_| io::ErrorKind::TimedOut)??;
+            timeout(wait, handshake(&mut stream, &hello)).await.map_err(|_| io::ErrorKind::TimedOut)?
+        }
+    };
+    let all: Vec<u16> = MODERN_SUITES.iter().chain(WEAK_SUITES).copied().collect();
+    let mut result = TlsAudit { port, versions: Vec::new(), ciphers: BTreeMap::new(), alpn: Vec::new(), certificate: None, findings: Vec::new() };
+    let mut best_legacy = None;
+    for version in [TLS10, TLS11, TLS12, TLS13] {
+        let hello = if version == TLS13 { client_hello_13() } else { client_hello(version, &all) };
+        let Ok(Some(sh)) = attempt(hello).await else { continue };
+        // Servers answer a too-new hello with their own highest version; only an exact match counts
+        if sh.version != version { continue; }
+        result.versions.push(version_name(version));
+        result.ciphers.insert(version_name(version), suite_name(sh.cipher));
+        if version != TLS13 { best_legacy = Some(version); }
+        if let Some(der) = sh.certificate { result.certificate = tls::parse_certificate(&der); }
+    }
+    if result.versions.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no TLS handshake"));
+    }
+    // rustls speaks TLS 1.2 and 1.3 only
+    if result.versions.iter().any(|v| *v == "TLS 1.2" || *v == "TLS 1.3") {
+        result.alpn = alpn_preferences(|offer| {
+            let connect = &connect;
+            async move {
+                let stream = timeout(wait, connect()).await.ok()?.ok()?;
+                timeout(wait, tls::alpn(stream, &offer)).await.ok()?.ok()?
+            }
+        }).await;
+    }
+    let weak_accepted = match best_legacy {
+        Some(v) => attempt(client_hello(v, WEAK_SUITES)).await.ok().flatten().filter(|sh| sh.version == v).map(|sh| sh.cipher),
+        None => None,
+    };
+    result.findings = findings(&result, weak_accepted);
+    Ok(result)
+}
+
+/// A server picks one protocol per handshake, so each pick is left out of
+/// the next offer until it picks none (or one that wasn't offered).
+async fn alpn_preferences<F, Fut>(negotiate: F) -> Vec<&'static str>
+where
+    F: Fn(Vec<&'static str>) -> Fut,
+    Fut: Future<Output = Option<String>>,
+{
+    let mut accepted = Vec::new();
+    loop {
+        let offer: Vec<&'static str> = ALPN.iter().copied().filter(|p| !accepted.contains(p)).collect();
+        if offer.is_empty() { break; }
+        let Some(picked) = negotiate(offer.clone()).await else { break };
+        let Some(&picked) = offer.iter().find(|p| **p == picked) else { break };
+        accepted.push(picked);
+    }
+    accepted
+}
+
+fn findings(audit: &TlsAudit, weak_accepted: Option<u16>) -> Vec<String> {
+    let mut out = Vec::new();
+    for old in ["TLS 1.0", "TLS 1.1"] {
+        if audit.versions.contains(&old) { out.push(format!("deprecated protocol {} enabled", old)); }
+    }
+    if !audit.versions.iter().any(|v| *v == "TLS 1.2" || *v == "TLS 1.3") {
+        out.push("neither TLS 1.2 nor TLS 1.3 supported".into());
+    }
+    if let Some(suite) = weak_accepted { out.push(format!("weak cipher accepted: {}", suite_name(suite))); }
+    if let Some(cert) = &audit.certificate {
+        if cert.expired { out.push(format!("certificate expired ({})", cert.not_after)); }
+        if cert.self_signed { out.push("self-signed certificate".into()); }
+    }
+    out
+}
+
+fn version_name(v: u16) -> &'static str {
+    match v {
+        TLS10 => "TLS 1.0",
+        TLS11 => "TLS 1.1",
+        TLS12 => "TLS 1.2",
+        TLS13 => "TLS 1.3",
+        _ => "unknown",
+    }
+}
+
+fn suite_name(id: u16) -> String {
+    let name = match id {
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xc009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc00a => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x000a => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        0xc012 => "TLS_ECDHE_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x0005 => "TLS_RSA_WITH_RC4_128_SHA",
+        0x0004 => "TLS_RSA_WITH_RC4_128_MD5",
+        0xc011 => "TLS_ECDHE_RSA_WITH_RC4_128_SHA",
+        0x0009 => "TLS_RSA_WITH_DES_CBC_SHA",
+        0x0003 => "TLS_RSA_EXPORT_WITH_RC4_40_MD5",
+        0x0008 => "TLS_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x0001 => "TLS_RSA_WITH_NULL_MD5",
+        0x0002 => "TLS_RSA_WITH_NULL_SHA",
+        0x003b => "TLS_RSA_WITH_NULL_SHA256",
+        other => return format!("0x{:04x}", other),
+    };
+    name.to_string()
+}
+
+fn extension(out: &mut Vec<u8>, kind: u16, data: &[u8]) {
+    out.extend_from_slice(&kind.to_be_bytes());
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn u16_list(items: &[u16]) -> Vec<u8> {
+    let mut out = ((items.len() * 2) as u16).to_be_bytes().to_vec();
+    for i in items { out.extend_from_slice(&i.to_be_bytes()); }
+    out
+}
+
+/// Wraps a ClientHello body (version onwards) in handshake and record headers.
+fn wrap_hello(version: u16, suites: &[u16], extensions: &[u8]) -> Vec<u8> {
+    let mut random = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut random);
+    let mut body = version.to_be_bytes().to_vec();
+    body.extend_from_slice(&random);
+    body.push(0); // session id
+    body.extend(u16_list(suites));
+    body.extend_from_slice(&[1, 0]); // null compression
+    body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    body.extend_from_slice(extensions);
+
+    let mut hs = vec![1];
+    hs.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    hs.extend(body);
+    let mut record = vec![22, 0x03, 0x01];
+    record.extend_from_slice(&(hs.len() as u16).to_be_bytes());
+    record.extend(hs);
+    record
+}
+
+fn client_hello(version: u16, suites: &[u16]) -> Vec<u8> {
+    let mut ext = Vec::new();
+    extension(&mut ext, 0x000a, &u16_list(&[0x001d, 0x0017, 0x0018])); // supported groups
+    extension(&mut ext, 0x000b, &[1, 0]); // uncompressed points
+    extension(&mut ext, 0x000d, &u16_list(&[0x0403, 0x0503, 0x0804, 0x0805, 0x0401, 0x0501, 0x0201, 0x0203]));
+    extension(&mut ext, 0xff01, &[0]); // renegotiation info
+    wrap_hello(version, suites, &ext)
+}
+
+fn client_hello_13() -> Vec<u8> {
+    let mut key = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut key);
+    let mut share = vec![0, 36, 0x00, 0x1d, 0, 32];
+    share.extend_from_slice(&key);
+    let mut ext = Vec::new();
+    extension(&mut ext, 0x002b, &[2, 0x03, 0x04]); // supported versions: TLS 1.3 only
+    extension(&mut ext, 0x000a, &u16_list(&[0x001d]));
+    extension(&mut ext, 0x000d, &u16_list(&[0x0403, 0x0503, 0x0804, 0x0805, 0x0401, 0x0501]));
+    extension(&mut ext, 0x0033, &share);
+    wrap_hello(TLS12, TLS13_SUITES, &ext)
+}
+
+/// Sends the hello and reads records until the ServerHello (TLS 1.3) or the
+/// server's certificate / hello-done (older versions). None on an alert.
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, hello: &[u8]) -> io::Result<Option<ServerHello>> {
+    stream.write_all(hello).await?;
+    let mut messages = Vec::new();
+    let mut server_hello: Option<ServerHello> = None;
+    while messages.len() < 64 * 1024 {
+        let mut header = [0u8; 5];
+        if stream.read_exact(&mut header).await.is_err() { break; }
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+        match header[0] {
+            21 => return Ok(server_hello), // alert
+            22 => messages.extend(body),
+            _ => break, // encrypted or unexpected
+        }
+        let mut rest = &messages[..];
+        while rest.len() >= 4 {
+            let n = u32::from_be_bytes([0, rest[1], rest[2], rest[3]]) as usize;
+            let Some(msg) = rest.get(4..4 + n) else { break };
+            match rest[0] {
+                2 => server_hello = parse_server_hello(msg),
+                11 => if let Some(sh) = server_hello.as_mut() { sh.certificate = first_certificate(msg); return Ok(server_hello); },
+                14 => return Ok(server_hello), // ServerHelloDone without a certificate (anonymous suites)
+                _ => {}
+            }
+            if server_hello.as_ref().is_some_and(|sh| sh.version == TLS13) { return Ok(server_hello); }
+            rest = &rest[4 + n..];
+        }
+    }
+    Ok(server_hello)
+}
+
+fn parse_server_hello(msg: &[u8]) -> Option<ServerHello> {
+    let mut version = u16::from_be_bytes([*msg.first()?, *msg.get(1)?]);
+    let sid_len = *msg.get(34)? as usize;
+    let mut pos = 35 + sid_len;
+    let cipher = u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]);
+    pos += 3; // cipher, compression
+    if let Some(ext_len) = msg.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]) as usize) {
+        let mut ext = msg.get(pos + 2..pos + 2 + ext_len)?;
+        while ext.len() >= 4 {
+            let kind = u16::from_be_bytes([ext[0], ext[1]]);
+            let n = u16::from_be_bytes([ext[2], ext[3]]) as usize;
+            let data = ext.get(4..4 + n)?;
+            if kind == 0x002b && n == 2 { version = u16::from_be_bytes([data[0], data[1]]); }
+            ext = &ext[4 + n..];
+        }
+    }
+    Some(ServerHello { version, cipher, certificate: None })
+}
+
+/// Leaf certificate of a (TLS <= 1.2) Certificate message.
+fn first_certificate(msg: &[u8]) -> Option<Vec<u8>> {
+    let n = u32::from_be_bytes([0, *msg.get(3)?, *msg.get(4)?, *msg.get(5)?]) as usize;
+    msg.get(6..6 + n).map(<[u8]>::to_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_hello_framing_and_server_hello() {
+        let hello = client_hello(TLS12, MODERN_SUITES);
+        assert_eq!(&hello[..3], &[22, 3, 1]);
+        assert_eq!(u16::from_be_bytes([hello[3], hello[4]]) as usize, hello.len() - 5);
+        assert_eq!(u32::from_be_bytes([0, hello[6], hello[7], hello[8]]) as usize, hello.len() - 9);
+
+        // ServerHello: TLS 1.2 legacy version, empty session id, TLS_AES_128_GCM_SHA256, supported_versions = 1.3
+        let mut sh = vec![0x03, 0x03];
+        sh.extend_from_slice(&[0; 32]);
+        sh.extend_from_slice(&[0, 0x13, 0x01, 0, 0, 6, 0, 0x2b, 0, 2, 0x03, 0x04]);
+        let parsed = parse_server_hello(&sh).unwrap();
+        assert_eq!((parsed.version, suite_name(parsed.cipher).as_str()), (TLS13, "TLS_AES_128_GCM_SHA256"));
+    }
+    #[test]
+    fn test_findings() {
+        let audit = TlsAudit {
+            port: 443, versions: vec!["TLS 1.0", "TLS 1.2"], ciphers: BTreeMap::new(), alpn: vec![], certificate: None, findings: vec![],
+        };
+        assert_eq!(findings(&audit, Some(0x0005)), vec![
+            "deprecated protocol TLS 1.0 enabled".to_string(),
+            "weak cipher accepted: TLS_RSA_WITH_RC4_128_SHA".to_string(),
+        ]);
+    }
+    #[test]
+    fn test_alpn_preferences() {
+        // Prefers http/1.1 over h2 and knows nothing else
+        let server = |offer: Vec<&'static str>| async move {
+            ["http/1.1", "h2"].into_iter().find(|p| offer.contains(p)).map(str::to_string)
+        };
+        assert_eq!(futures::executor::block_on(alpn_preferences(server)), vec!["http/1.1", "h2"]);
+        assert!(futures::executor::block_on(alpn_preferences(|_| async { None })).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::rawsock;
+use serde::Serialize;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const BASE_PORT: u16 = 33434;
+const IPPROTO_UDP: u8 = 17;
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMP_TIME_EXCEEDED: u8 = 11;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub ttl: u8,
+    /// None when the hop didn't answer within the wait window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<u128>,
+}
+
+/// UDP traceroute (classic 33434+ports) towards `dst`. All TTLs are sent at
+/// once and ICMP answers collected for `wait`, so a trace costs one timeout
+/// instead of one per hop. Requires raw socket privileges to read ICMP.
+pub async fn trace(dst: Ipv4Addr, max_hops: u8, wait: Duration) -> io::Result<Vec<Hop>> {
+    tokio::task::spawn_blocking(move || trace_blocking(dst, max_hops, wait))
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn trace_blocking(dst: Ipv4Addr, max_hops: u8, wait: Duration) -> io::Result<Vec<Hop>> {
+    let icmp = rawsock::open_raw(Protocol::ICMPV4)?;
+    let udp = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    udp.bind(&SockAddr::from(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)))?;
+    let sport = udp.local_addr()?.as_socket().map(|a| a.port()).unwrap_or(0);
+
+    let mut hops: Vec<Hop> = (1..=max_hops).map(|ttl| Hop { ttl, ip: None, rtt_ms: None }).collect();
+    let mut sent_at = Vec::with_capacity(max_hops as usize);
+    for ttl in 1..=max_hops {
+        udp.set_ttl_v4(ttl as u32)?;
+        let addr = SockAddr::from(SocketAddr::new(dst.into(), BASE_PORT + ttl as u16));
+        sent_at.push(Instant::now());
+        // Errors for individual TTLs (e.g. ICMP already queued on the socket) are not fatal
+        let _ = udp.send_to(b"PulseNet traceroute", &addr);
+    }
+
+    let deadline = Instant::now() + wait;
+    let mut reached: Option<u8> = None;
+    let mut buf = [0u8; 1500];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+        icmp.set_read_timeout(Some(left))?;
+        let n = match (&icmp).read(&mut buf) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        let Some((from, kind, ttl)) = match_reply(&buf[..n], dst, sport) else { continue };
+        if ttl == 0 || ttl > max_hops { continue; }
+        let hop = &mut hops[ttl as usize - 1];
+        if hop.ip.is_none() {
+            hop.ip = Some(from.to_string());
+            hop.rtt_ms = Some(sent_at[ttl as usize - 1].elapsed().as_millis());
+        }
+        if kind == ICMP_DEST_UNREACHABLE || from == dst {
+            reached = Some(reached.map_or(ttl, |r| r.min(ttl)));
+        }
+        // Stop early once the destination answered and every hop before it is in
+        if let Some(r) = reached && hops[..r as usize].iter().all(|h| h.ip.is_some()) { break; }
+    }
+    if let Some(r) = reached { hops.truncate(r as usize); }
+    else {
+        // Drop the silent tail past the last responding hop
+        let last = hops.iter().rposition(|h| h.ip.is_some()).map_or(0, |i| i + 1);
+        hops.truncate(last);
+    }
+    Ok(hops)
+}
+
+/// Matches an ICMP time-exceeded / unreachable packet to one of our probes by
+/// the quoted UDP header. Returns (responder, icmp type, probe ttl).
+fn match_reply(packet: &[u8], dst: Ipv4Addr, sport: u16) -> Option<(Ipv4Addr, u8, u8)> {
+    let outer = rawsock::parse_ipv4(packet)?;
+    let icmp = outer.payload;
+    if icmp.len() < 8 || !matches!(icmp[0], ICMP_TIME_EXCEEDED | ICMP_DEST_UNREACHABLE) { return None; }
+    let inner = rawsock::parse_ipv4(&icmp[8..])?;
+    if inner.protocol != IPPROTO_UDP || inner.dst != dst || inner.payload.len() < 4 { return None; }
+    let udp = inner.payload;
+    if u16::from_be_bytes([udp[0], udp[1]]) != sport { return None; }
+    let dport = u16::from_be_bytes([udp[2], udp[3]]);
+    let ttl = dport.checked_sub(BASE_PORT)?;
+    Some((outer.src, icmp[0], u8::try_from(ttl).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4(src: [u8; 4], dst: [u8; 4], proto: u8, payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, proto, 0, 0];
+        p.extend_from_slice(&src);
+        p.extend_from_slice(&dst);
+        p.extend_from_slice(payload);
+        let len = p.len() as u16;
+        p[2..4].copy_from_slice(&len.to_be_bytes());
+        p
+    }
+
+    #[test]
+    fn test_match_time_exceeded() {
+        let target = Ipv4Addr::new(203, 0, 113, 9);
+        let quoted_udp = [0x9c, 0x40, 0x82, 0x9d, 0, 8, 0, 0]; // 40000 -> 33437 (ttl 3)
+        let quoted = ipv4([10, 0, 0, 2], target.octets(), IPPROTO_UDP, &quoted_udp);
+        let mut icmp = vec![ICMP_TIME_EXCEEDED, 0, 0, 0, 0, 0, 0, 0];
+        icmp.extend_from_slice(&quoted);
+        let pkt = ipv4([192, 0, 2, 1], [10, 0, 0, 2], 1, &icmp);
+        assert_eq!(match_reply(&pkt, target, 40000), Some((Ipv4Addr::new(192, 0, 2, 1), ICMP_TIME_EXCEEDED, 3)));
+        assert_eq!(match_reply(&pkt, target, 40001), None);
+    }
+}
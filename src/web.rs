@@ -0,0 +1,209 @@
+use crate::{cdn, http, tech, tls};
+use base64::Engine;
+use rand::RngCore;
+use serde::Serialize;
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Ports spoken to over TLS; everything else gets plain HTTP.
+const TLS_PORTS: &[u16] = &[443, 4443, 8443, 9443, 10443];
+const MAX_BODY: usize = 256 * 1024;
+/// Appended to Sec-WebSocket-Key before hashing (RFC 6455, 1.3)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// What an HTTP(S) service on one port answered.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct WebInfo {
+    pub port: u16,
+    pub scheme: &'static str,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Shodan-compatible `http.favicon.hash` of /favicon.ico
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon_hash: Option<i32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub technologies: Vec<String>,
+    /// --websocket paths that completed the Upgrade handshake
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub websocket: Vec<String>,
+    /// Edge named by the response headers; recorded per host as `cdn`
+    #[serde(skip)]
+    pub cdn: Option<&'static str>,
+}
+
+/// Fetches `/` and `/favicon.ico`, one connection each, and matches the page
+/// against the technology rules. Each of `websocket` gets an Upgrade request
+/// on a connection of its own.
+pub async fn probe<F, Fut>(ip: Ipv4Addr, port: u16, connect: F, rules: &tech::TechRules, websocket: &[String], wait: Duration) -> io::Result<WebInfo>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = io::Result<TcpStream>>,
+{
+    let https = TLS_PORTS.contains(&port);
+    let host = if matches!(port, 80 | 443) { ip.to_string() } else { format!("{}:{}", ip, port) };
+    let send = |path: &str, headers: Vec<(&'static str, String)>| {
+        let (connect, host, path) = (&connect, &host, path.to_string());
+        async move {
+            let stream = timeout(wait, connect()).await.map_err(|_| io::ErrorKind::TimedOut)??;
+            let req = http::Request { headers, ..http::Request::get(host, &path) };
+            let fetch = async {
+                if https { http::send(tls::connect(stream, ip, None).await?, &req, MAX_BODY).await } else { http::send(stream, &req, MAX_BODY).await }
+            };
+            timeout(wait, fetch).await.map_err(|_| io::ErrorKind::TimedOut)?
+        }
+    };
+
+    let get = |path| send(path, Vec::new());
+
+    let page = get("/").await?;
+    let favicon = get("/favicon.ico").await.ok()
+        .filter(|r| r.status == 200 && !r.body.is_empty() && !r.body.starts_with(b"<"));
+    let mut upgraded = Vec::new();
+    for path in websocket {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let key = base64::engine::general_purpose::STANDARD.encode(nonce);
+        let headers = vec![
+            ("Connection", "Upgrade".into()), ("Upgrade", "websocket".into()),
+            ("Sec-WebSocket-Key", key.clone()), ("Sec-WebSocket-Version", "13".into()),
+        ];
+        if send(path, headers).await.is_ok_and(|resp| websocket_accepted(&resp, &key)) { upgraded.push(path.clone()); }
+    }
+    Ok(WebInfo {
+        port,
+        scheme: if https { "https" } else { "http" },
+        status: page.status,
+        server: page.header("server").map(str::to_string),
+        title: title(&String::from_utf8_lossy(&page.body)),
+        favicon_hash: favicon.map(|r| favicon_hash(&r.body)),
+        technologies: rules.detect(&page),
+        websocket: upgraded,
+        cdn: cdn::by_headers(&page),
+    })
+}
+
+/// A 101 that names websocket and proves it read our key; a proxy or
+/// framework answering 101 to anything doesn't get the accept value right.
+fn websocket_accepted(resp: &http::Response, key: &str) -> bool {
+    let expected = base64::engine::general_purpose::STANDARD.encode(sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+    resp.status == 101
+        && resp.header("upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket"))
+        && resp.header("sec-websocket-accept") == Some(expected.as_str())
+}
+
+fn title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let open = start + lower[start..].find('>')? + 1;
+    let close = open + lower[open..].find("</title")?;
+    let text = html[open..close].split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then(|| text.chars().take(200).collect())
+}
+
+/// MurmurHash3 (x86, 32-bit, seed 0) of the favicon's base64 encoding with a
+/// newline every 76 characters, matching Python's `mmh3.hash(base64.encodebytes(data))`.
+pub fn favicon_hash(data: &[u8]) -> i32 {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let mut wrapped = Vec::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.extend_from_slice(line);
+        wrapped.push(b'\n');
+    }
+    murmur3_32(&wrapped, 0) as i32
+}
+
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let mut h = seed;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, &b| (k << 8) | b as u32);
+        h ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
+
+/// SHA-1, only for the WebSocket accept value.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 { msg.push(0); }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in msg.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() { w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]); }
+        for i in 16..80 { w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1); }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*wi);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (state, v) in h.iter_mut().zip([a, b, c, d, e]) { *state = state.wrapping_add(v); }
+    }
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_exact_mut(4).zip(h) { chunk.copy_from_slice(&v.to_be_bytes()); }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_murmur3_and_favicon_hash() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"hello", 0) as i32, 613153351);
+        assert_eq!(murmur3_32(b"The quick brown fox jumps over the lazy dog", 0), 0x2e4ff723);
+        // 60 bytes encode to 80 base64 characters, wrapped after 76
+        let data = [0x42u8; 60];
+        let mut expected = base64::engine::general_purpose::STANDARD.encode(data).into_bytes();
+        expected.insert(76, b'\n');
+        expected.push(b'\n');
+        assert_eq!(favicon_hash(&data), murmur3_32(&expected, 0) as i32);
+    }
+    #[test]
+    fn test_title() {
+        assert_eq!(title("<html><TITLE>\n  Router   Login </TITLE>").as_deref(), Some("Router Login"));
+        assert_eq!(title("<title></title>"), None);
+    }
+    #[test]
+    fn test_websocket_accept() {
+        // RFC 6455, 1.3
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let mut resp = http::Response {
+            status: 101,
+            headers: vec![("Upgrade".into(), "websocket".into()), ("Sec-WebSocket-Accept".into(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".into())],
+            body: Vec::new(),
+        };
+        assert!(websocket_accepted(&resp, key));
+        assert!(!websocket_accepted(&resp, "AAAAAAAAAAAAAAAAAAAAAA=="));
+        resp.status = 200;
+        assert!(!websocket_accepted(&resp, key));
+        assert_eq!(sha1(b"abc")[..4], [0xa9, 0x99, 0x3e, 0x36]);
+    }
+}
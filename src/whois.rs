@@ -0,0 +1,139 @@
+use crate::resolver::Resolver;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const IANA_WHOIS: &str = "whois.iana.org";
+const QUERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Who holds the netblock a hit is in, from the registry's WHOIS.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct WhoisInfo {
+    /// First and last address of the netblock
+    pub range: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+    /// Registry server that answered
+    pub server: String,
+}
+
+/// WHOIS lookups with the answers cached per netblock, so hits in the same
+/// network cost one query. The registry for each /8 comes from IANA.
+pub struct Whois {
+    blocks: Mutex<Vec<(u32, u32, Arc<WhoisInfo>)>>,
+    registries: Mutex<HashMap<u8, String>>,
+    resolver: Arc<Resolver>,
+}
+
+impl Whois {
+    pub fn new(resolver: Arc<Resolver>) -> Self {
+        Self { blocks: Mutex::default(), registries: Mutex::default(), resolver }
+    }
+
+    pub async fn lookup(&self, ip: Ipv4Addr) -> Option<WhoisInfo> {
+        let n = u32::from(ip);
+        let cached = self.blocks.lock().unwrap().iter().find(|(lo, hi, _)| (*lo..=*hi).contains(&n)).map(|b| b.2.clone());
+        if let Some(info) = cached { return Some((*info).clone()); }
+        let server = self.registry(ip).await?;
+        // ARIN wants `n +` for the network record; RIPE-style servers skip contacts with -r
+        let q = match server.as_str() {
+            "whois.arin.net" => format!("n + {}", ip),
+            "whois.lacnic.net" => ip.to_string(),
+            _ => format!("-r {}", ip),
+        };
+        let body = query(&self.resolver, &server, &q).await.ok()?;
+        let (lo, hi, mut info) = parse(&body, ip)?;
+        info.server = server;
+        self.blocks.lock().unwrap().push((lo, hi, Arc::new(info.clone())));
+        Some(info)
+    }
+
+    async fn registry(&self, ip: Ipv4Addr) -> Option<String> {
+        let slash8 = ip.octets()[0];
+        if let Some(server) = self.registries.lock().unwrap().get(&slash8) { return Some(server.clone()); }
+        let body = query(&self.resolver, IANA_WHOIS, &ip.to_string()).await.ok()?;
+        let server = field(&body, &["refer", "whois"])?;
+        self.registries.lock().unwrap().insert(slash8, server.clone());
+        Some(server)
+    }
+}
+
+async fn query(resolver: &Resolver, server: &str, q: &str) -> io::Result<String> {
+    let exchange = async {
+        let mut stream = resolver.connect(server, 43).await?;
+        stream.write_all(format!("{}\r\n", q).as_bytes()).await?;
+        let mut body = Vec::new();
+        stream.read_to_end(&mut body).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    };
+    tokio::time::timeout(QUERY_TIMEOUT, exchange).await
+        .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "WHOIS query timed out")))
+}
+
+/// First non-empty value of any of `keys` (case-insensitive).
+fn field(body: &str, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| body.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim().eq_ignore_ascii_case(key) && !v.trim().is_empty()).then(|| v.trim().to_string())
+    }))
+}
+
+/// `a.b.c.d - e.f.g.h`, or CIDR including LACNIC's short form `200.160/20`.
+fn parse_range(s: &str) -> Option<(u32, u32)> {
+    if let Some((lo, hi)) = s.split_once('-') {
+        return Some((u32::from(lo.trim().parse::<Ipv4Addr>().ok()?), u32::from(hi.trim().parse::<Ipv4Addr>().ok()?)));
+    }
+    let (addr, len) = s.trim().split_once('/')?;
+    let mut octets: Vec<&str> = addr.split('.').collect();
+    octets.resize(4, "0");
+    let net: ipnet::Ipv4Net = format!("{}/{}", octets.join("."), len).parse().ok()?;
+    Some((u32::from(net.network()), u32::from(net.broadcast())))
+}
+
+/// The most specific netblock in the answer that contains `ip`. Each
+/// `inetnum`/`NetRange` line starts a new record.
+fn parse(body: &str, ip: Ipv4Addr) -> Option<(u32, u32, WhoisInfo)> {
+    let mut records: Vec<(u32, u32, String)> = Vec::new();
+    for line in body.lines() {
+        let key = line.split_once(':').map(|(k, _)| k.trim().to_ascii_lowercase());
+        if let Some("inetnum" | "netrange") = key.as_deref() {
+            let value = line.split_once(':').map(|(_, v)| v).unwrap_or_default();
+            if let Some((lo, hi)) = parse_range(value) { records.push((lo, hi, String::new())); }
+        }
+        if let Some(record) = records.last_mut() { record.2.push_str(line); record.2.push('\n'); }
+    }
+    let n = u32::from(ip);
+    let (lo, hi, text) = records.into_iter().filter(|(lo, hi, _)| (*lo..=*hi).contains(&n)).min_by_key(|(lo, hi, _)| hi - lo)?;
+    let info = WhoisInfo {
+        range: format!("{} - {}", Ipv4Addr::from(lo), Ipv4Addr::from(hi)),
+        netname: field(&text, &["netname"]),
+        org: field(&text, &["orgname", "org-name", "owner", "organization", "descr"]).or_else(|| field(body, &["orgname", "org-name"])),
+        server: String::new(),
+    };
+    Some((lo, hi, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_parse_registry_answers() {
+        let ripe = "% Information related to '185.1.0.0 - 185.1.255.255'\n\ninetnum:        185.1.0.0 - 185.1.255.255\nnetname:        EU-BIG\n\ninetnum:        185.1.2.0 - 185.1.2.255\nnetname:        EXAMPLE-NET\ndescr:          Example Hosting Oy\nsource:         RIPE\n";
+        let (lo, hi, info) = parse(ripe, Ipv4Addr::new(185, 1, 2, 77)).unwrap();
+        assert_eq!((Ipv4Addr::from(lo), Ipv4Addr::from(hi)), (Ipv4Addr::new(185, 1, 2, 0), Ipv4Addr::new(185, 1, 2, 255)));
+        assert_eq!((info.netname.as_deref(), info.org.as_deref()), (Some("EXAMPLE-NET"), Some("Example Hosting Oy")));
+        let arin = "NetRange:       8.8.8.0 - 8.8.8.255\nCIDR:           8.8.8.0/24\nNetName:        GOGL\nOrganization:   Google LLC (GOGL)\n\nOrgName:        Google LLC\n";
+        let (_, _, info) = parse(arin, Ipv4Addr::new(8, 8, 8, 8)).unwrap();
+        assert_eq!((info.netname.as_deref(), info.org.as_deref()), (Some("GOGL"), Some("Google LLC")));
+        let lacnic = "inetnum:     200.160/20\nowner:       Example Telecom\n";
+        assert_eq!(parse(lacnic, Ipv4Addr::new(200, 160, 3, 1)).unwrap().2.range, "200.160.0.0 - 200.160.15.255");
+        assert!(parse(lacnic, Ipv4Addr::new(200, 161, 0, 1)).is_none());
+        assert_eq!(field("refer:        whois.ripe.net\n", &["refer"]).as_deref(), Some("whois.ripe.net"));
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Instant};
+
+const QUEUE: usize = 4096;
+const BUFFER: usize = 64 * 1024;
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/// Line-oriented output owned by its own task. The scan loop only queues
+/// lines; the task batches them through a buffer and flushes at least once a
+/// second, so `tail -f` keeps up without a write call per result. Each line
+/// (or binary record) reaches the buffer whole, so concurrent appenders never
+/// see torn lines.
+pub struct LineWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl LineWriter {
+    /// Appends to `path`. A file that doesn't exist yet is written as
+    /// `<path>.partial` and renamed into place by `finish`, so nothing picks
+    /// up a half-written file. `header` starts a new file.
+    pub fn append(path: &str, header: Option<&str>) -> Result<Self> {
+        let fresh = std::fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+        let partial = format!("{}.partial", path);
+        let open = if fresh { &partial } else { path };
+        let mut file = OpenOptions::new().create(true).append(true).open(open).with_context(|| format!("failed to open '{}'", path))?;
+        if fresh {
+            // A stale .partial from an interrupted run is replaced
+            file.set_len(0)?;
+            if let Some(header) = header { writeln!(file, "{}", header)?; }
+        }
+        Ok(Self::start(Box::new(file), fresh.then(|| (PathBuf::from(partial), PathBuf::from(path)))))
+    }
+
+    /// Runs `out` on a writer task; `rename` is applied once everything is flushed.
+    pub fn start(out: Box<dyn Write + Send>, rename: Option<(PathBuf, PathBuf)>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(QUEUE);
+        let handle = tokio::spawn(async move {
+            let mut out = BufWriter::with_capacity(BUFFER, out);
+            let mut deadline = Instant::now() + FLUSH_EVERY;
+            loop {
+                match timeout(deadline.saturating_duration_since(Instant::now()), rx.recv()).await {
+                    Ok(Some(record)) => {
+                        out.write_all(&record)?;
+                        continue;
+                    }
+                    Ok(None) => break,
+                    Err(_) => {}
+                }
+                out.flush()?;
+                deadline = Instant::now() + FLUSH_EVERY;
+            }
+            out.flush()?;
+            drop(out);
+            if let Some((from, to)) = rename { std::fs::rename(from, to)?; }
+            Ok(())
+        });
+        Self { tx, handle }
+    }
+
+    /// Queues one line (without its newline), waiting while the queue is full.
+    pub async fn line(&self, line: String) -> io::Result<()> {
+        let mut record = line.into_bytes();
+        record.push(b'\n');
+        self.record(record).await
+    }
+
+    /// Queues bytes written as they are, e.g. one MessagePack map. Fails once
+    /// the writer task has stopped on a write error; `finish` reports that error.
+    pub async fn record(&self, bytes: Vec<u8>) -> io::Result<()> {
+        self.tx.send(bytes).await.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "output writer stopped"))
+    }
+
+    /// Writes out what's queued, then moves a new file into place.
+    pub async fn finish(self) -> io::Result<()> {
+        drop(self.tx);
+        self.handle.await.map_err(io::Error::other)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_new_file_appears_on_finish() {
+        let dir = std::env::temp_dir().join(format!("pulsenet-writer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("found.csv").to_string_lossy().into_owned();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+        rt.block_on(async {
+            let out = LineWriter::append(&path, Some("ip,port")).unwrap();
+            out.line("10.0.0.1,80".into()).await.unwrap();
+            assert!(!std::path::Path::new(&path).exists());
+            out.finish().await.unwrap();
+            // An existing file is appended to in place, without a second header
+            let out = LineWriter::append(&path, Some("ip,port")).unwrap();
+            out.line("10.0.0.2,22".into()).await.unwrap();
+            out.finish().await.unwrap();
+        });
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ip,port\n10.0.0.1,80\n10.0.0.2,22\n");
+        assert!(!std::path::Path::new(&format!("{}.partial", path)).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}